@@ -1,35 +1,57 @@
 const loginSection = document.getElementById("login-section");
 const twoFASection = document.getElementById("2fa-section");
 const signupSection = document.getElementById("signup-section");
+const forgotSection = document.getElementById("forgot-section");
+const resetSection = document.getElementById("reset-section");
+
+function showSection(section) {
+    for (const x of [loginSection, twoFASection, signupSection, forgotSection, resetSection]) {
+        x.style.display = x === section ? "block" : "none";
+    }
+}
 
 const signupLink = document.getElementById("signup-link");
 const twoFALoginLink = document.getElementById("2fa-login-link");
 const signupLoginLink = document.getElementById("signup-login-link");
+const forgotLink = document.getElementById("forgot-link");
+const forgotLoginLink = document.getElementById("forgot-login-link");
 
 signupLink.addEventListener("click", (e) => {
     e.preventDefault();
 
-    loginSection.style.display = "none";
-    twoFASection.style.display = "none";
-    signupSection.style.display = "block";
+    showSection(signupSection);
 });
 
 twoFALoginLink.addEventListener("click", (e) => {
     e.preventDefault();
 
-    loginSection.style.display = "block";
-    twoFASection.style.display = "none";
-    signupSection.style.display = "none";
+    showSection(loginSection);
 });
 
 signupLoginLink.addEventListener("click", (e) => {
     e.preventDefault();
 
-    loginSection.style.display = "block";
-    twoFASection.style.display = "none";
-    signupSection.style.display = "none";
+    showSection(loginSection);
+});
+
+forgotLink.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    showSection(forgotSection);
+});
+
+forgotLoginLink.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    showSection(loginSection);
 });
 
+// Links in password reset emails open this page with the token in the query
+const resetToken = new URLSearchParams(window.location.search).get("reset_token");
+if (resetToken) {
+    showSection(resetSection);
+}
+
 // -----------------------------------------------------
 
 const loginForm = document.getElementById("login-form");
@@ -58,9 +80,7 @@ loginButton.addEventListener("click", (e) => {
             loginForm.email.value = "";
             loginForm.password.value = "";
 
-            loginSection.style.display = "none";
-            twoFASection.style.display = "block";
-            signupSection.style.display = "none";
+            showSection(twoFASection);
             loginErrAlter.style.display = "none";
         } else if (response.status === 200) {
             loginForm.email.value = "";
@@ -104,10 +124,8 @@ signupButton.addEventListener("click", (e) => {
             signupForm.password.value = "";
             signupForm.twoFA.checked = false;
             signupErrAlter.style.display = "none";
-            alert("You have successfully created a user.");
-            loginSection.style.display = "block";
-            twoFASection.style.display = "none";
-            signupSection.style.display = "none";
+            alert("You have successfully created a user. Check your email for the link that verifies your address.");
+            showSection(loginSection);
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -146,9 +164,7 @@ TwoFAButton.addEventListener("click", (e) => {
             TwoFAForm.login_attempt_id.value = "";
             TwoFAErrAlter.style.display = "none";
             alert("You have successfully logged in.");
-            loginSection.style.display = "block";
-            twoFASection.style.display = "none";
-            signupSection.style.display = "none";
+            showSection(loginSection);
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -161,4 +177,75 @@ TwoFAButton.addEventListener("click", (e) => {
             });
         }
     });
-});
\ No newline at end of file
+});
+
+const forgotForm = document.getElementById("forgot-form");
+const forgotButton = document.getElementById("forgot-form-submit");
+const forgotErrAlter = document.getElementById("forgot-err-alert");
+
+forgotButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const email = forgotForm.email.value;
+
+    fetch('/password-reset/request', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ email }),
+    }).then(response => {
+        if (response.ok) {
+            forgotForm.email.value = "";
+            forgotErrAlter.style.display = "none";
+            alert("If an account uses that address, a link to reset its password is on its way.");
+            showSection(loginSection);
+        } else {
+            response.json().then(data => {
+                let error_msg = data.error;
+                if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
+                    forgotErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
+                    forgotErrAlter.style.display = "block";
+                } else {
+                    forgotErrAlter.style.display = "none";
+                }
+            });
+        }
+    });
+});
+
+const resetForm = document.getElementById("reset-form");
+const resetButton = document.getElementById("reset-form-submit");
+const resetErrAlter = document.getElementById("reset-err-alert");
+
+resetButton.addEventListener("click", (e) => {
+    e.preventDefault();
+
+    const newPassword = resetForm.password.value;
+
+    fetch('/password-reset/confirm', {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+        },
+        body: JSON.stringify({ token: resetToken, newPassword }),
+    }).then(response => {
+        if (response.ok) {
+            resetForm.password.value = "";
+            resetErrAlter.style.display = "none";
+            window.history.replaceState(null, "", window.location.pathname);
+            alert("Your password has been changed. Log in with the new one.");
+            showSection(loginSection);
+        } else {
+            response.json().then(data => {
+                let error_msg = data.error;
+                if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
+                    resetErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
+                    resetErrAlter.style.display = "block";
+                } else {
+                    resetErrAlter.style.display = "none";
+                }
+            });
+        }
+    });
+});
@@ -0,0 +1,6 @@
+-- Kept to the subset of SQL shared by SQLite and Postgres.
+CREATE TABLE IF NOT EXISTS users (
+    email TEXT PRIMARY KEY NOT NULL,
+    password_hash TEXT NOT NULL,
+    requires_2fa BOOLEAN NOT NULL
+);
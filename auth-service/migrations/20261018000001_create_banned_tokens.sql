@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS banned_tokens (
+    token TEXT PRIMARY KEY NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS banned_tokens_expires_at_idx ON banned_tokens (expires_at);
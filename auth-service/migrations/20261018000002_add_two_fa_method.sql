@@ -0,0 +1,4 @@
+-- `requires_2fa` only knew about emailed codes.
+ALTER TABLE users ADD COLUMN two_fa_method TEXT NOT NULL DEFAULT 'none';
+UPDATE users SET two_fa_method = 'email' WHERE requires_2fa;
+ALTER TABLE users DROP COLUMN requires_2fa;
@@ -0,0 +1,8 @@
+-- `secret` is the confirmed secret, `pending_secret` one waiting for its first
+-- code. Secrets are base32 encoded.
+CREATE TABLE IF NOT EXISTS totp_secrets (
+    email TEXT PRIMARY KEY NOT NULL,
+    secret TEXT,
+    pending_secret TEXT,
+    last_used_step BIGINT
+);
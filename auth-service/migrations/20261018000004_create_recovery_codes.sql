@@ -0,0 +1,6 @@
+-- One row per unused recovery code. Only the SHA-256 of each code is kept.
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    email TEXT NOT NULL,
+    code_hash TEXT NOT NULL,
+    PRIMARY KEY (email, code_hash)
+);
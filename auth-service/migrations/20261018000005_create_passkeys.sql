@@ -0,0 +1,10 @@
+-- WebAuthn credentials. `credential_id` and `public_key` (a SEC1 P-256
+-- point) are base64url encoded.
+CREATE TABLE IF NOT EXISTS passkeys (
+    credential_id TEXT PRIMARY KEY NOT NULL,
+    email TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    sign_count BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS passkeys_email ON passkeys (email);
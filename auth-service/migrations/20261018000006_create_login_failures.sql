@@ -0,0 +1,7 @@
+-- Failed login counters for throttling, keyed by "email:<address>" or
+-- "ip:<address>". `last_failure_at` is a unix timestamp.
+CREATE TABLE IF NOT EXISTS login_failures (
+    throttle_key TEXT PRIMARY KEY NOT NULL,
+    failures BIGINT NOT NULL,
+    last_failure_at BIGINT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Unix timestamp of when the user followed the link emailed at signup, NULL
+-- until then. Accounts from before verification existed count as verified.
+ALTER TABLE users ADD COLUMN email_verified_at BIGINT;
+UPDATE users SET email_verified_at = 0;
@@ -0,0 +1,9 @@
+-- The newest link emailed to each user for each purpose. `token_id` is the
+-- `jti` of the signed token in the link, `sent_at` a unix timestamp.
+CREATE TABLE IF NOT EXISTS email_tokens (
+    email TEXT NOT NULL,
+    purpose TEXT NOT NULL,
+    token_id TEXT NOT NULL,
+    sent_at BIGINT NOT NULL,
+    PRIMARY KEY (email, purpose)
+);
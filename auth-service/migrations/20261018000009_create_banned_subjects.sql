@@ -0,0 +1,8 @@
+-- Every token for `subject` issued before `issued_before` is rejected, e.g.
+-- after a password reset. Both columns are unix timestamps; the row can go
+-- once `expires_at` has passed, as the banned tokens have all expired by then.
+CREATE TABLE IF NOT EXISTS banned_subjects (
+    subject TEXT PRIMARY KEY NOT NULL,
+    issued_before BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
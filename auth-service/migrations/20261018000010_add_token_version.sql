@@ -0,0 +1,6 @@
+-- Bumped to invalidate every token issued to the user so far. Tokens carry
+-- the version they were issued with.
+ALTER TABLE users ADD COLUMN token_version BIGINT NOT NULL DEFAULT 0;
+
+-- Token versions replace per-subject bans
+DROP TABLE IF EXISTS banned_subjects;
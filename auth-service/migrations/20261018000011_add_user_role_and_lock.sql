@@ -0,0 +1,4 @@
+-- What the user may do, see `Role`
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
+-- Unix timestamp of when an admin locked the account, NULL while unlocked
+ALTER TABLE users ADD COLUMN locked_at BIGINT;
@@ -0,0 +1,9 @@
+-- Unix timestamp a lock lifts at, see `AccountStatus::LockedUntil`
+ALTER TABLE users ADD COLUMN locked_until BIGINT;
+-- Why the account was disabled, NULL unless it is. Takes precedence over
+-- `locked_until`.
+ALTER TABLE users ADD COLUMN disabled_reason TEXT;
+
+-- Locks used to last until lifted, which is what disabling does now
+UPDATE users SET disabled_reason = 'Locked by an admin' WHERE locked_at IS NOT NULL;
+ALTER TABLE users DROP COLUMN locked_at;
@@ -0,0 +1,13 @@
+-- Refresh tokens by SHA-256 hash, grouped in one family per session.
+-- Rotated tokens are kept until they expire so their reuse can be detected.
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+    token_hash TEXT PRIMARY KEY NOT NULL,
+    email TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    rotated_at BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_email_idx ON refresh_tokens (email);
+CREATE INDEX IF NOT EXISTS refresh_tokens_session_id_idx ON refresh_tokens (session_id);
+CREATE INDEX IF NOT EXISTS refresh_tokens_expires_at_idx ON refresh_tokens (expires_at);
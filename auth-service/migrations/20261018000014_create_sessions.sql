@@ -0,0 +1,13 @@
+-- Login sessions, one per device. `ip` is the address the session was started
+-- from in text form. Times are unix timestamps.
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY NOT NULL,
+    email TEXT NOT NULL,
+    user_agent TEXT,
+    ip TEXT,
+    created_at BIGINT NOT NULL,
+    last_seen_at BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS sessions_email_idx ON sessions (email);
+CREATE INDEX IF NOT EXISTS sessions_last_seen_at_idx ON sessions (last_seen_at);
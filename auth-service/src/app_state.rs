@@ -1,33 +1,74 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use crate::domain::{BannedTokenStore, EmailClient, TwoFACodeStore, UserStore};
+use crate::domain::{
+    AuditSink, BannedTokenStore, EmailClient, EmailTokenStore, LoginThrottleStore, PasskeyStore,
+    RecoveryCodeStore, RefreshTokenStore, SessionStore, TotpStore, TwoFACodeStore, UserStore,
+    WebAuthnChallengeStore,
+};
 
 pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
 pub type BannedtokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
+pub type RefreshTokenStoreType = Arc<RwLock<dyn RefreshTokenStore + Send + Sync>>;
 pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
+pub type TotpStoreType = Arc<RwLock<dyn TotpStore + Send + Sync>>;
+pub type RecoveryCodeStoreType = Arc<RwLock<dyn RecoveryCodeStore + Send + Sync>>;
+pub type PasskeyStoreType = Arc<RwLock<dyn PasskeyStore + Send + Sync>>;
+pub type WebAuthnChallengeStoreType = Arc<RwLock<dyn WebAuthnChallengeStore + Send + Sync>>;
+pub type LoginThrottleStoreType = Arc<RwLock<dyn LoginThrottleStore + Send + Sync>>;
+pub type EmailTokenStoreType = Arc<RwLock<dyn EmailTokenStore + Send + Sync>>;
+pub type SessionStoreType = Arc<RwLock<dyn SessionStore + Send + Sync>>;
 pub type EmailClientType = Arc<dyn EmailClient + Send + Sync>;
+pub type AuditSinkType = Arc<RwLock<dyn AuditSink + Send + Sync>>;
 
 #[derive(Clone)]
 pub struct AppState {
     pub user_store: UserStoreType,
     pub banned_token_store: BannedtokenStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub totp_store: TotpStoreType,
+    pub recovery_code_store: RecoveryCodeStoreType,
+    pub passkey_store: PasskeyStoreType,
+    pub webauthn_challenge_store: WebAuthnChallengeStoreType,
+    pub login_throttle_store: LoginThrottleStoreType,
+    pub email_token_store: EmailTokenStoreType,
+    pub session_store: SessionStoreType,
     pub email_client: EmailClientType,
+    pub audit_sink: AuditSinkType,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_store: UserStoreType,
         banned_token_store: BannedtokenStoreType,
+        refresh_token_store: RefreshTokenStoreType,
         two_fa_code_store: TwoFACodeStoreType,
+        totp_store: TotpStoreType,
+        recovery_code_store: RecoveryCodeStoreType,
+        passkey_store: PasskeyStoreType,
+        webauthn_challenge_store: WebAuthnChallengeStoreType,
+        login_throttle_store: LoginThrottleStoreType,
+        email_token_store: EmailTokenStoreType,
+        session_store: SessionStoreType,
         email_client: EmailClientType,
+        audit_sink: AuditSinkType,
     ) -> Self {
         Self {
             user_store,
             banned_token_store,
+            refresh_token_store,
             two_fa_code_store,
+            totp_store,
+            recovery_code_store,
+            passkey_store,
+            webauthn_challenge_store,
+            login_throttle_store,
+            email_token_store,
+            session_store,
             email_client,
+            audit_sink,
         }
     }
 }
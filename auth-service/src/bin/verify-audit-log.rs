@@ -0,0 +1,65 @@
+use std::process::ExitCode;
+
+use auth_service::{
+    domain::{verify_chain, AuditChainError},
+    services::file_audit_sink::{read_audit_log, AuditLogError},
+};
+
+// Check that an audit log written by the service hasn't been tampered with:
+//
+//     verify-audit-log <path> [--head <hash>]
+//
+// The chain shows records that were edited, removed or reordered, but not
+// records cut off the end. Passing the last hash printed by an earlier run as
+// `--head` catches those too.
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (path, head) = match args.as_slice() {
+        [path] => (path, None),
+        [path, flag, hash] if flag == "--head" => (path, Some(hash)),
+        _ => {
+            eprintln!("usage: verify-audit-log <path> [--head <hash>]");
+            return ExitCode::from(2);
+        }
+    };
+
+    let records = match read_audit_log(path).await {
+        Ok(x) => x,
+        Err(AuditLogError::Io(e)) => {
+            eprintln!("failed to read {}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+        Err(AuditLogError::Malformed { line }) => {
+            eprintln!("line {}: not an audit record", line);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(AuditChainError::Broken { position }) = verify_chain(&records) {
+        eprintln!(
+            "line {}: chain broken, this record or the one before it was changed",
+            position + 1
+        );
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(head) = head {
+        if !records.iter().any(|record| &record.hash == head) {
+            eprintln!("no record with hash {}, the log was cut short", head);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match records.last() {
+        Some(last) => println!(
+            "{} records ok, the last one is {} with hash {}",
+            records.len(),
+            last.seq,
+            last.hash
+        ),
+        None => println!("the log is empty"),
+    }
+
+    ExitCode::SUCCESS
+}
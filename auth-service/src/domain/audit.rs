@@ -0,0 +1,273 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+// What a request tried to do. Every route records one; token verifications
+// are only recorded when they fail, and so is the admin check of admin routes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Signup,
+    VerifyEmail,
+    ResendVerificationEmail,
+    RequestPasswordReset,
+    ConfirmPasswordReset,
+    Login,
+    #[serde(rename = "verify_2fa")]
+    Verify2FA,
+    StartPasskeyLogin,
+    FinishPasskeyLogin,
+    Logout,
+    Refresh,
+    VerifyToken,
+    EnrollTotp,
+    ConfirmTotp,
+    StartPasskeyRegistration,
+    FinishPasskeyRegistration,
+    RegenerateRecoveryCodes,
+    ChangePassword,
+    DeleteAccount,
+    ListSessions,
+    RevokeSession,
+    RevokeAllSessions,
+    // A logged in user who isn't an admin tried an admin route
+    AdminAccess,
+    AdminListUsers,
+    AdminGetUser,
+    AdminDeleteUser,
+    AdminLockUser,
+    AdminDisableUser,
+    AdminActivateUser,
+    AdminLogoutUser,
+    AdminSetUserTwoFA,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    // The password was right and the login now waits for the second factor,
+    // whose outcome is recorded by `verify_2fa`
+    SecondFactorRequired,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            206 => Self::SecondFactorRequired,
+            200..=399 => Self::Success,
+            _ => Self::Failure,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub action: AuditAction,
+    pub outcome: AuditOutcome,
+    // HTTP status of the response
+    pub status: u16,
+    // The error message returned, if the request failed
+    pub error: Option<String>,
+    // The user the request was about, if known. For failed logins it's the
+    // email that was tried, which needn't belong to anyone.
+    pub email: Option<String>,
+    // The admin who made the request, for admin actions
+    pub actor: Option<String>,
+    pub ip: Option<IpAddr>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+}
+
+// `prevHash` of the first record
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// An event as stored. Each record carries the hash of the one before it, so
+// editing, removing or reordering records breaks the chain from there on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    // Counts up from 0
+    pub seq: u64,
+    // Unix timestamp in seconds
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+    #[serde(rename = "prevHash")]
+    pub prev_hash: String,
+    // SHA-256 of `prevHash` and the rest of the record, hex encoded
+    pub hash: String,
+}
+
+impl AuditRecord {
+    // The record for `event` following `previous`, or starting the chain
+    pub fn new(event: AuditEvent, timestamp: i64, previous: Option<&AuditRecord>) -> Self {
+        let mut record = Self {
+            seq: previous.map_or(0, |previous| previous.seq + 1),
+            timestamp,
+            event,
+            prev_hash: previous.map_or(GENESIS_HASH.to_owned(), |previous| previous.hash.clone()),
+            hash: String::new(),
+        };
+        record.hash = record.compute_hash();
+        record
+    }
+
+    // Whether this record is intact and directly follows `previous`
+    pub fn follows(&self, previous: Option<&AuditRecord>) -> bool {
+        let (seq, prev_hash) = match previous {
+            Some(previous) => (previous.seq + 1, previous.hash.as_str()),
+            None => (0, GENESIS_HASH),
+        };
+
+        self.seq == seq && self.prev_hash == prev_hash && self.hash == self.compute_hash()
+    }
+
+    fn compute_hash(&self) -> String {
+        let unhashed = Self {
+            hash: String::new(),
+            ..self.clone()
+        };
+        // Serializing a struct can't fail
+        let json = serde_json::to_string(&unhashed).unwrap_or_default();
+
+        let digest = Sha256::digest(json.as_bytes());
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+// Check that `records` form one unbroken chain from the first record ever
+// written. Returns the number of records, or the position of the first one
+// that doesn't follow the one before it. Records cut off the end can only be
+// noticed by comparing the last hash with one noted down earlier.
+pub fn verify_chain<'a>(
+    records: impl IntoIterator<Item = &'a AuditRecord>,
+) -> Result<usize, AuditChainError> {
+    let mut previous = None;
+    let mut count = 0;
+
+    for record in records {
+        if !record.follows(previous) {
+            return Err(AuditChainError::Broken { position: count });
+        }
+        previous = Some(record);
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuditChainError {
+    // Counted from 0
+    Broken { position: usize },
+}
+
+// Where audit records go. Sinks chain each event onto the record before it.
+#[async_trait::async_trait]
+pub trait AuditSink {
+    async fn record(&mut self, event: AuditEvent, timestamp: i64) -> Result<(), AuditSinkError>;
+    // Every record so far, oldest first
+    async fn get_records(&self) -> Result<Vec<AuditRecord>, AuditSinkError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuditSinkError {
+    UnexpectedError,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(action: AuditAction) -> AuditEvent {
+        AuditEvent {
+            action,
+            outcome: AuditOutcome::Success,
+            status: 200,
+            error: None,
+            email: Some("test@example.com".to_owned()),
+            actor: None,
+            ip: Some("127.0.0.1".parse().unwrap()),
+            user_agent: None,
+        }
+    }
+
+    fn chain() -> Vec<AuditRecord> {
+        let first = AuditRecord::new(event(AuditAction::Signup), 1, None);
+        let second = AuditRecord::new(event(AuditAction::Login), 2, Some(&first));
+        let third = AuditRecord::new(event(AuditAction::Logout), 3, Some(&second));
+        vec![first, second, third]
+    }
+
+    #[test]
+    fn test_records_follow_each_other() {
+        let records = chain();
+        assert_eq!(records[0].prev_hash, GENESIS_HASH);
+        assert_eq!(records[2].seq, 2);
+
+        assert!(records[0].follows(None));
+        assert!(records[1].follows(Some(&records[0])));
+        assert!(records[2].follows(Some(&records[1])));
+    }
+
+    #[test]
+    fn test_edited_record_is_detected() {
+        let mut records = chain();
+        records[1].event.email = Some("other@example.com".to_owned());
+        assert!(!records[1].follows(Some(&records[0])));
+
+        // Rehashing the edited record doesn't help, the next one still
+        // carries the old hash
+        records[1].hash = records[1].compute_hash();
+        assert!(records[1].follows(Some(&records[0])));
+        assert!(!records[2].follows(Some(&records[1])));
+    }
+
+    #[test]
+    fn test_removed_record_is_detected() {
+        let records = chain();
+        assert!(!records[2].follows(Some(&records[0])));
+        assert!(!records[1].follows(None));
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let mut records = chain();
+        assert_eq!(verify_chain(&records), Ok(3));
+        assert_eq!(verify_chain(&records[..0]), Ok(0));
+
+        // Cutting records off the front is noticed, off the end it isn't
+        assert_eq!(
+            verify_chain(&records[1..]),
+            Err(AuditChainError::Broken { position: 0 })
+        );
+        assert_eq!(verify_chain(&records[..2]), Ok(2));
+
+        records.swap(1, 2);
+        assert_eq!(
+            verify_chain(&records),
+            Err(AuditChainError::Broken { position: 1 })
+        );
+    }
+
+    #[test]
+    fn test_record_survives_a_json_round_trip() {
+        let record = chain().remove(1);
+        let json = serde_json::to_string(&record).unwrap();
+        let parsed: AuditRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.hash, parsed.compute_hash());
+    }
+
+    #[test]
+    fn test_outcome_from_status() {
+        assert_eq!(AuditOutcome::from_status(200), AuditOutcome::Success);
+        assert_eq!(AuditOutcome::from_status(204), AuditOutcome::Success);
+        assert_eq!(
+            AuditOutcome::from_status(206),
+            AuditOutcome::SecondFactorRequired
+        );
+        assert_eq!(AuditOutcome::from_status(401), AuditOutcome::Failure);
+    }
+}
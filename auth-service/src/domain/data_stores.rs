@@ -1,15 +1,64 @@
-use rand::{self, Rng};
+use chrono::Utc;
+use rand::{self, rngs::OsRng, Rng};
 use std::fmt;
 use uuid::Uuid;
 
-use super::{Email, Password, User};
+use super::{
+    AccountStatus, AccountStatusError, CredentialId, Email, EmailTokenId, EmailTokenPurpose,
+    FailedLogins, HashedPassword, HashedRecoveryCode, Passkey, Password, Role, Session, SessionId,
+    ThrottleKey, TotpSecret, TwoFAMethod, User, WebAuthnCeremony, WebAuthnChallenge,
+};
 
 #[async_trait::async_trait]
 pub trait UserStore {
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
     async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
-    async fn validate_user(&self, email: &Email, password: &Password)
-        -> Result<(), UserStoreError>;
+    // Checks a raw password against the stored hash, then that the account is
+    // active. Unknown users take as long to check as real ones.
+    async fn validate_user(
+        &self,
+        email: &Email,
+        raw_password: &Password,
+    ) -> Result<(), UserStoreError>;
+    // Replace the hash `old` with `new`, e.g. one computed with the current
+    // cost parameters. Does nothing if the password has changed since `old`
+    // was read.
+    async fn upgrade_password(
+        &mut self,
+        email: &Email,
+        old: &HashedPassword,
+        new: HashedPassword,
+    ) -> Result<(), UserStoreError>;
+    async fn set_two_fa_method(
+        &mut self,
+        email: &Email,
+        two_fa_method: TwoFAMethod,
+    ) -> Result<(), UserStoreError>;
+    async fn set_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError>;
+    async fn set_password(
+        &mut self,
+        email: &Email,
+        password: HashedPassword,
+    ) -> Result<(), UserStoreError>;
+    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError>;
+    // Invalidate every token issued to the user so far. Returns the new
+    // version, which tokens issued from now on carry.
+    async fn bump_token_version(&mut self, email: &Email) -> Result<u64, UserStoreError>;
+    // Up to `limit` users whose email starts with `email_prefix`, in email
+    // order. Pages after the first start after the last email of the one
+    // before.
+    async fn list_users(
+        &self,
+        email_prefix: &str,
+        after: Option<&Email>,
+        limit: usize,
+    ) -> Result<Vec<User>, UserStoreError>;
+    async fn set_role(&mut self, email: &Email, role: Role) -> Result<(), UserStoreError>;
+    async fn set_status(
+        &mut self,
+        email: &Email,
+        status: AccountStatus,
+    ) -> Result<(), UserStoreError>;
 }
 
 #[derive(Debug, PartialEq)]
@@ -17,13 +66,54 @@ pub enum UserStoreError {
     UserAlreadyExists,
     UserNotFound,
     InvalidCredentials,
+    // The password was right, but the account isn't active
+    AccountInactive(AccountStatusError),
     UnexpectedError,
 }
 
+// The checks of `UserStore::validate_user` on a user that has already been
+// looked up. Routes call this after letting go of the store's lock, so a
+// writer queued behind the lock doesn't hold up every login for as long as
+// the hash takes.
+pub async fn validate_user_password(
+    user: Result<User, UserStoreError>,
+    raw_password: &Password,
+) -> Result<User, UserStoreError> {
+    let user = match user {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => {
+            HashedPassword::verify_dummy_password(raw_password).await;
+            return Err(UserStoreError::UserNotFound);
+        }
+        Err(e) => return Err(e),
+    };
+
+    if user
+        .password
+        .verify_raw_password(raw_password)
+        .await
+        .is_err()
+    {
+        return Err(UserStoreError::InvalidCredentials);
+    }
+
+    user.check_status(Utc::now().timestamp())
+        .map_err(UserStoreError::AccountInactive)?;
+
+    Ok(user)
+}
+
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
-    async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError>;
-    async fn get_token(&self, token: &String) -> bool;
+    // `expires_at` is the token's `exp` claim. Once it has passed the token is
+    // rejected on its own, so the entry can be pruned.
+    async fn add_token(
+        &mut self,
+        token: String,
+        expires_at: usize,
+    ) -> Result<(), BannedTokenStoreError>;
+    async fn get_token(&self, token: &str) -> Result<bool, BannedTokenStoreError>;
+    async fn prune_expired(&mut self) -> Result<(), BannedTokenStoreError>;
 }
 
 #[derive(Debug, PartialEq)]
@@ -31,6 +121,91 @@ pub enum BannedTokenStoreError {
     UnexpectedError,
 }
 
+// Refresh tokens are grouped in families, one per session: every rotation
+// replaces a token with a new one in the same family. Presenting a token that
+// was already rotated means it leaked, so the caller should revoke the whole
+// family with `revoke_family`.
+#[async_trait::async_trait]
+pub trait RefreshTokenStore {
+    // Start the family of a new session, e.g. on login.
+    async fn add_token(
+        &mut self,
+        email: Email,
+        session_id: SessionId,
+        token: RefreshToken,
+        expires_at: i64,
+    ) -> Result<(), RefreshTokenStoreError>;
+    // Swap `token` for `new_token` and return the owner and session of the
+    // family. Fails with `TokenReused` for a token that was already rotated.
+    async fn rotate_token(
+        &mut self,
+        token: &RefreshToken,
+        new_token: RefreshToken,
+        expires_at: i64,
+    ) -> Result<(Email, SessionId), RefreshTokenStoreError>;
+    // Revoke the family `token` belongs to, rotated or not, and return its
+    // owner and session so the session can be ended too.
+    async fn revoke_family(
+        &mut self,
+        token: &RefreshToken,
+    ) -> Result<(Email, SessionId), RefreshTokenStoreError>;
+    async fn revoke_session(
+        &mut self,
+        session_id: &SessionId,
+    ) -> Result<(), RefreshTokenStoreError>;
+    // Revoke every family belonging to `email`
+    async fn revoke_user(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError>;
+    async fn prune_expired(&mut self) -> Result<(), RefreshTokenStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RefreshTokenStoreError {
+    TokenNotFound,
+    TokenExpired,
+    TokenReused,
+    UnexpectedError,
+}
+
+// Every session each user is logged in with. A jwt is only accepted while its
+// session is here, so removing a session logs that device out.
+#[async_trait::async_trait]
+pub trait SessionStore {
+    async fn add_session(
+        &mut self,
+        email: Email,
+        session: Session,
+    ) -> Result<(), SessionStoreError>;
+    // Record activity on the session at `now`. Fails once the session has
+    // been removed.
+    async fn touch_session(
+        &mut self,
+        email: &Email,
+        id: &SessionId,
+        now: i64,
+    ) -> Result<(), SessionStoreError>;
+    async fn get_session(
+        &self,
+        email: &Email,
+        id: &SessionId,
+    ) -> Result<Session, SessionStoreError>;
+    // Oldest first
+    async fn get_sessions(&self, email: &Email) -> Result<Vec<Session>, SessionStoreError>;
+    async fn remove_session(
+        &mut self,
+        email: &Email,
+        id: &SessionId,
+    ) -> Result<(), SessionStoreError>;
+    async fn remove_sessions(&mut self, email: &Email) -> Result<(), SessionStoreError>;
+    // Drop sessions that haven't been seen since `last_seen_before`
+    async fn prune_idle(&mut self, last_seen_before: i64) -> Result<(), SessionStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SessionStoreError {
+    SessionNotFound,
+    UnexpectedError,
+}
+
 #[async_trait::async_trait]
 pub trait TwoFACodeStore {
     async fn add_code(
@@ -39,17 +214,236 @@ pub trait TwoFACodeStore {
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError>;
+    // Start a login attempt that is confirmed by a code the store doesn't know,
+    // e.g. one from an authenticator app. See `verify_login_attempt`.
+    async fn add_login_attempt(
+        &mut self,
+        email: Email,
+        login_attempt_id: LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError>;
     async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
     async fn get_code(
         &self,
         email: &Email,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+    // Check a login attempt against the stored one. A match consumes the
+    // code; a miss counts as a failed attempt and the code is dropped once the
+    // store's attempt limit is reached. Expired codes never match.
+    async fn verify_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: &TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError>;
+    // Same as `verify_code`, with the caller deciding whether the code it was
+    // given is right. Expiry and the attempt limit apply all the same.
+    async fn verify_login_attempt(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code_is_valid: bool,
+    ) -> Result<(), TwoFACodeStoreError>;
+}
+
+// Authenticator app secrets. A new secret stays pending until the user has
+// proved they set it up by entering a first code, so a half-finished
+// enrollment can't lock them out.
+#[async_trait::async_trait]
+pub trait TotpStore {
+    // Replaces any earlier pending secret; a confirmed one stays in use
+    async fn set_pending_secret(
+        &mut self,
+        email: Email,
+        secret: TotpSecret,
+    ) -> Result<(), TotpStoreError>;
+    async fn get_pending_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError>;
+    // Make the pending secret the user's secret. `step` is the step of the
+    // code that confirmed it, which can't be used again.
+    async fn confirm_secret(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError>;
+    async fn get_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError>;
+    // Record that a code for `step` was accepted. Fails for steps at or before
+    // the last accepted one, so a code can't be replayed.
+    async fn use_step(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError>;
+    // Remove both the confirmed and the pending secret, if there are any
+    async fn delete_secrets(&mut self, email: &Email) -> Result<(), TotpStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TotpStoreError {
+    SecretNotFound,
+    StepAlreadyUsed,
+    UnexpectedError,
+}
+
+// Hashes of each user's unused recovery codes
+#[async_trait::async_trait]
+pub trait RecoveryCodeStore {
+    // Replace the user's codes with a new set
+    async fn set_codes(
+        &mut self,
+        email: Email,
+        codes: Vec<HashedRecoveryCode>,
+    ) -> Result<(), RecoveryCodeStoreError>;
+    // Whether `code` is one of the user's unused codes. Doesn't use it up.
+    async fn has_code(
+        &self,
+        email: &Email,
+        code: &HashedRecoveryCode,
+    ) -> Result<bool, RecoveryCodeStoreError>;
+    // Remove `code` from the user's set; it can't be used again.
+    async fn use_code(
+        &mut self,
+        email: &Email,
+        code: &HashedRecoveryCode,
+    ) -> Result<(), RecoveryCodeStoreError>;
+    async fn count_codes(&self, email: &Email) -> Result<usize, RecoveryCodeStoreError>;
+    async fn delete_codes(&mut self, email: &Email) -> Result<(), RecoveryCodeStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RecoveryCodeStoreError {
+    CodeNotFound,
+    UnexpectedError,
+}
+
+// WebAuthn credentials registered by each user
+#[async_trait::async_trait]
+pub trait PasskeyStore {
+    async fn add_passkey(
+        &mut self,
+        email: Email,
+        passkey: Passkey,
+    ) -> Result<(), PasskeyStoreError>;
+    async fn get_passkeys(&self, email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError>;
+    async fn update_sign_count(
+        &mut self,
+        email: &Email,
+        credential_id: &CredentialId,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError>;
+    async fn delete_passkeys(&mut self, email: &Email) -> Result<(), PasskeyStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PasskeyStoreError {
+    CredentialAlreadyExists,
+    CredentialNotFound,
+    UnexpectedError,
+}
+
+// Outstanding WebAuthn challenges. Starting a ceremony doesn't cancel the
+// user's earlier challenges, so nobody can break a login in progress by
+// starting another one for the same email. Taking a challenge removes it, so
+// each can only be answered once.
+#[async_trait::async_trait]
+pub trait WebAuthnChallengeStore {
+    // Drops the user's oldest challenge for the ceremony once they have too
+    // many outstanding
+    async fn add_challenge(
+        &mut self,
+        email: Email,
+        ceremony: WebAuthnCeremony,
+        challenge: WebAuthnChallenge,
+    ) -> Result<(), WebAuthnChallengeStoreError>;
+    // Take the user's challenge whose value is `challenge`, as found in the
+    // browser's answer
+    async fn take_challenge(
+        &mut self,
+        email: &Email,
+        ceremony: WebAuthnCeremony,
+        challenge: &str,
+    ) -> Result<WebAuthnChallenge, WebAuthnChallengeStoreError>;
+    async fn delete_challenges(&mut self, email: &Email)
+        -> Result<(), WebAuthnChallengeStoreError>;
+}
+
+// Failed login counters behind login throttling. Instances behind a load
+// balancer need a shared implementation so they see each other's failures.
+#[async_trait::async_trait]
+pub trait LoginThrottleStore {
+    async fn get_failures(
+        &self,
+        key: &ThrottleKey,
+    ) -> Result<Option<FailedLogins>, LoginThrottleStoreError>;
+    // Count a failure at `now`, starting again from one if the last failure
+    // was `reset_after_seconds` or more ago.
+    async fn record_failure(
+        &mut self,
+        key: ThrottleKey,
+        now: i64,
+        reset_after_seconds: i64,
+    ) -> Result<FailedLogins, LoginThrottleStoreError>;
+    async fn clear_failures(&mut self, key: &ThrottleKey) -> Result<(), LoginThrottleStoreError>;
+    // Take back the failure counted at `failed_at`, for an attempt that was
+    // counted before it was checked and turned out to be right. Unless a
+    // later failure came in since, the last failure goes back to
+    // `previous_failure_at`.
+    async fn forgive_failure(
+        &mut self,
+        key: &ThrottleKey,
+        failed_at: i64,
+        previous_failure_at: Option<i64>,
+    ) -> Result<(), LoginThrottleStoreError>;
+    // Drop the counters of keys whose last failure was before
+    // `last_failure_before`
+    async fn prune_expired(
+        &mut self,
+        last_failure_before: i64,
+    ) -> Result<(), LoginThrottleStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoginThrottleStoreError {
+    UnexpectedError,
+}
+
+// The newest link emailed to each user for each purpose. Sending a new link
+// supersedes the old one, and taking a link removes it, so each works once.
+#[async_trait::async_trait]
+pub trait EmailTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        purpose: EmailTokenPurpose,
+        token_id: EmailTokenId,
+        sent_at: i64,
+    ) -> Result<(), EmailTokenStoreError>;
+    // When the current link was sent, if there is one
+    async fn get_sent_at(
+        &self,
+        email: &Email,
+        purpose: EmailTokenPurpose,
+    ) -> Result<Option<i64>, EmailTokenStoreError>;
+    async fn take_token(
+        &mut self,
+        email: &Email,
+        purpose: EmailTokenPurpose,
+        token_id: &EmailTokenId,
+    ) -> Result<(), EmailTokenStoreError>;
+    // Drop the user's links for every purpose
+    async fn delete_tokens(&mut self, email: &Email) -> Result<(), EmailTokenStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EmailTokenStoreError {
+    TokenNotFound,
+    UnexpectedError,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum WebAuthnChallengeStoreError {
+    ChallengeNotFound,
+    ChallengeExpired,
+    UnexpectedError,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum TwoFACodeStoreError {
     LoginAttemptIdNotFound,
     EmailNotFound,
+    IncorrectCode,
+    CodeExpired,
+    TooManyAttempts,
     UnexpectedError,
 }
 
@@ -91,24 +485,21 @@ pub struct TwoFACode(String);
 
 impl TwoFACode {
     pub fn parse(code: String) -> Result<Self, String> {
-        // Ensure `code` is a valid 6-digit code
-        match code.parse::<i32>() {
-            Ok(_) => {
-                if code.len() == 6 {
-                    Ok(Self(code))
-                } else {
-                    Err("code length not equal to 6".to_string())
-                }
-            }
-            Err(e) => Err(e.to_string()),
+        // Ensure `code` is exactly 6 digits, leading zeros included
+        if code.len() != 6 {
+            return Err("code length not equal to 6".to_string());
         }
+        if !code.chars().all(|c| c.is_ascii_digit()) {
+            return Err("code must only contain digits".to_string());
+        }
+        Ok(Self(code))
     }
 }
 
 impl Default for TwoFACode {
     fn default() -> Self {
-        // The code should be 6 digits (ex: 834629)
-        Self(rand::thread_rng().gen_range(1..=6).to_string())
+        // Uniform over 000000-999999, drawn from the OS CSPRNG
+        Self(format!("{:06}", OsRng.gen_range(0..1_000_000)))
     }
 }
 
@@ -117,3 +508,57 @@ impl AsRef<str> for TwoFACode {
         &self.0
     }
 }
+
+// Opaque refresh token: 32 random bytes, hex encoded.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RefreshToken(String);
+
+impl RefreshToken {
+    pub fn parse(token: String) -> Result<Self, String> {
+        if token.len() == 64 && token.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(Self(token.to_ascii_lowercase()))
+        } else {
+            Err("refresh token must be 64 hex characters".to_string())
+        }
+    }
+}
+
+impl Default for RefreshToken {
+    fn default() -> Self {
+        let bytes: [u8; 32] = rand::thread_rng().gen();
+        Self(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+impl AsRef<str> for RefreshToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_two_fa_code_parses() {
+        for _ in 0..1000 {
+            let code = TwoFACode::default();
+            assert!(TwoFACode::parse(code.as_ref().to_owned()).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_two_fa_code_keeps_leading_zeros() {
+        let code = TwoFACode::parse("000042".to_owned()).unwrap();
+        assert_eq!(code.as_ref(), "000042");
+    }
+
+    #[test]
+    fn test_two_fa_code_rejects_non_digits() {
+        assert!(TwoFACode::parse("-12345".to_owned()).is_err());
+        assert!(TwoFACode::parse("+12345".to_owned()).is_err());
+        assert!(TwoFACode::parse("12a456".to_owned()).is_err());
+        assert!(TwoFACode::parse("1234567".to_owned()).is_err());
+    }
+}
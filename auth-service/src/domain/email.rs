@@ -4,7 +4,7 @@ pub struct Email(String);
 impl Email {
     pub fn parse(s: String) -> Result<Email, String> {
         match s.contains("@") {
-            true => Ok(Self(String::from(s))),
+            true => Ok(Self(s)),
             false => Err("missing '@' sign in email address".to_string()),
         }
     }
@@ -17,7 +17,6 @@ impl AsRef<str> for Email {
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::Email;
 
@@ -9,4 +9,16 @@ pub trait EmailClient {
         subject: &str,
         content: &str,
     ) -> Result<(), String>;
+
+    // Send an email with an HTML alternative to the plain text. Clients that
+    // can't send HTML only send the text.
+    async fn send_html_email(
+        &self,
+        recipient: &Email,
+        subject: &str,
+        text_content: &str,
+        _html_content: &str,
+    ) -> Result<(), String> {
+        self.send_email(recipient, subject, text_content).await
+    }
 }
@@ -0,0 +1,77 @@
+use uuid::Uuid;
+
+// What a link emailed to a user lets them do. It is part of the link's
+// signed token, so a link for one purpose can't be used for another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EmailTokenPurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+impl EmailTokenPurpose {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "verify_email" => Ok(Self::VerifyEmail),
+            "reset_password" => Ok(Self::ResetPassword),
+            other => Err(format!("unknown email token purpose: {}", other)),
+        }
+    }
+}
+
+impl AsRef<str> for EmailTokenPurpose {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::VerifyEmail => "verify_email",
+            Self::ResetPassword => "reset_password",
+        }
+    }
+}
+
+// Identifies one emailed link (the `jti` of its token), so the link works
+// only once and a newer link can supersede it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EmailTokenId(String);
+
+impl EmailTokenId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        match Uuid::parse_str(&id) {
+            Ok(uuid) => Ok(Self(uuid.to_string())),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+impl Default for EmailTokenId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for EmailTokenId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_purpose_round_trips() {
+        for purpose in [
+            EmailTokenPurpose::VerifyEmail,
+            EmailTokenPurpose::ResetPassword,
+        ] {
+            assert_eq!(EmailTokenPurpose::parse(purpose.as_ref()), Ok(purpose));
+        }
+        assert!(EmailTokenPurpose::parse("reset_everything").is_err());
+    }
+
+    #[test]
+    fn test_token_id_must_be_a_uuid() {
+        let id = EmailTokenId::default();
+        assert_eq!(EmailTokenId::parse(id.as_ref().to_owned()), Ok(id));
+        assert!(EmailTokenId::parse("not-a-uuid".to_owned()).is_err());
+    }
+}
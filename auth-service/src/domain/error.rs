@@ -1,3 +1,5 @@
+use super::AccountStatusError;
+
 pub enum AuthAPIError {
     UserAlreadyExists,
     InvalidCredentials,
@@ -5,4 +7,24 @@ pub enum AuthAPIError {
     UnexpectedError,
     MissingToken,
     InvalidToken,
+    // Too many failed logins; try again after this many seconds
+    TooManyAttempts { retry_after_seconds: u64 },
+    EmailNotVerified,
+    SessionNotFound,
+    // Logged in, but the user's role doesn't allow this
+    Forbidden,
+    UserNotFound,
+    // The account isn't active. The details are passed on so the login page
+    // can tell the user when they can try again, or why they can't.
+    AccountLocked { until: i64 },
+    AccountDisabled { reason: String },
+}
+
+impl From<AccountStatusError> for AuthAPIError {
+    fn from(error: AccountStatusError) -> Self {
+        match error {
+            AccountStatusError::Locked { until } => AuthAPIError::AccountLocked { until },
+            AccountStatusError::Disabled { reason } => AuthAPIError::AccountDisabled { reason },
+        }
+    }
 }
@@ -0,0 +1,224 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+
+use lazy_static::lazy_static;
+
+use super::Password;
+use crate::utils::constants::PASSWORD_HASHING_PARAMS;
+
+// Argon2id cost parameters. Changing them only affects new hashes; existing
+// hashes are upgraded the next time their owner logs in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PasswordHashingParams {
+    pub memory_cost_kib: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashingParams {
+    fn default() -> Self {
+        // OWASP recommended minimum for Argon2id
+        Self {
+            memory_cost_kib: 19456,
+            time_cost: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl PasswordHashingParams {
+    fn hasher(&self) -> Result<Argon2<'static>, String> {
+        let params = Params::new(self.memory_cost_kib, self.time_cost, self.parallelism, None)
+            .map_err(|e| e.to_string())?;
+
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+// An Argon2id password hash in PHC string format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashedPassword(String);
+
+impl HashedPassword {
+    // Hash a password with the configured cost parameters.
+    pub async fn parse(password: Password) -> Result<Self, String> {
+        Self::parse_with_params(password, *PASSWORD_HASHING_PARAMS).await
+    }
+
+    pub async fn parse_with_params(
+        password: Password,
+        params: PasswordHashingParams,
+    ) -> Result<Self, String> {
+        // Hashing is deliberately slow, keep it off the async runtime.
+        tokio::task::spawn_blocking(move || Self::hash_blocking(&password, params))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+
+    fn hash_blocking(password: &Password, params: PasswordHashingParams) -> Result<Self, String> {
+        let salt = SaltString::generate(&mut OsRng);
+        params
+            .hasher()?
+            .hash_password(password.as_ref().as_bytes(), &salt)
+            .map(|hash| Self(hash.to_string()))
+            .map_err(|e| e.to_string())
+    }
+
+    // Wrap an already computed PHC string, e.g. one loaded from a database.
+    pub fn parse_password_hash(hash: String) -> Result<Self, String> {
+        let parsed = PasswordHash::new(&hash).map_err(|e| e.to_string())?;
+        if parsed.algorithm != Algorithm::Argon2id.ident() {
+            return Err("password hash is not argon2id".to_string());
+        }
+        Ok(Self(hash))
+    }
+
+    // Check a candidate password against the hash. The comparison of the
+    // derived keys is constant time.
+    pub async fn verify_raw_password(&self, candidate: &Password) -> Result<(), String> {
+        let hash = self.0.clone();
+        let candidate = candidate.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let expected = PasswordHash::new(&hash).map_err(|e| e.to_string())?;
+            Argon2::default()
+                .verify_password(candidate.as_ref().as_bytes(), &expected)
+                .map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| e.to_string())?
+    }
+
+    // Take as long as `verify_raw_password` does, for when there is no hash to
+    // check against because the user doesn't exist. Otherwise the response
+    // time would tell who has an account.
+    pub async fn verify_dummy_password(candidate: &Password) {
+        let candidate = candidate.clone();
+
+        let _ = tokio::task::spawn_blocking(move || {
+            let expected = PasswordHash::new(DUMMY_HASH.as_ref()).map_err(|e| e.to_string())?;
+            Argon2::default()
+                .verify_password(candidate.as_ref().as_bytes(), &expected)
+                .map_err(|e| e.to_string())
+        })
+        .await;
+    }
+
+    // True when the hash was computed with parameters other than the
+    // configured ones.
+    pub fn needs_rehash(&self) -> bool {
+        self.needs_rehash_with_params(&PASSWORD_HASHING_PARAMS)
+    }
+
+    pub fn needs_rehash_with_params(&self, params: &PasswordHashingParams) -> bool {
+        let hash = match PasswordHash::new(&self.0) {
+            Ok(x) => x,
+            Err(_) => return true,
+        };
+
+        match Params::try_from(&hash) {
+            Ok(current) => {
+                hash.algorithm != Algorithm::Argon2id.ident()
+                    || current.m_cost() != params.memory_cost_kib
+                    || current.t_cost() != params.time_cost
+                    || current.p_cost() != params.parallelism
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+lazy_static! {
+    // Hash of a password nobody has, with the configured cost parameters
+    static ref DUMMY_HASH: HashedPassword = HashedPassword::hash_blocking(
+        &Password::parse(uuid::Uuid::new_v4().to_string()).expect("uuid is long enough"),
+        *PASSWORD_HASHING_PARAMS,
+    )
+    .expect("Failed to hash dummy password");
+}
+
+impl AsRef<str> for HashedPassword {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast_params() -> PasswordHashingParams {
+        PasswordHashingParams {
+            memory_cost_kib: 1024,
+            time_cost: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_produce_an_argon2id_phc_string() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+
+        let hashed = HashedPassword::parse_with_params(password, fast_params())
+            .await
+            .unwrap();
+
+        assert!(hashed
+            .as_ref()
+            .starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert!(!hashed.as_ref().contains("password123"));
+    }
+
+    #[tokio::test]
+    async fn it_should_verify_the_original_password_only() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let hashed = HashedPassword::parse_with_params(password.clone(), fast_params())
+            .await
+            .unwrap();
+
+        assert!(hashed.verify_raw_password(&password).await.is_ok());
+
+        let wrong = Password::parse("password124".to_owned()).unwrap();
+        assert!(hashed.verify_raw_password(&wrong).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_should_salt_every_hash() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let first = HashedPassword::parse_with_params(password.clone(), fast_params())
+            .await
+            .unwrap();
+        let second = HashedPassword::parse_with_params(password, fast_params())
+            .await
+            .unwrap();
+
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn it_should_need_rehash_when_params_change() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let hashed = HashedPassword::parse_with_params(password, fast_params())
+            .await
+            .unwrap();
+
+        assert!(!hashed.needs_rehash_with_params(&fast_params()));
+
+        let stronger = PasswordHashingParams {
+            time_cost: 2,
+            ..fast_params()
+        };
+        assert!(hashed.needs_rehash_with_params(&stronger));
+    }
+
+    #[test]
+    fn it_should_reject_strings_that_are_not_argon2id_hashes() {
+        assert!(HashedPassword::parse_password_hash("password123".to_owned()).is_err());
+        assert!(HashedPassword::parse_password_hash(
+            "$argon2i$v=19$m=16,t=2,p=1$c29tZXNhbHQ$+RRs0e/dyZUIl5OqIvEMNQ".to_owned()
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::net::IpAddr;
+
+use super::Email;
+
+// What failed logins are counted against. Counting per account stops
+// guessing one user's password from many addresses, counting per address
+// stops trying a common password against many accounts.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ThrottleKey {
+    Email(Email),
+    Ip(IpAddr),
+}
+
+impl ThrottleKey {
+    // Stable text form for stores that key by string
+    pub fn to_key_string(&self) -> String {
+        match self {
+            ThrottleKey::Email(email) => format!("email:{}", email.as_ref()),
+            ThrottleKey::Ip(ip) => format!("ip:{}", ip),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FailedLogins {
+    pub count: u32,
+    // Unix timestamp in seconds
+    pub last_failure_at: i64,
+}
+
+// How long to make a key wait after each failed login. The first
+// `free_attempts` failures cost nothing, after that the wait doubles with
+// every failure up to `max_delay_seconds`. At `lockout_after` failures the
+// key is locked out for `lockout_seconds`. Failures are forgotten once
+// `reset_after_seconds` pass without a new one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThrottlePolicy {
+    pub free_attempts: u32,
+    pub base_delay_seconds: i64,
+    pub max_delay_seconds: i64,
+    pub lockout_after: u32,
+    pub lockout_seconds: i64,
+    pub reset_after_seconds: i64,
+}
+
+impl ThrottlePolicy {
+    // Seconds until the key may try again, or None if it may try now
+    pub fn retry_after(&self, failures: &FailedLogins, now: i64) -> Option<u64> {
+        if self.is_expired(failures, now) {
+            return None;
+        }
+
+        let delay = if failures.count >= self.lockout_after {
+            self.lockout_seconds
+        } else if failures.count <= self.free_attempts {
+            0
+        } else {
+            let doublings = (failures.count - self.free_attempts - 1).min(62);
+            self.base_delay_seconds
+                .saturating_mul(1 << doublings)
+                .min(self.max_delay_seconds)
+        };
+
+        let wait = failures.last_failure_at + delay - now;
+        (wait > 0).then_some(wait as u64)
+    }
+
+    // Whether the failures are old enough to be forgotten
+    pub fn is_expired(&self, failures: &FailedLogins, now: i64) -> bool {
+        now - failures.last_failure_at >= self.reset_after_seconds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: ThrottlePolicy = ThrottlePolicy {
+        free_attempts: 3,
+        base_delay_seconds: 2,
+        max_delay_seconds: 30,
+        lockout_after: 10,
+        lockout_seconds: 600,
+        reset_after_seconds: 900,
+    };
+
+    fn failures(count: u32) -> FailedLogins {
+        FailedLogins {
+            count,
+            last_failure_at: 1000,
+        }
+    }
+
+    #[test]
+    fn test_free_attempts_are_not_delayed() {
+        for count in 0..=3 {
+            assert_eq!(POLICY.retry_after(&failures(count), 1000), None);
+        }
+    }
+
+    #[test]
+    fn test_delay_doubles_up_to_the_maximum() {
+        assert_eq!(POLICY.retry_after(&failures(4), 1000), Some(2));
+        assert_eq!(POLICY.retry_after(&failures(5), 1000), Some(4));
+        assert_eq!(POLICY.retry_after(&failures(6), 1000), Some(8));
+        assert_eq!(POLICY.retry_after(&failures(9), 1000), Some(30));
+        // time already waited counts
+        assert_eq!(POLICY.retry_after(&failures(6), 1005), Some(3));
+        assert_eq!(POLICY.retry_after(&failures(6), 1008), None);
+    }
+
+    #[test]
+    fn test_lockout() {
+        assert_eq!(POLICY.retry_after(&failures(10), 1000), Some(600));
+        assert_eq!(POLICY.retry_after(&failures(50), 1300), Some(300));
+        assert_eq!(POLICY.retry_after(&failures(50), 1600), None);
+    }
+
+    #[test]
+    fn test_failures_are_forgotten() {
+        assert!(!POLICY.is_expired(&failures(10), 1899));
+        assert!(POLICY.is_expired(&failures(10), 1900));
+        assert_eq!(POLICY.retry_after(&failures(10), 1900), None);
+    }
+}
@@ -1,13 +1,29 @@
+pub mod audit;
 pub mod data_stores;
 pub mod email;
 pub mod email_client;
+pub mod email_token;
 pub mod error;
+pub mod hashed_password;
+pub mod login_throttle;
 pub mod password;
+pub mod recovery_code;
+pub mod session;
+pub mod totp;
 pub mod user;
+pub mod webauthn;
 
+pub use audit::*;
 pub use data_stores::*;
 pub use email::*;
 pub use email_client::*;
+pub use email_token::*;
 pub use error::*;
+pub use hashed_password::*;
+pub use login_throttle::*;
 pub use password::*;
+pub use recovery_code::*;
+pub use session::*;
+pub use totp::*;
 pub use user::*;
+pub use webauthn::*;
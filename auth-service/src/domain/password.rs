@@ -4,7 +4,7 @@ pub struct Password(String);
 impl Password {
     pub fn parse(s: String) -> Result<Password, String> {
         match s.len() >= 8 {
-            true => Ok(Self(String::from(s))),
+            true => Ok(Self(s)),
             false => Err("length must be greter than or equal to 8".to_string()),
         }
     }
@@ -17,7 +17,6 @@ impl AsRef<str> for Password {
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::Password;
 
@@ -0,0 +1,131 @@
+use rand::{rngs::OsRng, Rng};
+use sha2::{Digest, Sha256};
+
+// Lowercase letters and digits minus the ones that are easy to misread
+// (0/o, 1/l/i), so codes survive being written down.
+const ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const GROUP_LEN: usize = 5;
+
+// Single-use code that stands in for the second factor when a user has lost
+// it, e.g. `7hk2m-qx9rt`. About 49 bits of entropy, which together with the
+// 2FA attempt limit makes guessing one hopeless.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryCode(String);
+
+impl RecoveryCode {
+    // Accepts codes with or without the dash, in any case and with stray
+    // whitespace, since users copy them from wherever they wrote them down.
+    pub fn parse(code: String) -> Result<Self, String> {
+        let normalized: String = code
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        if normalized.len() != 2 * GROUP_LEN {
+            return Err("recovery code must be 10 characters".to_string());
+        }
+        if !normalized.bytes().all(|b| ALPHABET.contains(&b)) {
+            return Err("recovery code contains invalid characters".to_string());
+        }
+
+        let (first, second) = normalized.split_at(GROUP_LEN);
+        Ok(Self(format!("{}-{}", first, second)))
+    }
+
+    // Codes are stored hashed. They are random enough that a fast hash is
+    // fine, and it keeps lookups by hash possible.
+    pub fn hash(&self) -> HashedRecoveryCode {
+        let digest = Sha256::digest(self.0.as_bytes());
+        HashedRecoveryCode(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+impl Default for RecoveryCode {
+    fn default() -> Self {
+        let mut rng = OsRng;
+        let mut code = String::with_capacity(2 * GROUP_LEN + 1);
+        for i in 0..2 * GROUP_LEN {
+            if i == GROUP_LEN {
+                code.push('-');
+            }
+            code.push(ALPHABET[rng.gen_range(0..ALPHABET.len())] as char);
+        }
+        Self(code)
+    }
+}
+
+impl AsRef<str> for RecoveryCode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// SHA-256 of a recovery code, hex encoded
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HashedRecoveryCode(String);
+
+impl HashedRecoveryCode {
+    pub fn parse(hash: String) -> Result<Self, String> {
+        if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            Ok(Self(hash.to_ascii_lowercase()))
+        } else {
+            Err("recovery code hash must be 64 hex characters".to_string())
+        }
+    }
+}
+
+impl AsRef<str> for HashedRecoveryCode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_code_parses_to_itself() {
+        for _ in 0..100 {
+            let code = RecoveryCode::default();
+            assert_eq!(RecoveryCode::parse(code.as_ref().to_owned()), Ok(code));
+        }
+    }
+
+    #[test]
+    fn test_parse_normalizes_what_users_type() {
+        let expected = RecoveryCode::parse("7hk2m-qx9rt".to_owned()).unwrap();
+
+        assert_eq!(
+            RecoveryCode::parse("7HK2MQX9RT".to_owned()),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            RecoveryCode::parse(" 7hk2m qx9rt\n".to_owned()),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_other_input() {
+        assert!(RecoveryCode::parse("123456".to_owned()).is_err());
+        assert!(RecoveryCode::parse("7hk2m-qx9r0".to_owned()).is_err());
+        assert!(RecoveryCode::parse("7hk2m-qx9rtt".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_hash_is_stable_and_parses() {
+        let code = RecoveryCode::parse("7hk2m-qx9rt".to_owned()).unwrap();
+        let hash = code.hash();
+
+        assert_eq!(
+            hash,
+            RecoveryCode::parse("7HK2MQX9RT".to_owned()).unwrap().hash()
+        );
+        assert_eq!(
+            HashedRecoveryCode::parse(hash.as_ref().to_owned()),
+            Ok(hash)
+        );
+    }
+}
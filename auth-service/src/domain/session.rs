@@ -0,0 +1,63 @@
+use std::net::IpAddr;
+use uuid::Uuid;
+
+// Identifies a login session. Every jwt issued for the session carries it as
+// its `jti`, and the session's refresh tokens form one family.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SessionId(String);
+
+impl SessionId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        match Uuid::parse_str(&id) {
+            Ok(uuid) => Ok(Self(uuid.to_string())),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+impl Default for SessionId {
+    fn default() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for SessionId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// A device a user is logged in on. The user agent and address are the ones
+// the session was started from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub id: SessionId,
+    pub user_agent: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub created_at: i64,
+    pub last_seen_at: i64,
+}
+
+impl Session {
+    pub fn new(user_agent: Option<String>, ip: Option<IpAddr>, now: i64) -> Self {
+        Self {
+            id: SessionId::default(),
+            user_agent,
+            ip,
+            created_at: now,
+            last_seen_at: now,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_id_must_be_a_uuid() {
+        let id = SessionId::default();
+        assert_eq!(SessionId::parse(id.as_ref().to_owned()), Ok(id));
+        assert!(SessionId::parse("not-a-uuid".to_owned()).is_err());
+    }
+}
@@ -1,18 +1,128 @@
-use super::{Email, Password};
+use serde::{Deserialize, Serialize};
+
+use super::{Email, HashedPassword};
+
+// How a user proves the second factor after their password, if at all
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TwoFAMethod {
+    None,
+    // A one-time code sent by email on every login
+    Email,
+    // A code from an authenticator app, see `TotpSecret`
+    Totp,
+}
+
+impl TwoFAMethod {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "none" => Ok(Self::None),
+            "email" => Ok(Self::Email),
+            "totp" => Ok(Self::Totp),
+            other => Err(format!("unknown 2FA method: {}", other)),
+        }
+    }
+}
+
+impl AsRef<str> for TwoFAMethod {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::None => "none",
+            Self::Email => "email",
+            Self::Totp => "totp",
+        }
+    }
+}
+
+// What a user may do. Carried in their jwts, so routes can check it without
+// a store lookup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    // May manage other users through the admin API
+    Admin,
+}
+
+impl Role {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "user" => Ok(Self::User),
+            "admin" => Ok(Self::Admin),
+            other => Err(format!("unknown role: {}", other)),
+        }
+    }
+}
+
+impl AsRef<str> for Role {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::User => "user",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+// Whether a user may log in. Set by admins; accounts start out active.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountStatus {
+    Active,
+    // Locked until this unix timestamp in seconds. The lock lifts by itself.
+    LockedUntil(i64),
+    // Off until an admin turns it back on, e.g. because it was compromised.
+    // The reason is shown to the user.
+    Disabled(String),
+}
+
+// Why a user who isn't active was turned away
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AccountStatusError {
+    Locked { until: i64 },
+    Disabled { reason: String },
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct User {
     pub email: Email,
-    pub password: Password,
-    pub requires_2fa: bool,
+    pub password: HashedPassword,
+    pub two_fa_method: TwoFAMethod,
+    // Set once the user has followed the link emailed at signup. Unverified
+    // users can't log in.
+    pub email_verified: bool,
+    // Carried by every token issued to the user. Bumping it invalidates all
+    // of them at once.
+    pub token_version: u64,
+    pub role: Role,
+    pub status: AccountStatus,
 }
 
 impl User {
-    pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
+    // New users start out unverified, as regular active users
+    pub fn new(email: Email, password: HashedPassword, two_fa_method: TwoFAMethod) -> Self {
         Self {
             email,
             password,
-            requires_2fa,
+            two_fa_method,
+            email_verified: false,
+            token_version: 0,
+            role: Role::User,
+            status: AccountStatus::Active,
+        }
+    }
+
+    pub fn requires_2fa(&self) -> bool {
+        self.two_fa_method != TwoFAMethod::None
+    }
+
+    // Ok if the user may log in at `now`, a unix timestamp in seconds
+    pub fn check_status(&self, now: i64) -> Result<(), AccountStatusError> {
+        match &self.status {
+            AccountStatus::LockedUntil(until) if *until > now => {
+                Err(AccountStatusError::Locked { until: *until })
+            }
+            AccountStatus::Disabled(reason) => Err(AccountStatusError::Disabled {
+                reason: reason.clone(),
+            }),
+            _ => Ok(()),
         }
     }
 }
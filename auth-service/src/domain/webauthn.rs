@@ -0,0 +1,634 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::Value;
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// COSE algorithm id of ECDSA over P-256 with SHA-256, the only kind of key
+// we ask authenticators for. Every platform authenticator supports it.
+pub const COSE_ALG_ES256: i64 = -7;
+
+// Bits of the flags byte in authenticator data
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+#[derive(Debug, PartialEq)]
+pub enum WebAuthnError {
+    InvalidEncoding,
+    WrongCeremony,
+    ChallengeMismatch,
+    OriginMismatch,
+    RpIdMismatch,
+    UserNotPresent,
+    UserNotVerified,
+    CredentialIdMismatch,
+    UnsupportedKey,
+    InvalidSignature,
+    SignCountNotIncreased,
+}
+
+// Which ceremony a challenge was issued for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WebAuthnCeremony {
+    Registration,
+    Authentication,
+}
+
+impl WebAuthnCeremony {
+    // The `type` the browser puts in clientDataJSON
+    fn client_data_type(self) -> &'static str {
+        match self {
+            WebAuthnCeremony::Registration => "webauthn.create",
+            WebAuthnCeremony::Authentication => "webauthn.get",
+        }
+    }
+}
+
+// 32 random bytes, base64url encoded as they appear in clientDataJSON
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebAuthnChallenge(String);
+
+impl Default for WebAuthnChallenge {
+    fn default() -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self(URL_SAFE_NO_PAD.encode(bytes))
+    }
+}
+
+impl AsRef<str> for WebAuthnChallenge {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Credential id chosen by the authenticator, base64url encoded
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CredentialId(String);
+
+impl CredentialId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        match URL_SAFE_NO_PAD.decode(&id) {
+            // The spec caps credential ids at 1023 bytes
+            Ok(bytes) if !bytes.is_empty() && bytes.len() <= 1023 => Ok(Self(id)),
+            _ => Err("credential id must be 1 to 1023 base64url encoded bytes".to_string()),
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self(URL_SAFE_NO_PAD.encode(bytes))
+    }
+}
+
+impl AsRef<str> for CredentialId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// A registered authenticator. `public_key` is the P-256 point in SEC1
+// uncompressed form.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Passkey {
+    pub credential_id: CredentialId,
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+// What `navigator.credentials.create()` returns, with the ArrayBuffers
+// base64url encoded
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+impl RegistrationCredential {
+    // The challenge the browser answered, unchecked
+    pub fn challenge(&self) -> Result<String, WebAuthnError> {
+        client_data_challenge(&self.response.client_data_json)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+// What `navigator.credentials.get()` returns, with the ArrayBuffers base64url
+// encoded
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AuthenticationCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+impl AuthenticationCredential {
+    // The challenge the browser answered, unchecked
+    pub fn challenge(&self) -> Result<String, WebAuthnError> {
+        client_data_challenge(&self.response.client_data_json)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: [u8; 32],
+    flags: u8,
+    sign_count: u32,
+    // Only present when registering
+    credential: Option<(CredentialId, Vec<u8>)>,
+}
+
+// The site passkeys are bound to. `id` is the domain, `origin` the exact
+// origin the browser reports for pages of the site.
+#[derive(Clone, Debug)]
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+    pub origin: String,
+}
+
+impl RelyingParty {
+    // Check the response to a registration challenge and return the new
+    // passkey. Attestation statements are not checked: we ask for "none" and
+    // don't restrict which authenticators users may register.
+    pub fn verify_registration(
+        &self,
+        challenge: &WebAuthnChallenge,
+        credential: &RegistrationCredential,
+        require_user_verification: bool,
+    ) -> Result<Passkey, WebAuthnError> {
+        let client_data_json = decode(&credential.response.client_data_json)?;
+        self.check_client_data(&client_data_json, WebAuthnCeremony::Registration, challenge)?;
+
+        let auth_data = attestation_auth_data(&decode(&credential.response.attestation_object)?)?;
+        let auth_data = self.check_authenticator_data(&auth_data, require_user_verification)?;
+
+        let (credential_id, public_key) =
+            auth_data.credential.ok_or(WebAuthnError::InvalidEncoding)?;
+        if credential_id.as_ref() != credential.id {
+            return Err(WebAuthnError::CredentialIdMismatch);
+        }
+
+        Ok(Passkey {
+            credential_id,
+            public_key,
+            sign_count: auth_data.sign_count,
+        })
+    }
+
+    // Check the response to an authentication challenge against `passkey`
+    // and return the authenticator's new signature counter.
+    pub fn verify_authentication(
+        &self,
+        challenge: &WebAuthnChallenge,
+        passkey: &Passkey,
+        credential: &AuthenticationCredential,
+        require_user_verification: bool,
+    ) -> Result<u32, WebAuthnError> {
+        if credential.id != passkey.credential_id.as_ref() {
+            return Err(WebAuthnError::CredentialIdMismatch);
+        }
+
+        let client_data_json = decode(&credential.response.client_data_json)?;
+        self.check_client_data(
+            &client_data_json,
+            WebAuthnCeremony::Authentication,
+            challenge,
+        )?;
+
+        let raw_auth_data = decode(&credential.response.authenticator_data)?;
+        let auth_data = self.check_authenticator_data(&raw_auth_data, require_user_verification)?;
+
+        // The signature covers the authenticator data followed by the hash of
+        // the client data.
+        let key = VerifyingKey::from_sec1_bytes(&passkey.public_key)
+            .map_err(|_| WebAuthnError::UnsupportedKey)?;
+        let signature = Signature::from_der(&decode(&credential.response.signature)?)
+            .map_err(|_| WebAuthnError::InvalidSignature)?;
+        let mut signed = raw_auth_data;
+        signed.extend_from_slice(&Sha256::digest(&client_data_json));
+        key.verify(&signed, &signature)
+            .map_err(|_| WebAuthnError::InvalidSignature)?;
+
+        // Authenticators that keep a counter must increase it every time. One
+        // that goes backwards suggests the key was cloned.
+        let counter_in_use = auth_data.sign_count != 0 || passkey.sign_count != 0;
+        if counter_in_use && auth_data.sign_count <= passkey.sign_count {
+            return Err(WebAuthnError::SignCountNotIncreased);
+        }
+
+        Ok(auth_data.sign_count)
+    }
+
+    fn check_client_data(
+        &self,
+        client_data_json: &[u8],
+        ceremony: WebAuthnCeremony,
+        challenge: &WebAuthnChallenge,
+    ) -> Result<(), WebAuthnError> {
+        let client_data: ClientData =
+            serde_json::from_slice(client_data_json).map_err(|_| WebAuthnError::InvalidEncoding)?;
+
+        if client_data.ceremony != ceremony.client_data_type() {
+            return Err(WebAuthnError::WrongCeremony);
+        }
+        if client_data.challenge != challenge.as_ref() {
+            return Err(WebAuthnError::ChallengeMismatch);
+        }
+        if client_data.origin != self.origin {
+            return Err(WebAuthnError::OriginMismatch);
+        }
+
+        Ok(())
+    }
+
+    fn check_authenticator_data(
+        &self,
+        bytes: &[u8],
+        require_user_verification: bool,
+    ) -> Result<AuthenticatorData, WebAuthnError> {
+        let auth_data = parse_authenticator_data(bytes)?;
+
+        if auth_data.rp_id_hash[..] != Sha256::digest(self.id.as_bytes())[..] {
+            return Err(WebAuthnError::RpIdMismatch);
+        }
+        if auth_data.flags & FLAG_USER_PRESENT == 0 {
+            return Err(WebAuthnError::UserNotPresent);
+        }
+        if require_user_verification && auth_data.flags & FLAG_USER_VERIFIED == 0 {
+            return Err(WebAuthnError::UserNotVerified);
+        }
+
+        Ok(auth_data)
+    }
+}
+
+fn client_data_challenge(client_data_json: &str) -> Result<String, WebAuthnError> {
+    let client_data: ClientData = serde_json::from_slice(&decode(client_data_json)?)
+        .map_err(|_| WebAuthnError::InvalidEncoding)?;
+    Ok(client_data.challenge)
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, WebAuthnError> {
+    URL_SAFE_NO_PAD
+        .decode(value)
+        .map_err(|_| WebAuthnError::InvalidEncoding)
+}
+
+// The attestation object is a CBOR map of `fmt`, `attStmt` and `authData`
+fn attestation_auth_data(attestation_object: &[u8]) -> Result<Vec<u8>, WebAuthnError> {
+    let value: Value =
+        ciborium::from_reader(attestation_object).map_err(|_| WebAuthnError::InvalidEncoding)?;
+
+    value
+        .as_map()
+        .and_then(|map| {
+            map.iter()
+                .find(|(key, _)| key.as_text() == Some("authData"))
+        })
+        .and_then(|(_, value)| value.as_bytes())
+        .cloned()
+        .ok_or(WebAuthnError::InvalidEncoding)
+}
+
+// Layout: rpIdHash (32) | flags (1) | signCount (4, big endian), then when
+// registering: aaguid (16) | credentialIdLength (2) | credentialId | COSE key
+fn parse_authenticator_data(bytes: &[u8]) -> Result<AuthenticatorData, WebAuthnError> {
+    if bytes.len() < 37 {
+        return Err(WebAuthnError::InvalidEncoding);
+    }
+
+    let mut rp_id_hash = [0u8; 32];
+    rp_id_hash.copy_from_slice(&bytes[..32]);
+    let flags = bytes[32];
+    let sign_count = u32::from_be_bytes([bytes[33], bytes[34], bytes[35], bytes[36]]);
+
+    let credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+        let rest = bytes.get(37 + 16..).ok_or(WebAuthnError::InvalidEncoding)?;
+        let id_len = match rest {
+            [high, low, ..] => u16::from_be_bytes([*high, *low]) as usize,
+            _ => return Err(WebAuthnError::InvalidEncoding),
+        };
+        let id = rest
+            .get(2..2 + id_len)
+            .ok_or(WebAuthnError::InvalidEncoding)?;
+        let cose_key = rest
+            .get(2 + id_len..)
+            .ok_or(WebAuthnError::InvalidEncoding)?;
+
+        Some((CredentialId::from_bytes(id), es256_public_key(cose_key)?))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash,
+        flags,
+        sign_count,
+        credential,
+    })
+}
+
+// Turn a COSE EC2 key into a SEC1 point, rejecting anything but ES256 on P-256
+fn es256_public_key(cose_key: &[u8]) -> Result<Vec<u8>, WebAuthnError> {
+    let value: Value =
+        ciborium::from_reader(cose_key).map_err(|_| WebAuthnError::InvalidEncoding)?;
+    let map = value.as_map().ok_or(WebAuthnError::InvalidEncoding)?;
+
+    let get = |label: i64| {
+        map.iter()
+            .find(|(key, _)| key.as_integer() == Some(label.into()))
+            .map(|(_, value)| value)
+    };
+    let int = |label: i64| {
+        get(label)
+            .and_then(Value::as_integer)
+            .and_then(|x| i64::try_from(x).ok())
+    };
+
+    // kty 2 is EC2, crv 1 is P-256
+    if int(1) != Some(2) || int(3) != Some(COSE_ALG_ES256) || int(-1) != Some(1) {
+        return Err(WebAuthnError::UnsupportedKey);
+    }
+
+    let x = get(-2).and_then(Value::as_bytes);
+    let y = get(-3).and_then(Value::as_bytes);
+    let (x, y) = match (x, y) {
+        (Some(x), Some(y)) if x.len() == 32 && y.len() == 32 => (x, y),
+        _ => return Err(WebAuthnError::UnsupportedKey),
+    };
+
+    let mut point = Vec::with_capacity(65);
+    point.push(0x04);
+    point.extend_from_slice(x);
+    point.extend_from_slice(y);
+
+    VerifyingKey::from_sec1_bytes(&point).map_err(|_| WebAuthnError::UnsupportedKey)?;
+    Ok(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p256::ecdsa::{signature::Signer, SigningKey};
+
+    fn rp() -> RelyingParty {
+        RelyingParty {
+            id: "example.com".to_owned(),
+            name: "Example".to_owned(),
+            origin: "https://example.com".to_owned(),
+        }
+    }
+
+    fn client_data(ceremony: &str, challenge: &WebAuthnChallenge) -> String {
+        let json = serde_json::json!({
+            "type": ceremony,
+            "challenge": challenge.as_ref(),
+            "origin": "https://example.com",
+        });
+        URL_SAFE_NO_PAD.encode(json.to_string())
+    }
+
+    fn auth_data(flags: u8, sign_count: u32) -> Vec<u8> {
+        let mut data = Sha256::digest(b"example.com").to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        data
+    }
+
+    fn register(
+        key: &SigningKey,
+        challenge: &WebAuthnChallenge,
+        flags: u8,
+    ) -> RegistrationCredential {
+        let point = key.verifying_key().to_encoded_point(false);
+        let cose_key = Value::Map(vec![
+            (1.into(), 2.into()),
+            (3.into(), COSE_ALG_ES256.into()),
+            ((-1).into(), 1.into()),
+            ((-2).into(), Value::Bytes(point.x().unwrap().to_vec())),
+            ((-3).into(), Value::Bytes(point.y().unwrap().to_vec())),
+        ]);
+
+        let credential_id = [7u8; 16];
+        let mut data = auth_data(flags | FLAG_ATTESTED_CREDENTIAL_DATA, 0);
+        data.extend_from_slice(&[0u8; 16]);
+        data.extend_from_slice(&(credential_id.len() as u16).to_be_bytes());
+        data.extend_from_slice(&credential_id);
+        ciborium::into_writer(&cose_key, &mut data).unwrap();
+
+        let attestation_object = Value::Map(vec![
+            ("fmt".into(), "none".into()),
+            ("attStmt".into(), Value::Map(vec![])),
+            ("authData".into(), Value::Bytes(data)),
+        ]);
+        let mut attestation_bytes = Vec::new();
+        ciborium::into_writer(&attestation_object, &mut attestation_bytes).unwrap();
+
+        RegistrationCredential {
+            id: URL_SAFE_NO_PAD.encode(credential_id),
+            response: AttestationResponse {
+                client_data_json: client_data("webauthn.create", challenge),
+                attestation_object: URL_SAFE_NO_PAD.encode(attestation_bytes),
+            },
+        }
+    }
+
+    fn authenticate(
+        key: &SigningKey,
+        passkey: &Passkey,
+        challenge: &WebAuthnChallenge,
+        flags: u8,
+        sign_count: u32,
+    ) -> AuthenticationCredential {
+        let client_data_json = client_data("webauthn.get", challenge);
+        let data = auth_data(flags, sign_count);
+
+        let mut signed = data.clone();
+        signed.extend_from_slice(&Sha256::digest(decode(&client_data_json).unwrap()));
+        let signature: Signature = key.sign(&signed);
+
+        AuthenticationCredential {
+            id: passkey.credential_id.as_ref().to_owned(),
+            response: AssertionResponse {
+                client_data_json,
+                authenticator_data: URL_SAFE_NO_PAD.encode(data),
+                signature: URL_SAFE_NO_PAD.encode(signature.to_der().as_bytes()),
+            },
+        }
+    }
+
+    fn registered(key: &SigningKey) -> Passkey {
+        let challenge = WebAuthnChallenge::default();
+        let credential = register(key, &challenge, FLAG_USER_PRESENT);
+        rp().verify_registration(&challenge, &credential, false)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_registration_returns_the_public_key() {
+        let key = SigningKey::random(&mut OsRng);
+        let passkey = registered(&key);
+
+        assert_eq!(
+            passkey.public_key,
+            key.verifying_key().to_encoded_point(false).as_bytes()
+        );
+        assert_eq!(
+            passkey.credential_id.as_ref(),
+            URL_SAFE_NO_PAD.encode([7u8; 16])
+        );
+    }
+
+    #[test]
+    fn test_credentials_tell_which_challenge_they_answer() {
+        let key = SigningKey::random(&mut OsRng);
+        let challenge = WebAuthnChallenge::default();
+        let credential = register(&key, &challenge, FLAG_USER_PRESENT);
+        assert_eq!(credential.challenge(), Ok(challenge.as_ref().to_owned()));
+
+        let passkey = registered(&key);
+        let credential = authenticate(&key, &passkey, &challenge, FLAG_USER_PRESENT, 1);
+        assert_eq!(credential.challenge(), Ok(challenge.as_ref().to_owned()));
+    }
+
+    #[test]
+    fn test_registration_checks_challenge_and_origin() {
+        let key = SigningKey::random(&mut OsRng);
+        let challenge = WebAuthnChallenge::default();
+        let credential = register(&key, &challenge, FLAG_USER_PRESENT);
+
+        assert_eq!(
+            rp().verify_registration(&WebAuthnChallenge::default(), &credential, false),
+            Err(WebAuthnError::ChallengeMismatch)
+        );
+
+        let other_origin = RelyingParty {
+            origin: "https://evil.example".to_owned(),
+            ..rp()
+        };
+        assert_eq!(
+            other_origin.verify_registration(&challenge, &credential, false),
+            Err(WebAuthnError::OriginMismatch)
+        );
+
+        let other_rp_id = RelyingParty {
+            id: "evil.example".to_owned(),
+            ..rp()
+        };
+        assert_eq!(
+            other_rp_id.verify_registration(&challenge, &credential, false),
+            Err(WebAuthnError::RpIdMismatch)
+        );
+    }
+
+    #[test]
+    fn test_user_verification_is_enforced_when_required() {
+        let key = SigningKey::random(&mut OsRng);
+        let challenge = WebAuthnChallenge::default();
+        let credential = register(&key, &challenge, FLAG_USER_PRESENT);
+
+        assert_eq!(
+            rp().verify_registration(&challenge, &credential, true),
+            Err(WebAuthnError::UserNotVerified)
+        );
+
+        let passkey = registered(&key);
+        let credential = authenticate(&key, &passkey, &challenge, FLAG_USER_PRESENT, 1);
+        assert_eq!(
+            rp().verify_authentication(&challenge, &passkey, &credential, true),
+            Err(WebAuthnError::UserNotVerified)
+        );
+    }
+
+    #[test]
+    fn test_authentication_accepts_a_valid_signature() {
+        let key = SigningKey::random(&mut OsRng);
+        let passkey = registered(&key);
+        let challenge = WebAuthnChallenge::default();
+        let flags = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+        let credential = authenticate(&key, &passkey, &challenge, flags, 1);
+
+        assert_eq!(
+            rp().verify_authentication(&challenge, &passkey, &credential, true),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn test_authentication_rejects_another_key() {
+        let passkey = registered(&SigningKey::random(&mut OsRng));
+        let challenge = WebAuthnChallenge::default();
+        let other_key = SigningKey::random(&mut OsRng);
+        let credential = authenticate(&other_key, &passkey, &challenge, FLAG_USER_PRESENT, 1);
+
+        assert_eq!(
+            rp().verify_authentication(&challenge, &passkey, &credential, false),
+            Err(WebAuthnError::InvalidSignature)
+        );
+    }
+
+    #[test]
+    fn test_authentication_rejects_a_registration_response() {
+        let key = SigningKey::random(&mut OsRng);
+        let passkey = registered(&key);
+        let challenge = WebAuthnChallenge::default();
+        let mut credential = authenticate(&key, &passkey, &challenge, FLAG_USER_PRESENT, 1);
+        credential.response.client_data_json = client_data("webauthn.create", &challenge);
+
+        assert_eq!(
+            rp().verify_authentication(&challenge, &passkey, &credential, false),
+            Err(WebAuthnError::WrongCeremony)
+        );
+    }
+
+    #[test]
+    fn test_sign_count_must_increase_once_used() {
+        let key = SigningKey::random(&mut OsRng);
+        let mut passkey = registered(&key);
+        let challenge = WebAuthnChallenge::default();
+
+        // Authenticators without a counter always report 0
+        let credential = authenticate(&key, &passkey, &challenge, FLAG_USER_PRESENT, 0);
+        assert_eq!(
+            rp().verify_authentication(&challenge, &passkey, &credential, false),
+            Ok(0)
+        );
+
+        passkey.sign_count = 5;
+        let credential = authenticate(&key, &passkey, &challenge, FLAG_USER_PRESENT, 5);
+        assert_eq!(
+            rp().verify_authentication(&challenge, &passkey, &credential, false),
+            Err(WebAuthnError::SignCountNotIncreased)
+        );
+    }
+
+    #[test]
+    fn test_credential_id_parse() {
+        assert!(CredentialId::parse(URL_SAFE_NO_PAD.encode([1u8; 16])).is_ok());
+        assert!(CredentialId::parse(String::new()).is_err());
+        assert!(CredentialId::parse("not base64!".to_owned()).is_err());
+    }
+}
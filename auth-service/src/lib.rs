@@ -1,16 +1,22 @@
 use axum::{
-    http::{Method, StatusCode},
+    extract::connect_info::{ConnectInfo, IntoMakeServiceWithConnectInfo},
+    http::{header, Method, StatusCode},
+    middleware::AddExtension,
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{delete, get, post, put},
     serve::Serve,
     Json, Router,
 };
 use domain::AuthAPIError;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use std::{error::Error, net::SocketAddr};
 use tower_http::{cors::CorsLayer, services::ServeDir};
 
 use app_state::AppState;
+use utils::{
+    constants::RATE_LIMITS,
+    rate_limit::{RateLimitKey, RateLimitLayer, RateLimits},
+};
 
 pub mod app_state;
 pub mod domain;
@@ -20,20 +26,35 @@ pub mod utils;
 
 // This struct encapsulates our application-related logic.
 pub struct Application {
-    server: Serve<Router, Router>,
+    // Connection info gives handlers the client's address
+    server: Serve<
+        IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+        AddExtension<Router, ConnectInfo<SocketAddr>>,
+    >,
     // address is exposed as a public field
     // so we have access to it in tests.
     pub address: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Default, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
+    // Unix timestamp in seconds a locked account can log in again at
+    #[serde(
+        rename = "lockedUntil",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub locked_until: Option<i64>,
+    // Why the account was disabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
-impl IntoResponse for AuthAPIError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
+impl AuthAPIError {
+    // The status and message of the response the error turns into
+    pub fn status_and_message(&self) -> (StatusCode, &'static str) {
+        match self {
             AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
             AuthAPIError::IncorrectCredentials => {
@@ -44,16 +65,60 @@ impl IntoResponse for AuthAPIError {
             }
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Missing jwt token"),
             AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+            AuthAPIError::TooManyAttempts { .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, "Too many login attempts")
+            }
+            AuthAPIError::EmailNotVerified => (StatusCode::FORBIDDEN, "Email not verified"),
+            AuthAPIError::SessionNotFound => (StatusCode::NOT_FOUND, "Session not found"),
+            AuthAPIError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden"),
+            AuthAPIError::UserNotFound => (StatusCode::NOT_FOUND, "User not found"),
+            AuthAPIError::AccountLocked { .. } => (StatusCode::FORBIDDEN, "Account locked"),
+            AuthAPIError::AccountDisabled { .. } => (StatusCode::FORBIDDEN, "Account disabled"),
+        }
+    }
+}
+
+impl IntoResponse for AuthAPIError {
+    fn into_response(self) -> Response {
+        let retry_after_seconds = match self {
+            AuthAPIError::TooManyAttempts {
+                retry_after_seconds,
+            } => Some(retry_after_seconds),
+            _ => None,
+        };
+
+        let (locked_until, reason) = match &self {
+            AuthAPIError::AccountLocked { until } => (Some(*until), None),
+            AuthAPIError::AccountDisabled { reason } => (None, Some(reason.clone())),
+            _ => (None, None),
         };
+
+        let (status, error_message) = self.status_and_message();
         let body = Json(ErrorResponse {
             error: error_message.to_string(),
+            locked_until,
+            reason,
         });
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if let Some(seconds) = retry_after_seconds {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, seconds.into());
+        }
+        response
     }
 }
 
 impl Application {
     pub async fn build(app_state: AppState, address: &str) -> Result<Self, Box<dyn Error>> {
+        Self::build_with_rate_limits(app_state, address, &RATE_LIMITS).await
+    }
+
+    pub async fn build_with_rate_limits(
+        app_state: AppState,
+        address: &str,
+        rate_limits: &RateLimits,
+    ) -> Result<Self, Box<dyn Error>> {
         let allowed_origins = [
             "http://localhost:8000".parse()?,
             // TODO: Replace [YOUR_DROPLET_IP] with your Droplet IP address
@@ -61,25 +126,137 @@ impl Application {
         ];
 
         let cors = CorsLayer::new()
-            // Allow GET and POST requests
-            .allow_methods([Method::GET, Method::POST])
+            // Allow GET, POST, PUT and DELETE requests
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
             // Allow cookies to be included in requests
             .allow_credentials(true)
             .allow_origin(allowed_origins);
 
+        // Routes sharing a layer share its buckets. Routes behind a jwt are
+        // limited per user, the rest per client address.
+        let signup_limit = RateLimitLayer::new(rate_limits.signup, RateLimitKey::Ip);
+        let login_limit = RateLimitLayer::new(rate_limits.login, RateLimitKey::Ip);
+        let verify_2fa_limit = RateLimitLayer::new(rate_limits.verify_2fa, RateLimitKey::Ip);
+        let verify_token_limit = RateLimitLayer::new(rate_limits.verify_token, RateLimitKey::Ip);
+        let session_limit = RateLimitLayer::new(rate_limits.session, RateLimitKey::Ip);
+        let account_limit = RateLimitLayer::new(rate_limits.account, RateLimitKey::Subject);
+
         let router = Router::new()
             .nest_service("/", ServeDir::new("assets"))
-            .route("/signup", post(routes::signup))
-            .route("/login", post(routes::login))
-            .route("/verify_2fa", post(routes::verify_2fa))
-            .route("/verify_token", post(routes::verify_token))
-            .route("/logout", post(routes::logout))
+            .route("/signup", post(routes::signup).layer(signup_limit.clone()))
+            .route(
+                "/verify-email",
+                get(routes::verify_email).layer(signup_limit.clone()),
+            )
+            .route(
+                "/verify-email/resend",
+                post(routes::resend_verification_email).layer(signup_limit.clone()),
+            )
+            .route(
+                "/password-reset/request",
+                post(routes::request_password_reset).layer(signup_limit.clone()),
+            )
+            .route(
+                "/password-reset/confirm",
+                post(routes::confirm_password_reset).layer(signup_limit),
+            )
+            .route("/login", post(routes::login).layer(login_limit.clone()))
+            .route(
+                "/webauthn/login/start",
+                post(routes::start_passkey_login).layer(login_limit.clone()),
+            )
+            .route(
+                "/webauthn/login/finish",
+                post(routes::finish_passkey_login).layer(login_limit),
+            )
+            .route(
+                "/verify_2fa",
+                post(routes::verify_2fa).layer(verify_2fa_limit),
+            )
+            .route(
+                "/verify_token",
+                post(routes::verify_token).layer(verify_token_limit),
+            )
+            .route("/logout", post(routes::logout).layer(session_limit.clone()))
+            .route("/refresh", post(routes::refresh).layer(session_limit))
+            .route(
+                "/totp/enroll",
+                post(routes::enroll_totp).layer(account_limit.clone()),
+            )
+            .route(
+                "/totp/confirm",
+                post(routes::confirm_totp).layer(account_limit.clone()),
+            )
+            .route(
+                "/webauthn/register/start",
+                post(routes::start_passkey_registration).layer(account_limit.clone()),
+            )
+            .route(
+                "/webauthn/register/finish",
+                post(routes::finish_passkey_registration).layer(account_limit.clone()),
+            )
+            .route(
+                "/recovery_codes/regenerate",
+                post(routes::regenerate_recovery_codes).layer(account_limit.clone()),
+            )
+            .route(
+                "/change-password",
+                post(routes::change_password).layer(account_limit.clone()),
+            )
+            .route(
+                "/account",
+                delete(routes::delete_account).layer(account_limit.clone()),
+            )
+            .route(
+                "/sessions",
+                get(routes::list_sessions)
+                    .delete(routes::revoke_all_sessions)
+                    .layer(account_limit.clone()),
+            )
+            .route(
+                "/sessions/:id",
+                delete(routes::revoke_session).layer(account_limit.clone()),
+            )
+            .route(
+                "/admin/users",
+                get(routes::list_users).layer(account_limit.clone()),
+            )
+            .route(
+                "/admin/users/:email",
+                get(routes::get_user)
+                    .delete(routes::delete_user)
+                    .layer(account_limit.clone()),
+            )
+            .route(
+                "/admin/users/:email/lock",
+                post(routes::lock_user).layer(account_limit.clone()),
+            )
+            .route(
+                "/admin/users/:email/disable",
+                post(routes::disable_user).layer(account_limit.clone()),
+            )
+            .route(
+                "/admin/users/:email/activate",
+                post(routes::activate_user).layer(account_limit.clone()),
+            )
+            .route(
+                "/admin/users/:email/logout",
+                post(routes::logout_user).layer(account_limit.clone()),
+            )
+            .route(
+                "/admin/users/:email/2fa",
+                put(routes::set_user_two_fa).layer(account_limit),
+            )
+            .route("/.well-known/jwks.json", get(routes::jwks))
             .with_state(app_state)
             .layer(cors);
 
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        let server = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        );
 
         Ok(Self { server, address })
     }
@@ -1,32 +1,158 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 use tokio::sync::RwLock;
 
 use auth_service::{
     app_state::{
-        AppState, BannedtokenStoreType, EmailClientType, TwoFACodeStoreType, UserStoreType,
+        AppState, AuditSinkType, BannedtokenStoreType, EmailClientType, EmailTokenStoreType,
+        LoginThrottleStoreType, PasskeyStoreType, RecoveryCodeStoreType, RefreshTokenStoreType,
+        SessionStoreType, TotpStoreType, TwoFACodeStoreType, UserStoreType,
     },
+    domain::{Role, UserStoreError},
+    services::database::get_database_pool,
+    services::file_audit_sink::FileAuditSink,
+    services::hashmap_email_token_store::HashmapEmailTokenStore,
+    services::hashmap_login_throttle_store::HashmapLoginThrottleStore,
+    services::hashmap_passkey_store::HashmapPasskeyStore,
+    services::hashmap_recovery_code_store::HashmapRecoveryCodeStore,
+    services::hashmap_refresh_token_store::HashmapRefreshTokenStore,
+    services::hashmap_session_store::HashmapSessionStore,
+    services::hashmap_totp_store::HashmapTotpStore,
     services::hashmap_two_fa_code_store::HashmapTwoFACodeStore,
     services::hashmap_user_store::HashmapUserStore,
+    services::hashmap_webauthn_challenge_store::HashmapWebAuthnChallengeStore,
     services::hashset_banned_token_store::HashsetBannedTokenStore,
     services::mock_email_client::MockEmailClient,
-    utils::constants::prod,
+    services::smtp_email_client::SmtpEmailClient,
+    services::sql_banned_token_store::SqlBannedTokenStore,
+    services::sql_email_token_store::SqlEmailTokenStore,
+    services::sql_login_throttle_store::SqlLoginThrottleStore,
+    services::sql_passkey_store::SqlPasskeyStore,
+    services::sql_recovery_code_store::SqlRecoveryCodeStore,
+    services::sql_refresh_token_store::SqlRefreshTokenStore,
+    services::sql_session_store::SqlSessionStore,
+    services::sql_totp_store::SqlTotpStore,
+    services::sql_user_store::SqlUserStore,
+    utils::constants::{
+        prod, ADMIN_EMAILS, AUDIT_LOG_PATH, BANNED_TOKEN_PRUNE_INTERVAL_SECONDS, DATABASE_URL,
+        LOGIN_THROTTLE_PRUNE_INTERVAL_SECONDS, REFRESH_TOKEN_PRUNE_INTERVAL_SECONDS,
+        SESSION_PRUNE_INTERVAL_SECONDS, SMTP_SETTINGS,
+    },
+    utils::tasks::{
+        spawn_banned_token_pruner, spawn_login_throttle_pruner, spawn_refresh_token_pruner,
+        spawn_session_pruner,
+    },
     Application,
 };
 
+#[cfg(unix)]
+use auth_service::utils::tasks::spawn_jwt_keyring_reloader;
+
 #[tokio::main]
 async fn main() {
-    let user_store: UserStoreType = Arc::new(RwLock::new(HashmapUserStore::default()));
-    let banned_token_store: BannedtokenStoreType =
-        Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+    let (
+        user_store,
+        banned_token_store,
+        totp_store,
+        recovery_code_store,
+        passkey_store,
+        login_throttle_store,
+        email_token_store,
+        refresh_token_store,
+        session_store,
+    ): (
+        UserStoreType,
+        BannedtokenStoreType,
+        TotpStoreType,
+        RecoveryCodeStoreType,
+        PasskeyStoreType,
+        LoginThrottleStoreType,
+        EmailTokenStoreType,
+        RefreshTokenStoreType,
+        SessionStoreType,
+    ) = match DATABASE_URL.as_deref() {
+        Some(database_url) => {
+            let pool = get_database_pool(database_url)
+                .await
+                .expect("Failed to connect to database");
+            (
+                Arc::new(RwLock::new(SqlUserStore::new(pool.clone()))),
+                Arc::new(RwLock::new(SqlBannedTokenStore::new(pool.clone()))),
+                Arc::new(RwLock::new(SqlTotpStore::new(pool.clone()))),
+                Arc::new(RwLock::new(SqlRecoveryCodeStore::new(pool.clone()))),
+                Arc::new(RwLock::new(SqlPasskeyStore::new(pool.clone()))),
+                Arc::new(RwLock::new(SqlLoginThrottleStore::new(pool.clone()))),
+                Arc::new(RwLock::new(SqlEmailTokenStore::new(pool.clone()))),
+                Arc::new(RwLock::new(SqlRefreshTokenStore::new(pool.clone()))),
+                Arc::new(RwLock::new(SqlSessionStore::new(pool))),
+            )
+        }
+        None => (
+            Arc::new(RwLock::new(HashmapUserStore::default())),
+            Arc::new(RwLock::new(HashsetBannedTokenStore::default())),
+            Arc::new(RwLock::new(HashmapTotpStore::default())),
+            Arc::new(RwLock::new(HashmapRecoveryCodeStore::default())),
+            Arc::new(RwLock::new(HashmapPasskeyStore::default())),
+            Arc::new(RwLock::new(HashmapLoginThrottleStore::default())),
+            Arc::new(RwLock::new(HashmapEmailTokenStore::default())),
+            Arc::new(RwLock::new(HashmapRefreshTokenStore::default())),
+            Arc::new(RwLock::new(HashmapSessionStore::default())),
+        ),
+    };
+    // Users who signed up before being listed in ADMIN_EMAILS
+    for email in ADMIN_EMAILS.iter() {
+        match user_store.write().await.set_role(email, Role::Admin).await {
+            Ok(()) | Err(UserStoreError::UserNotFound) => {}
+            Err(e) => panic!("Failed to promote admin: {:?}", e),
+        }
+    }
+    spawn_banned_token_pruner(
+        banned_token_store.clone(),
+        Duration::from_secs(BANNED_TOKEN_PRUNE_INTERVAL_SECONDS),
+    );
+    spawn_refresh_token_pruner(
+        refresh_token_store.clone(),
+        Duration::from_secs(REFRESH_TOKEN_PRUNE_INTERVAL_SECONDS),
+    );
+    spawn_login_throttle_pruner(
+        login_throttle_store.clone(),
+        Duration::from_secs(LOGIN_THROTTLE_PRUNE_INTERVAL_SECONDS),
+    );
+    spawn_session_pruner(
+        session_store.clone(),
+        Duration::from_secs(SESSION_PRUNE_INTERVAL_SECONDS),
+    );
+    // Elsewhere there is no SIGHUP, so a new keyring needs a restart
+    #[cfg(unix)]
+    spawn_jwt_keyring_reloader().expect("Failed to listen for SIGHUP");
     let two_fa_code_store: TwoFACodeStoreType =
         Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
-    let email_client: EmailClientType = Arc::new(MockEmailClient {});
+    let webauthn_challenge_store = Arc::new(RwLock::new(HashmapWebAuthnChallengeStore::default()));
+    let email_client: EmailClientType = match SMTP_SETTINGS.clone() {
+        Some(settings) => {
+            Arc::new(SmtpEmailClient::new(settings).expect("Failed to configure SMTP email client"))
+        }
+        None => Arc::new(MockEmailClient {}),
+    };
+    let audit_sink: AuditSinkType = Arc::new(RwLock::new(
+        FileAuditSink::open(AUDIT_LOG_PATH.as_str())
+            .await
+            .expect("Failed to open audit log"),
+    ));
 
     let app_state = AppState::new(
         user_store,
         banned_token_store,
+        refresh_token_store,
         two_fa_code_store,
+        totp_store,
+        recovery_code_store,
+        passkey_store,
+        webauthn_challenge_store,
+        login_throttle_store,
+        email_token_store,
+        session_store,
         email_client,
+        audit_sink,
     );
 
     let app = Application::build(app_state, prod::APP_ADDRESS)
@@ -0,0 +1,185 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditAction, AuthAPIError, Email, Password, ThrottleKey, TwoFACodeStoreError,
+        UserStoreError,
+    },
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{revoke_user_tokens, AuthenticatedUser, ClientInfo},
+        constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME},
+    },
+};
+
+use super::login::{forgive_attempt, start_attempt, validate_user};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteAccountRequest {
+    pub password: String,
+}
+
+// Delete the logged in user's account and everything stored about them. The
+// password is asked for again so a stolen session isn't enough.
+pub async fn delete_account(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
+    jar: CookieJar,
+    Json(request): Json<DeleteAccountRequest>,
+) -> (CookieJar, Response) {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let (jar, result) = handle_delete_account(&state, client, user, jar, request).await;
+    let action = AuditAction::DeleteAccount;
+    let response = audit(&state, action, &client_info, subject, result).await;
+    (jar, response)
+}
+
+async fn handle_delete_account(
+    state: &AppState,
+    client: SocketAddr,
+    user: AuthenticatedUser,
+    jar: CookieJar,
+    request: DeleteAccountRequest,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let result = reauthenticate(state, client, user, request).await;
+
+    let email = match result {
+        Ok(email) => email,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    if let Err(e) = delete_user_data(state, &email).await {
+        return (jar, Err(e));
+    }
+
+    let jar = jar
+        .remove(JWT_COOKIE_NAME)
+        .remove(REFRESH_TOKEN_COOKIE_NAME);
+
+    (jar, Ok(StatusCode::NO_CONTENT))
+}
+
+async fn reauthenticate(
+    state: &AppState,
+    client: SocketAddr,
+    user: AuthenticatedUser,
+    request: DeleteAccountRequest,
+) -> Result<Email, AuthAPIError> {
+    let email = Email::parse(user.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let throttle_keys = [
+        ThrottleKey::Email(email.clone()),
+        ThrottleKey::Ip(client.ip()),
+    ];
+    let attempt = start_attempt(state, &throttle_keys).await?;
+
+    let result = validate_user(state, &email, &password).await;
+
+    match result {
+        Ok(_) => {
+            forgive_attempt(state, &attempt).await?;
+            Ok(email)
+        }
+        Err(UserStoreError::AccountInactive(e)) => {
+            forgive_attempt(state, &attempt).await?;
+            Err(e.into())
+        }
+        Err(UserStoreError::UnexpectedError) => {
+            forgive_attempt(state, &attempt).await?;
+            Err(AuthAPIError::UnexpectedError)
+        }
+        Err(_) => Err(AuthAPIError::IncorrectCredentials),
+    }
+}
+
+// Remove the user and their entries from every store. They are logged out
+// everywhere first, and jwts of a user that no longer exists are rejected
+// anyway. The user goes last, so if a step fails the account still exists
+// and the deletion can be retried.
+pub(crate) async fn delete_user_data(state: &AppState, email: &Email) -> Result<(), AuthAPIError> {
+    revoke_user_tokens(
+        email,
+        &state.user_store,
+        &state.refresh_token_store,
+        &state.session_store,
+    )
+    .await
+    .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    match state
+        .two_fa_code_store
+        .write()
+        .await
+        .remove_code(email)
+        .await
+    {
+        Ok(()) | Err(TwoFACodeStoreError::EmailNotFound) => {}
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    }
+
+    state
+        .totp_store
+        .write()
+        .await
+        .delete_secrets(email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .recovery_code_store
+        .write()
+        .await
+        .delete_codes(email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .passkey_store
+        .write()
+        .await
+        .delete_passkeys(email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // Challenges expire on their own, so there is nothing to fail on here
+    let _ = state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .delete_challenges(email)
+        .await;
+
+    state
+        .email_token_store
+        .write()
+        .await
+        .delete_tokens(email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .login_throttle_store
+        .write()
+        .await
+        .clear_failures(&ThrottleKey::Email(email.clone()))
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    match state.user_store.write().await.delete_user(email).await {
+        Ok(()) | Err(UserStoreError::UserNotFound) => Ok(()),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
@@ -0,0 +1,454 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AccountStatus, AccountStatusError, AuditAction, AuthAPIError, Email, Role, TwoFAMethod,
+        User, UserStoreError,
+    },
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{revoke_user_tokens, AdminUser, ClientInfo},
+    },
+};
+
+use super::account::delete_user_data;
+
+// Page size when the request doesn't ask for one, and the most it may ask for
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 100;
+
+// Disable reasons are shown to the user, so they are kept short
+const MAX_DISABLED_REASON_LENGTH: usize = 500;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminUserResponse {
+    pub email: String,
+    pub role: Role,
+    #[serde(rename = "twoFAMethod")]
+    pub two_fa_method: String,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+    #[serde(rename = "emailVerified")]
+    pub email_verified: bool,
+    // active, locked or disabled. Locks that have run out show as active.
+    pub status: String,
+    // Unix timestamp in seconds
+    #[serde(rename = "lockedUntil")]
+    pub locked_until: Option<i64>,
+    #[serde(rename = "disabledReason")]
+    pub disabled_reason: Option<String>,
+}
+
+impl From<User> for AdminUserResponse {
+    fn from(user: User) -> Self {
+        let (status, locked_until, disabled_reason) =
+            match user.check_status(Utc::now().timestamp()) {
+                Ok(()) => ("active", None, None),
+                Err(AccountStatusError::Locked { until }) => ("locked", Some(until), None),
+                Err(AccountStatusError::Disabled { reason }) => ("disabled", None, Some(reason)),
+            };
+
+        Self {
+            email: user.email.as_ref().to_owned(),
+            role: user.role,
+            two_fa_method: user.two_fa_method.as_ref().to_owned(),
+            requires_2fa: user.two_fa_method != TwoFAMethod::None,
+            email_verified: user.email_verified,
+            status: status.to_owned(),
+            locked_until,
+            disabled_reason,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListUsersQuery {
+    #[serde(rename = "emailPrefix", default)]
+    pub email_prefix: String,
+    // The `nextCursor` of the previous page
+    pub after: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListUsersResponse {
+    pub users: Vec<AdminUserResponse>,
+    // Pass as `after` to get the next page. Null on the last page.
+    #[serde(rename = "nextCursor")]
+    pub next_cursor: Option<String>,
+}
+
+// List users ordered by email, optionally only those whose email starts with
+// `emailPrefix`
+pub async fn list_users(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    admin: AdminUser,
+    Query(query): Query<ListUsersQuery>,
+) -> Response {
+    let subject = admin_subject(&admin, None);
+    let result = handle_list_users(&state, query).await;
+    let action = AuditAction::AdminListUsers;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_list_users(
+    state: &AppState,
+    query: ListUsersQuery,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let after = query
+        .after
+        .map(Email::parse)
+        .transpose()
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    // One extra user tells whether there is another page
+    let mut users = state
+        .user_store
+        .read()
+        .await
+        .list_users(&query.email_prefix, after.as_ref(), limit + 1)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let next_cursor = if users.len() > limit {
+        users.truncate(limit);
+        users.last().map(|user| user.email.as_ref().to_owned())
+    } else {
+        None
+    };
+
+    Ok(Json(ListUsersResponse {
+        users: users.into_iter().map(AdminUserResponse::from).collect(),
+        next_cursor,
+    }))
+}
+
+pub async fn get_user(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    admin: AdminUser,
+    Path(email): Path<String>,
+) -> Response {
+    let subject = admin_subject(&admin, Some(&email));
+    let result = handle_get_user(&state, email).await;
+    let action = AuditAction::AdminGetUser;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_get_user(
+    state: &AppState,
+    email: String,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = parse_email(email)?;
+    let user = fetch_user(state, &email).await?;
+
+    Ok(Json(AdminUserResponse::from(user)))
+}
+
+// Delete the user and everything stored about them, like they can do
+// themselves
+pub async fn delete_user(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    admin: AdminUser,
+    Path(email): Path<String>,
+) -> Response {
+    let subject = admin_subject(&admin, Some(&email));
+    let result = handle_delete_user(&state, email).await;
+    let action = AuditAction::AdminDeleteUser;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_delete_user(
+    state: &AppState,
+    email: String,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = parse_email(email)?;
+    fetch_user(state, &email).await?;
+
+    delete_user_data(state, &email).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct LockUserRequest {
+    // Unix timestamp in seconds, in the future
+    #[serde(rename = "lockedUntil")]
+    pub locked_until: i64,
+}
+
+// Stop the user from logging in until `lockedUntil`. They are logged out
+// everywhere too.
+pub async fn lock_user(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    admin: AdminUser,
+    Path(email): Path<String>,
+    Json(request): Json<LockUserRequest>,
+) -> Response {
+    let subject = admin_subject(&admin, Some(&email));
+    let result = handle_lock_user(&state, email, request).await;
+    let action = AuditAction::AdminLockUser;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_lock_user(
+    state: &AppState,
+    email: String,
+    request: LockUserRequest,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = parse_email(email)?;
+    if request.locked_until <= Utc::now().timestamp() {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    deactivate(
+        state,
+        &email,
+        AccountStatus::LockedUntil(request.locked_until),
+    )
+    .await?;
+
+    Ok(Json(AdminUserResponse::from(
+        fetch_user(state, &email).await?,
+    )))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DisableUserRequest {
+    // Shown to the user when they try to log in
+    pub reason: String,
+}
+
+// Stop the user from logging in until they are activated again, e.g. because
+// the account was compromised. They are logged out everywhere too.
+pub async fn disable_user(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    admin: AdminUser,
+    Path(email): Path<String>,
+    Json(request): Json<DisableUserRequest>,
+) -> Response {
+    let subject = admin_subject(&admin, Some(&email));
+    let result = handle_disable_user(&state, email, request).await;
+    let action = AuditAction::AdminDisableUser;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_disable_user(
+    state: &AppState,
+    email: String,
+    request: DisableUserRequest,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = parse_email(email)?;
+    let reason = request.reason.trim();
+    if reason.is_empty() || reason.chars().count() > MAX_DISABLED_REASON_LENGTH {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    deactivate(state, &email, AccountStatus::Disabled(reason.to_owned())).await?;
+
+    Ok(Json(AdminUserResponse::from(
+        fetch_user(state, &email).await?,
+    )))
+}
+
+// Lift a lock or undo disabling
+pub async fn activate_user(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    admin: AdminUser,
+    Path(email): Path<String>,
+) -> Response {
+    let subject = admin_subject(&admin, Some(&email));
+    let result = handle_activate_user(&state, email).await;
+    let action = AuditAction::AdminActivateUser;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_activate_user(
+    state: &AppState,
+    email: String,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = parse_email(email)?;
+    set_status(state, &email, AccountStatus::Active).await?;
+
+    Ok(Json(AdminUserResponse::from(
+        fetch_user(state, &email).await?,
+    )))
+}
+
+// Log the user out on every device
+pub async fn logout_user(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    admin: AdminUser,
+    Path(email): Path<String>,
+) -> Response {
+    let subject = admin_subject(&admin, Some(&email));
+    let result = handle_logout_user(&state, email).await;
+    let action = AuditAction::AdminLogoutUser;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_logout_user(
+    state: &AppState,
+    email: String,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = parse_email(email)?;
+    fetch_user(state, &email).await?;
+
+    revoke_user_tokens(
+        &email,
+        &state.user_store,
+        &state.refresh_token_store,
+        &state.session_store,
+    )
+    .await
+    .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SetTwoFARequest {
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+}
+
+// Turn 2FA off, e.g. for a user who lost their authenticator and their
+// recovery codes, or require it by email. Users who already have 2FA keep
+// their method.
+pub async fn set_user_two_fa(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    admin: AdminUser,
+    Path(email): Path<String>,
+    Json(request): Json<SetTwoFARequest>,
+) -> Response {
+    let subject = admin_subject(&admin, Some(&email));
+    let result = handle_set_user_two_fa(&state, email, request).await;
+    let action = AuditAction::AdminSetUserTwoFA;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_set_user_two_fa(
+    state: &AppState,
+    email: String,
+    request: SetTwoFARequest,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = parse_email(email)?;
+    let user = fetch_user(state, &email).await?;
+
+    let two_fa_method = match (request.requires_2fa, user.two_fa_method) {
+        (true, TwoFAMethod::None) => TwoFAMethod::Email,
+        (true, method) => method,
+        (false, _) => TwoFAMethod::None,
+    };
+
+    if two_fa_method != user.two_fa_method {
+        state
+            .user_store
+            .write()
+            .await
+            .set_two_fa_method(&email, two_fa_method)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+    }
+
+    // The secrets and codes of a method that's off would only come back to
+    // life if it were turned on again
+    if two_fa_method == TwoFAMethod::None {
+        state
+            .totp_store
+            .write()
+            .await
+            .delete_secrets(&email)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+        state
+            .recovery_code_store
+            .write()
+            .await
+            .delete_codes(&email)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+    }
+
+    Ok(Json(AdminUserResponse::from(
+        fetch_user(state, &email).await?,
+    )))
+}
+
+// The admin is who made the request; the user they manage, if any, is who
+// it was about
+fn admin_subject(admin: &AdminUser, email: Option<&str>) -> AuditSubject {
+    AuditSubject {
+        email: email.map(str::to_owned),
+        actor: Some(admin.0.claims.sub.clone()),
+    }
+}
+
+fn parse_email(email: String) -> Result<Email, AuthAPIError> {
+    Email::parse(email).map_err(|_| AuthAPIError::InvalidCredentials)
+}
+
+async fn fetch_user(state: &AppState, email: &Email) -> Result<User, AuthAPIError> {
+    match state.user_store.read().await.get_user(email).await {
+        Ok(user) => Ok(user),
+        Err(UserStoreError::UserNotFound) => Err(AuthAPIError::UserNotFound),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+// Lock or disable the user, and log them out everywhere
+async fn deactivate(
+    state: &AppState,
+    email: &Email,
+    status: AccountStatus,
+) -> Result<(), AuthAPIError> {
+    set_status(state, email, status).await?;
+
+    revoke_user_tokens(
+        email,
+        &state.user_store,
+        &state.refresh_token_store,
+        &state.session_store,
+    )
+    .await
+    .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+async fn set_status(
+    state: &AppState,
+    email: &Email,
+    status: AccountStatus,
+) -> Result<(), AuthAPIError> {
+    match state
+        .user_store
+        .write()
+        .await
+        .set_status(email, status)
+        .await
+    {
+        Ok(()) => Ok(()),
+        Err(UserStoreError::UserNotFound) => Err(AuthAPIError::UserNotFound),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
@@ -0,0 +1,158 @@
+use axum::{
+    extract::{ConnectInfo, State},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditAction, AuthAPIError, Email, HashedPassword, Password, ThrottleKey, UserStoreError,
+    },
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{revoke_user_tokens, start_session, AuthenticatedUser, ClientInfo},
+        constants::EMAIL_LINK_BASE_URL,
+        emails::password_changed_email,
+    },
+};
+
+use super::login::{forgive_attempt, start_attempt, validate_user};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    pub current_password: String,
+    #[serde(rename = "newPassword")]
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordResponse {
+    pub message: String,
+}
+
+// Change the logged in user's password. Every other session is revoked and
+// this one gets fresh cookies, so the user stays logged in where they made
+// the change.
+pub async fn change_password(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
+    jar: CookieJar,
+    Json(request): Json<ChangePasswordRequest>,
+) -> (CookieJar, Response) {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let (jar, result) =
+        handle_change_password(&state, client, &client_info, user, jar, request).await;
+    let action = AuditAction::ChangePassword;
+    let response = audit(&state, action, &client_info, subject, result).await;
+    (jar, response)
+}
+
+async fn handle_change_password(
+    state: &AppState,
+    client: SocketAddr,
+    client_info: &ClientInfo,
+    user: AuthenticatedUser,
+    jar: CookieJar,
+    request: ChangePasswordRequest,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let result = update_password(state, client, user, request).await;
+
+    let email = match result {
+        Ok(email) => email,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let (auth_cookie, refresh_cookie) = match start_session(state, &email, client_info).await {
+        Ok(x) => x,
+        Err(e) => return (jar, Err(e.into())),
+    };
+
+    // The password has changed either way, so a failed notice doesn't fail
+    // the request
+    if send_password_changed_email(state, &email).await.is_err() {
+        eprintln!("failed to send password changed email");
+    }
+
+    let response = Json(ChangePasswordResponse {
+        message: "Password changed".to_owned(),
+    });
+
+    (jar.add(auth_cookie).add(refresh_cookie), Ok(response))
+}
+
+async fn update_password(
+    state: &AppState,
+    client: SocketAddr,
+    user: AuthenticatedUser,
+    request: ChangePasswordRequest,
+) -> Result<Email, AuthAPIError> {
+    let email = Email::parse(user.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let current_password =
+        Password::parse(request.current_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let new_password =
+        Password::parse(request.new_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // Wrong current passwords count towards the login throttle, so a stolen
+    // session can't be used to guess the password
+    let throttle_keys = [
+        ThrottleKey::Email(email.clone()),
+        ThrottleKey::Ip(client.ip()),
+    ];
+    let attempt = start_attempt(state, &throttle_keys).await?;
+
+    let new_password = HashedPassword::parse(new_password)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let result = validate_user(state, &email, &current_password).await;
+
+    match result {
+        Ok(_) => forgive_attempt(state, &attempt).await?,
+        Err(UserStoreError::AccountInactive(e)) => {
+            forgive_attempt(state, &attempt).await?;
+            return Err(e.into());
+        }
+        Err(_) => return Err(AuthAPIError::IncorrectCredentials),
+    }
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_password(&email, new_password)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            _ => AuthAPIError::UnexpectedError,
+        })?;
+
+    revoke_user_tokens(
+        &email,
+        &state.user_store,
+        &state.refresh_token_store,
+        &state.session_store,
+    )
+    .await
+    .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(email)
+}
+
+async fn send_password_changed_email(state: &AppState, email: &Email) -> Result<(), AuthAPIError> {
+    let link = format!("{}/", *EMAIL_LINK_BASE_URL);
+    let message = password_changed_email(&link).map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .email_client
+        .send_html_email(email, &message.subject, &message.text, &message.html)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
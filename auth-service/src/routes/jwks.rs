@@ -0,0 +1,9 @@
+use axum::{response::IntoResponse, Json};
+
+use crate::utils::auth::jwt_keyring;
+
+// Publish the public keys of the keyring so other services can verify our
+// tokens without calling back. Shared secrets are never listed.
+pub async fn jwks() -> impl IntoResponse {
+    Json(jwt_keyring().jwks())
+}
@@ -1,11 +1,26 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    extract::{ConnectInfo, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
 use axum_extra::extract::CookieJar;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, LoginAttemptId, Password, TwoFACode},
-    utils::auth::generate_auth_cookie,
+    domain::{
+        validate_user_password, AuditAction, AuthAPIError, Email, HashedPassword, LoginAttemptId,
+        Password, ThrottleKey, ThrottlePolicy, TwoFACode, TwoFAMethod, User, UserStoreError,
+    },
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{start_session, ClientInfo},
+        constants::{LOGIN_THROTTLE_EMAIL_POLICY, LOGIN_THROTTLE_IP_POLICY},
+        emails::two_fa_code_email,
+    },
 };
 
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
@@ -33,54 +48,208 @@ pub enum LoginError {
     StatusCode(StatusCode),
 }
 
+// Logins that need a second factor are audited as such; how that went is
+// audited by the route that checks it.
 pub async fn login(
     State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    client_info: ClientInfo,
     jar: CookieJar,
     Json(request): Json<LoginRequest>,
+) -> (CookieJar, Response) {
+    let subject = AuditSubject::email(&request.email);
+    let (jar, result) = handle_login(&state, client, &client_info, jar, request).await;
+    let response = audit(&state, AuditAction::Login, &client_info, subject, result).await;
+    (jar, response)
+}
+
+async fn handle_login(
+    state: &AppState,
+    client: SocketAddr,
+    client_info: &ClientInfo,
+    jar: CookieJar,
+    request: LoginRequest,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
     let email = match Email::parse(request.email.clone()) {
         Ok(x) => x,
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
-    //println!("email: {:?}", email);
 
     let password = match Password::parse(request.password.clone()) {
         Ok(x) => x,
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
-    //println!("password: {:?}", password);
-
-    let user_store = &state.user_store.read().await;
 
-    if user_store.validate_user(&email, &password).await.is_err() {
-        return (jar.clone(), Err(AuthAPIError::IncorrectCredentials));
-    }
-    //println!("user_store validate_user ok");
+    let throttle_keys = [
+        ThrottleKey::Email(email.clone()),
+        ThrottleKey::Ip(client.ip()),
+    ];
+    let attempt = match start_attempt(state, &throttle_keys).await {
+        Ok(x) => x,
+        Err(e) => return (jar, Err(e)),
+    };
 
-    //    if user_store.get_user(&email).await.is_err() {
-    //        return (jar.clone(), Err(AuthAPIError::IncorrectCredentials));
-    //    }
-    let user = match user_store.get_user(&email).await {
+    // A wrong password stays counted
+    let user = match validate_user(state, &email, &password).await {
         Ok(user) => user,
+        // The password was right, so this doesn't count as a failure
+        Err(UserStoreError::AccountInactive(e)) => {
+            if let Err(e) = forgive_attempt(state, &attempt).await {
+                return (jar, Err(e));
+            }
+            return (jar, Err(e.into()));
+        }
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
-    //println!("got user: {:?}", user);
 
-    let auth_cookie = match generate_auth_cookie(&email) {
-        Ok(x) => x,
-        Err(_) => return (jar.clone(), Err(AuthAPIError::UnexpectedError)),
+    if let Err(e) = forgive_attempt(state, &attempt).await {
+        return (jar, Err(e));
+    }
+
+    if user.password.needs_rehash() {
+        if let Err(e) = upgrade_password(state, &email, &user.password, password).await {
+            return (jar, Err(e));
+        }
+    }
+
+    // Only the account's counter is reset. Logging in to their own account
+    // mustn't let someone keep guessing other accounts from the same address.
+    if state
+        .login_throttle_store
+        .write()
+        .await
+        .clear_failures(&throttle_keys[0])
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+
+    // Checked after the password so the answer doesn't tell strangers
+    // whether an address has signed up
+    if !user.email_verified {
+        return (jar, Err(AuthAPIError::EmailNotVerified));
+    }
+
+    // The auth cookie is only handed out once every required factor has been
+    // checked. For 2FA users that happens in `verify_2fa`.
+    match user.two_fa_method {
+        TwoFAMethod::Email => handle_2fa(&user.email, state, jar).await,
+        TwoFAMethod::Totp => handle_totp(&user.email, state, jar).await,
+        TwoFAMethod::None => handle_no_2fa(&user.email, state, client_info, jar).await,
+    }
+}
+
+// Look the user up and check their password and status. The password is
+// checked once the store's read guard is gone.
+pub(crate) async fn validate_user(
+    state: &AppState,
+    email: &Email,
+    password: &Password,
+) -> Result<User, UserStoreError> {
+    let user = state.user_store.read().await.get_user(email).await;
+    validate_user_password(user, password).await
+}
+
+// Rehash a password that was hashed with outdated cost parameters. The new
+// hash is computed before taking the write lock, so other logins aren't held
+// up by it.
+async fn upgrade_password(
+    state: &AppState,
+    email: &Email,
+    old: &HashedPassword,
+    password: Password,
+) -> Result<(), AuthAPIError> {
+    let new = HashedPassword::parse(password)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .upgrade_password(email, old, new)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+fn throttle_policy(key: &ThrottleKey) -> &'static ThrottlePolicy {
+    match key {
+        ThrottleKey::Email(_) => &LOGIN_THROTTLE_EMAIL_POLICY,
+        ThrottleKey::Ip(_) => &LOGIN_THROTTLE_IP_POLICY,
+    }
+}
+
+// A login attempt, counted as a failure against each key until it turns out
+// to be right. Keeps when each key last failed before, so forgiving the
+// attempt can put that back.
+pub(crate) struct Attempt {
+    at: i64,
+    keys: Vec<(ThrottleKey, Option<i64>)>,
+}
+
+// Refuse the attempt while any of the keys is backing off or locked out,
+// otherwise count it as a failure until it turns out to be right. Checking
+// and counting under one guard means concurrent guesses can't all slip in
+// before the first of them is counted.
+pub(crate) async fn start_attempt(
+    state: &AppState,
+    keys: &[ThrottleKey],
+) -> Result<Attempt, AuthAPIError> {
+    let mut throttle_store = state.login_throttle_store.write().await;
+    let now = Utc::now().timestamp();
+
+    let mut attempt = Attempt {
+        at: now,
+        keys: Vec::with_capacity(keys.len()),
     };
-    //println!("got auth_cookie: {:?}", auth_cookie);
+    let mut retry_after_seconds = None;
+    for key in keys {
+        let failures = throttle_store
+            .get_failures(key)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+        let wait = failures.and_then(|x| throttle_policy(key).retry_after(&x, now));
+        retry_after_seconds = retry_after_seconds.max(wait);
+        attempt
+            .keys
+            .push((key.clone(), failures.map(|x| x.last_failure_at)));
+    }
 
-    let jar = jar.clone().add(auth_cookie);
+    if let Some(retry_after_seconds) = retry_after_seconds {
+        return Err(AuthAPIError::TooManyAttempts {
+            retry_after_seconds,
+        });
+    }
 
-    //(updated_jar, Ok(StatusCode::OK.into_response()))
-    match user.requires_2fa {
-        //        true => handle_2fa(updated_jar).await,
-        //        false => handle_no_2fa(&user.email, updated_jar).await,
-        true => handle_2fa(&user.email, &state, jar).await,
-        false => handle_no_2fa(&user.email, jar).await,
+    for key in keys {
+        throttle_store
+            .record_failure(key.clone(), now, throttle_policy(key).reset_after_seconds)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
     }
+
+    Ok(attempt)
+}
+
+// Take back the failure `start_attempt` counted, once the password is known
+// to be right. Otherwise each success would push the backoff of the failures
+// still counted, e.g. from others behind the same address, further out.
+pub(crate) async fn forgive_attempt(
+    state: &AppState,
+    attempt: &Attempt,
+) -> Result<(), AuthAPIError> {
+    let mut throttle_store = state.login_throttle_store.write().await;
+
+    for (key, previous_failure_at) in &attempt.keys {
+        throttle_store
+            .forgive_failure(key, attempt.at, *previous_failure_at)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+    }
+
+    Ok(())
 }
 
 async fn handle_2fa(
@@ -91,33 +260,36 @@ async fn handle_2fa(
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
-    println!(">>> entered handle_2fa");
-    let login_attempt_id = LoginAttemptId::default(); //todo!();
-    let two_fa_code = TwoFACode::default(); //todo!();
+    let login_attempt_id = LoginAttemptId::default();
+    let two_fa_code = TwoFACode::default();
 
-    let mut two_fa_code_store = state.two_fa_code_store.write().await;
-    if two_fa_code_store
-        .add_code(email.clone(), login_attempt_id.clone(), two_fa_code)
+    // The lock is released before sending, which can take a while with
+    // retries
+    if state
+        .two_fa_code_store
+        .write()
+        .await
+        .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
         .await
         .is_err()
     {
         return (jar, Err(AuthAPIError::UnexpectedError));
     };
 
-    let email_client = state.email_client.clone();
-    if email_client
-        .send_email(
-            &Email::parse("baz@example.com".to_string()).unwrap(),
-            &"2fa subject".to_string(),
-            &"2fa content".to_string(),
-        )
+    let message = match two_fa_code_email(&two_fa_code) {
+        Ok(x) => x,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    if state
+        .email_client
+        .send_html_email(email, &message.subject, &message.text, &message.html)
         .await
         .is_err()
     {
         return (jar, Err(AuthAPIError::UnexpectedError));
     };
 
-    println!("CREATING response");
     let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
         message: "2FA required".to_owned(),
         login_attempt_id: login_attempt_id.clone().to_string(),
@@ -126,13 +298,53 @@ async fn handle_2fa(
     (jar, Ok((StatusCode::PARTIAL_CONTENT, response)))
 }
 
+// Same as `handle_2fa`, except the code comes from the user's authenticator
+// app, so there is nothing to send.
+async fn handle_totp(
+    email: &Email,
+    state: &AppState,
+    jar: CookieJar,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    let login_attempt_id = LoginAttemptId::default();
+
+    if state
+        .two_fa_code_store
+        .write()
+        .await
+        .add_login_attempt(email.clone(), login_attempt_id.clone())
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    };
+
+    let response = Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
+        message: "2FA required".to_owned(),
+        login_attempt_id: login_attempt_id.to_string(),
+    }));
+
+    (jar, Ok((StatusCode::PARTIAL_CONTENT, response)))
+}
+
 // New!
 async fn handle_no_2fa(
-    _: &Email,
+    email: &Email,
+    state: &AppState,
+    client_info: &ClientInfo,
     jar: CookieJar,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
+    let (auth_cookie, refresh_cookie) = match start_session(state, email, client_info).await {
+        Ok(x) => x,
+        Err(e) => return (jar, Err(e.into())),
+    };
+
+    let jar = jar.add(auth_cookie).add(refresh_cookie);
+
     (jar, Ok((StatusCode::OK, Json(LoginResponse::RegularAuth))))
 }
@@ -1,39 +1,68 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
 use axum_extra::extract::CookieJar;
 
 use crate::{
     app_state::AppState,
-    domain::AuthAPIError,
-    utils::{auth::validate_token, constants::JWT_COOKIE_NAME},
+    domain::{AuditAction, AuthAPIError, Email, SessionId},
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{AuthenticatedUser, ClientInfo},
+        constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME},
+    },
 };
 
 pub async fn logout(
     State(state): State<AppState>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
     jar: CookieJar,
-) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
-    let cookie = match jar.get(JWT_COOKIE_NAME) {
-        Some(x) => x,
-        None => {
-            return (jar, Err(AuthAPIError::MissingToken));
-        }
-    };
-
-    let token = cookie.value().to_owned();
+) -> (CookieJar, Response) {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let (jar, result) = handle_logout(&state, user, jar).await;
+    let response = audit(&state, AuditAction::Logout, &client_info, subject, result).await;
+    (jar, response)
+}
 
-    let _ = match validate_token(&token).await {
-        Ok(x) => x,
-        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
-    };
+async fn handle_logout(
+    state: &AppState,
+    user: AuthenticatedUser,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    // Logging out ends the session along with its refresh token family
+    if let (Ok(email), Ok(session_id)) = (
+        Email::parse(user.claims.sub.clone()),
+        SessionId::parse(user.claims.jti.clone()),
+    ) {
+        let _ = state
+            .session_store
+            .write()
+            .await
+            .remove_session(&email, &session_id)
+            .await;
+        let _ = state
+            .refresh_token_store
+            .write()
+            .await
+            .revoke_session(&session_id)
+            .await;
+    }
 
-    let jar = jar.clone().remove(JWT_COOKIE_NAME);
+    let jar = jar
+        .remove(JWT_COOKIE_NAME)
+        .remove(REFRESH_TOKEN_COOKIE_NAME);
 
     let mut banned_token_store = state.banned_token_store.write().await;
-    println!("logout route token: {:?}", token);
-    let _ = banned_token_store.add_token(token.clone()).await;
-    println!(
-        "token set? {:?}",
-        banned_token_store.get_token(&token).await
-    );
+    if banned_token_store
+        .add_token(user.token, user.claims.exp)
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
 
     (jar, Ok(StatusCode::OK))
 }
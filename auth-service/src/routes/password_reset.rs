@@ -0,0 +1,213 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditAction, AuthAPIError, Email, EmailTokenId, EmailTokenPurpose, HashedPassword,
+        Password, UserStoreError,
+    },
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{
+            email_token_subject, generate_email_token, revoke_user_tokens, validate_email_token,
+            ClientInfo,
+        },
+        constants::{
+            EMAIL_LINK_BASE_URL, PASSWORD_RESET_COOLDOWN_SECONDS, PASSWORD_RESET_TTL_SECONDS,
+        },
+        emails::password_reset_email,
+    },
+};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetResponse {
+    pub message: String,
+}
+
+// Email a password reset link. The answer is the same whether or not the
+// address is valid, the account exists or an email actually went out, so it
+// can't be used to find out who has signed up.
+pub async fn request_password_reset(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    Json(request): Json<PasswordResetRequest>,
+) -> Response {
+    let subject = AuditSubject::email(&request.email);
+    let result = handle_request_password_reset(&state, request).await;
+    let action = AuditAction::RequestPasswordReset;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_request_password_reset(
+    state: &AppState,
+    request: PasswordResetRequest,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    // The rest happens after answering, so how long the answer takes doesn't
+    // tell either
+    if let Ok(email) = Email::parse(request.email) {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if reset_password_if_known(&state, &email).await.is_err() {
+                eprintln!("failed to send password reset email");
+            }
+        });
+    }
+
+    let response = Json(PasswordResetResponse {
+        message: "If the account exists, a reset link is on its way".to_owned(),
+    });
+
+    Ok((StatusCode::ACCEPTED, response))
+}
+
+async fn reset_password_if_known(state: &AppState, email: &Email) -> Result<(), AuthAPIError> {
+    let exists = match state.user_store.read().await.get_user(email).await {
+        Ok(_) => true,
+        Err(UserStoreError::UserNotFound) => false,
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    if exists && !in_cooldown(state, email).await? {
+        send_password_reset_email(state, email).await?;
+    }
+
+    Ok(())
+}
+
+async fn in_cooldown(state: &AppState, email: &Email) -> Result<bool, AuthAPIError> {
+    let sent_at = state
+        .email_token_store
+        .read()
+        .await
+        .get_sent_at(email, EmailTokenPurpose::ResetPassword)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(sent_at
+        .is_some_and(|sent_at| Utc::now().timestamp() < sent_at + PASSWORD_RESET_COOLDOWN_SECONDS))
+}
+
+async fn send_password_reset_email(state: &AppState, email: &Email) -> Result<(), AuthAPIError> {
+    let token_id = EmailTokenId::default();
+    let token = generate_email_token(
+        email,
+        EmailTokenPurpose::ResetPassword,
+        &token_id,
+        PASSWORD_RESET_TTL_SECONDS,
+    )
+    .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .email_token_store
+        .write()
+        .await
+        .add_token(
+            email.clone(),
+            EmailTokenPurpose::ResetPassword,
+            token_id,
+            Utc::now().timestamp(),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // The link opens the login page, which asks for the new password
+    let link = format!("{}/?reset_token={}", *EMAIL_LINK_BASE_URL, token);
+    let message = password_reset_email(&link).map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .email_client
+        .send_html_email(email, &message.subject, &message.text, &message.html)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PasswordResetConfirmRequest {
+    pub token: String,
+    #[serde(rename = "newPassword")]
+    pub new_password: String,
+}
+
+// Set a new password with the token from a reset link. Every session the
+// user had is revoked, and since the link proved they can read the inbox, an
+// unverified address counts as verified from now on.
+pub async fn confirm_password_reset(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    Json(request): Json<PasswordResetConfirmRequest>,
+) -> Response {
+    let subject = AuditSubject {
+        email: email_token_subject(&request.token, EmailTokenPurpose::ResetPassword),
+        actor: None,
+    };
+    let result = handle_confirm_password_reset(&state, request).await;
+    let action = AuditAction::ConfirmPasswordReset;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_confirm_password_reset(
+    state: &AppState,
+    request: PasswordResetConfirmRequest,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (email, token_id) = validate_email_token(&request.token, EmailTokenPurpose::ResetPassword)
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    // Checked before the link is used up, so a rejected password can be
+    // corrected
+    let password =
+        Password::parse(request.new_password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    state
+        .email_token_store
+        .write()
+        .await
+        .take_token(&email, EmailTokenPurpose::ResetPassword, &token_id)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let password = HashedPassword::parse(password)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    {
+        let mut user_store = state.user_store.write().await;
+
+        user_store
+            .set_password(&email, password)
+            .await
+            .map_err(|e| match e {
+                UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+                _ => AuthAPIError::UnexpectedError,
+            })?;
+
+        user_store
+            .set_email_verified(&email)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+    }
+
+    revoke_user_tokens(
+        &email,
+        &state.user_store,
+        &state.refresh_token_store,
+        &state.session_store,
+    )
+    .await
+    .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(Json(PasswordResetResponse {
+        message: "Password changed".to_owned(),
+    }))
+}
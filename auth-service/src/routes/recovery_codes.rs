@@ -0,0 +1,78 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuditAction, AuthAPIError, Email, RecoveryCode, TwoFAMethod, UserStoreError},
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{AuthenticatedUser, ClientInfo},
+        constants::RECOVERY_CODE_COUNT,
+    },
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryCodesResponse {
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
+
+// Replace the user's recovery codes with a fresh set. The old codes stop
+// working straight away.
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
+) -> Response {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let result = handle_regenerate_recovery_codes(&state, user).await;
+    let action = AuditAction::RegenerateRecoveryCodes;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_regenerate_recovery_codes(
+    state: &AppState,
+    user: AuthenticatedUser,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(user.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let two_fa_method = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user.two_fa_method,
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    // Without a second factor there is nothing to recover
+    if two_fa_method == TwoFAMethod::None {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    let recovery_codes = issue_recovery_codes(state, email).await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+// Generate a new set of recovery codes for `email` and store their hashes.
+// The plain codes are only ever returned here, to be shown to the user once.
+pub(crate) async fn issue_recovery_codes(
+    state: &AppState,
+    email: Email,
+) -> Result<Vec<String>, AuthAPIError> {
+    let codes: Vec<RecoveryCode> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| RecoveryCode::default())
+        .collect();
+
+    state
+        .recovery_code_store
+        .write()
+        .await
+        .set_codes(email, codes.iter().map(RecoveryCode::hash).collect())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(codes.iter().map(|code| code.as_ref().to_owned()).collect())
+}
@@ -0,0 +1,201 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditAction, AuthAPIError, Email, RefreshToken, RefreshTokenStoreError, SessionId,
+        SessionStoreError, UserStoreError,
+    },
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{create_refresh_cookie, generate_auth_cookie, refresh_token_expiry, ClientInfo},
+        constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME},
+    },
+};
+
+// Exchange the refresh cookie for a new JWT and a new refresh token. Both
+// stay in the refresh token's session, which must still be live.
+pub async fn refresh(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    jar: CookieJar,
+) -> (CookieJar, Response) {
+    let mut subject = AuditSubject::default();
+    let (jar, result) = handle_refresh(&state, jar, &mut subject).await;
+    let response = audit(&state, AuditAction::Refresh, &client_info, subject, result).await;
+    (jar, response)
+}
+
+// The user is only known once the refresh token has been looked up, so it's
+// filled into `subject` on the way
+async fn handle_refresh(
+    state: &AppState,
+    jar: CookieJar,
+    subject: &mut AuditSubject,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let cookie = match jar.get(REFRESH_TOKEN_COOKIE_NAME) {
+        Some(x) => x,
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+
+    let refresh_token = match RefreshToken::parse(cookie.value().to_owned()) {
+        Ok(x) => x,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let new_refresh_token = RefreshToken::default();
+
+    let result = state
+        .refresh_token_store
+        .write()
+        .await
+        .rotate_token(
+            &refresh_token,
+            new_refresh_token.clone(),
+            refresh_token_expiry(),
+        )
+        .await;
+
+    let (email, session_id) = match result {
+        Ok(x) => x,
+        Err(RefreshTokenStoreError::UnexpectedError) => {
+            return (jar, Err(AuthAPIError::UnexpectedError))
+        }
+        Err(RefreshTokenStoreError::TokenReused) => {
+            if let Err(e) = end_leaked_session(state, &refresh_token, subject).await {
+                return (jar, Err(e));
+            }
+            let jar = jar
+                .remove(JWT_COOKIE_NAME)
+                .remove(REFRESH_TOKEN_COOKIE_NAME);
+            return (jar, Err(AuthAPIError::InvalidToken));
+        }
+        Err(_) => {
+            let jar = jar
+                .remove(JWT_COOKIE_NAME)
+                .remove(REFRESH_TOKEN_COOKIE_NAME);
+            return (jar, Err(AuthAPIError::InvalidToken));
+        }
+    };
+    subject.email = Some(email.as_ref().to_owned());
+
+    // A session that was revoked or went idle takes its refresh tokens with
+    // it
+    match state
+        .session_store
+        .write()
+        .await
+        .touch_session(&email, &session_id, Utc::now().timestamp())
+        .await
+    {
+        Ok(()) => {}
+        Err(SessionStoreError::SessionNotFound) => {
+            let _ = state
+                .refresh_token_store
+                .write()
+                .await
+                .revoke_session(&session_id)
+                .await;
+            let jar = jar
+                .remove(JWT_COOKIE_NAME)
+                .remove(REFRESH_TOKEN_COOKIE_NAME);
+            return (jar, Err(AuthAPIError::InvalidToken));
+        }
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    }
+
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => {
+            let jar = jar
+                .remove(JWT_COOKIE_NAME)
+                .remove(REFRESH_TOKEN_COOKIE_NAME);
+            return (jar, Err(AuthAPIError::InvalidToken));
+        }
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    // Locked or disabled users get no new tokens, and the session ends so its
+    // refresh tokens can't be tried again
+    if let Err(e) = user.check_status(Utc::now().timestamp()) {
+        if end_session(state, &email, &session_id).await.is_err() {
+            return (jar, Err(AuthAPIError::UnexpectedError));
+        }
+        let jar = jar
+            .remove(JWT_COOKIE_NAME)
+            .remove(REFRESH_TOKEN_COOKIE_NAME);
+        return (jar, Err(e.into()));
+    }
+
+    let auth_cookie = match generate_auth_cookie(&user, &session_id) {
+        Ok(x) => x,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let jar = jar
+        .add(auth_cookie)
+        .add(create_refresh_cookie(new_refresh_token));
+
+    (jar, Ok(StatusCode::OK))
+}
+
+// A rotated refresh token was presented again, so it has leaked. Whoever holds
+// the newest one may be the thief, so the family and its session go, taking
+// the session's jwts with them.
+async fn end_leaked_session(
+    state: &AppState,
+    refresh_token: &RefreshToken,
+    subject: &mut AuditSubject,
+) -> Result<(), AuthAPIError> {
+    let (email, session_id) = match state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_family(refresh_token)
+        .await
+    {
+        Ok(x) => x,
+        // Revoked in the meantime
+        Err(RefreshTokenStoreError::TokenNotFound) => return Ok(()),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+    subject.email = Some(email.as_ref().to_owned());
+
+    end_session(state, &email, &session_id).await
+}
+
+// Revoke the session's refresh tokens and remove the session itself
+async fn end_session(
+    state: &AppState,
+    email: &Email,
+    session_id: &SessionId,
+) -> Result<(), AuthAPIError> {
+    let result = state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_session(session_id)
+        .await;
+
+    match result {
+        Ok(()) | Err(RefreshTokenStoreError::TokenNotFound) => {}
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    }
+
+    match state
+        .session_store
+        .write()
+        .await
+        .remove_session(email, session_id)
+        .await
+    {
+        Ok(()) | Err(SessionStoreError::SessionNotFound) => Ok(()),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
@@ -0,0 +1,191 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuditAction, AuthAPIError, Email, SessionId, SessionStoreError},
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{revoke_user_tokens, AuthenticatedUser, ClientInfo},
+        constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME},
+    },
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionResponse {
+    pub id: String,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    // Unix timestamps in seconds
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: i64,
+    // Whether this is the session the request was made from
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionsResponse {
+    pub sessions: Vec<SessionResponse>,
+}
+
+// List the devices the logged in user is logged in on, oldest first
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
+) -> Response {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let result = handle_list_sessions(&state, user).await;
+    let action = AuditAction::ListSessions;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_list_sessions(
+    state: &AppState,
+    user: AuthenticatedUser,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(user.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let sessions = state
+        .session_store
+        .read()
+        .await
+        .get_sessions(&email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let sessions = sessions
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: session.id.as_ref() == user.claims.jti,
+            id: session.id.as_ref().to_owned(),
+            user_agent: session.user_agent,
+            ip: session.ip.map(|ip| ip.to_string()),
+            created_at: session.created_at,
+            last_seen_at: session.last_seen_at,
+        })
+        .collect();
+
+    Ok(Json(SessionsResponse { sessions }))
+}
+
+// Log one of the user's sessions out. Its jwts stop working straight away and
+// its refresh tokens are revoked. Revoking the current session also clears
+// the cookies, like logging out.
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
+    jar: CookieJar,
+    Path(id): Path<String>,
+) -> (CookieJar, Response) {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let (jar, result) = handle_revoke_session(&state, user, jar, id).await;
+    let action = AuditAction::RevokeSession;
+    let response = audit(&state, action, &client_info, subject, result).await;
+    (jar, response)
+}
+
+async fn handle_revoke_session(
+    state: &AppState,
+    user: AuthenticatedUser,
+    jar: CookieJar,
+    id: String,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let email = match Email::parse(user.claims.sub) {
+        Ok(x) => x,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let session_id = match SessionId::parse(id) {
+        Ok(x) => x,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    // Only the owner's sessions are found, so other users' ids look unknown
+    match state
+        .session_store
+        .write()
+        .await
+        .remove_session(&email, &session_id)
+        .await
+    {
+        Ok(()) => {}
+        Err(SessionStoreError::SessionNotFound) => {
+            return (jar, Err(AuthAPIError::SessionNotFound))
+        }
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    }
+
+    if state
+        .refresh_token_store
+        .write()
+        .await
+        .revoke_session(&session_id)
+        .await
+        .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+
+    let jar = if session_id.as_ref() == user.claims.jti {
+        jar.remove(JWT_COOKIE_NAME)
+            .remove(REFRESH_TOKEN_COOKIE_NAME)
+    } else {
+        jar
+    };
+
+    (jar, Ok(StatusCode::NO_CONTENT))
+}
+
+// Log the user out on every device, this one included
+pub async fn revoke_all_sessions(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
+    jar: CookieJar,
+) -> (CookieJar, Response) {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let (jar, result) = handle_revoke_all_sessions(&state, user, jar).await;
+    let action = AuditAction::RevokeAllSessions;
+    let response = audit(&state, action, &client_info, subject, result).await;
+    (jar, response)
+}
+
+async fn handle_revoke_all_sessions(
+    state: &AppState,
+    user: AuthenticatedUser,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let email = match Email::parse(user.claims.sub) {
+        Ok(x) => x,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    if revoke_user_tokens(
+        &email,
+        &state.user_store,
+        &state.refresh_token_store,
+        &state.session_store,
+    )
+    .await
+    .is_err()
+    {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+
+    let jar = jar
+        .remove(JWT_COOKIE_NAME)
+        .remove(REFRESH_TOKEN_COOKIE_NAME);
+
+    (jar, Ok(StatusCode::NO_CONTENT))
+}
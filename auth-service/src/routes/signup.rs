@@ -1,34 +1,85 @@
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
 use serde::{Deserialize, Serialize};
 
+use super::{issue_recovery_codes, send_verification_email};
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, Password, User},
+    domain::{AuditAction, AuthAPIError, Email, HashedPassword, Password, Role, TwoFAMethod, User},
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::ClientInfo,
+        constants::ADMIN_EMAILS,
+    },
 };
 
 pub async fn signup(
     State(state): State<AppState>,
+    client_info: ClientInfo,
     Json(request): Json<SignupRequest>,
+) -> Response {
+    let subject = AuditSubject::email(&request.email);
+    let result = handle_signup(&state, request).await;
+    audit(&state, AuditAction::Signup, &client_info, subject, result).await
+}
+
+async fn handle_signup(
+    state: &AppState,
+    request: SignupRequest,
 ) -> Result<impl IntoResponse, AuthAPIError> {
     let email =
         Email::parse(request.email.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
     let password =
         Password::parse(request.password.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    let user = User::new(email, password, request.requires_2fa);
+    let password = HashedPassword::parse(password)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // Authenticator apps are set up after signup, see `enroll_totp`
+    let two_fa_method = match request.requires_2fa {
+        true => TwoFAMethod::Email,
+        false => TwoFAMethod::None,
+    };
+    let mut user = User::new(email, password, two_fa_method);
+    if ADMIN_EMAILS.contains(&user.email) {
+        user.role = Role::Admin;
+    }
+
+    let email = user.email.clone();
 
-    let mut user_store = state.user_store.write().await;
+    {
+        let mut user_store = state.user_store.write().await;
 
-    if user_store.get_user(&user.email).await.is_ok() {
-        return Err(AuthAPIError::UserAlreadyExists);
+        if user_store.get_user(&user.email).await.is_ok() {
+            return Err(AuthAPIError::UserAlreadyExists);
+        }
+
+        if user_store.add_user(user).await.is_err() {
+            return Err(AuthAPIError::UnexpectedError);
+        }
     }
 
-    if user_store.add_user(user).await.is_err() {
-        return Err(AuthAPIError::UnexpectedError);
+    // The account exists either way, so a failed email doesn't fail the
+    // signup. The user can ask for another link.
+    if send_verification_email(state, &email).await.is_err() {
+        eprintln!("failed to send verification email");
     }
 
+    // Users with 2FA get their recovery codes straight away, in case they
+    // lose access to their inbox before ever logging in.
+    let recovery_codes = match two_fa_method {
+        TwoFAMethod::None => None,
+        _ => Some(issue_recovery_codes(state, email).await?),
+    };
+
     let response = Json(SignupResponse {
         message: "User created successfully!".to_string(),
+        recovery_codes,
     });
 
     Ok((StatusCode::CREATED, response))
@@ -45,4 +96,10 @@ pub struct SignupRequest {
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct SignupResponse {
     pub message: String,
+    #[serde(
+        rename = "recoveryCodes",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub recovery_codes: Option<Vec<String>>,
 }
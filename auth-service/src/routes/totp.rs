@@ -0,0 +1,129 @@
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use qrcode::{render::svg, QrCode};
+use serde::{Deserialize, Serialize};
+
+use super::{issue_recovery_codes, RecoveryCodesResponse};
+use crate::{
+    app_state::AppState,
+    domain::{AuditAction, AuthAPIError, Email, TotpSecret, TwoFACode, TwoFAMethod},
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{AuthenticatedUser, ClientInfo},
+        constants::PRODUCT_NAME,
+    },
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpEnrollmentResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+    // The URI as a QR code for authenticator apps to scan
+    #[serde(rename = "qrCodeSvg")]
+    pub qr_code_svg: String,
+}
+
+// Start setting up an authenticator app. The new secret only replaces the
+// user's 2FA method once `confirm_totp` has seen a code generated from it.
+pub async fn enroll_totp(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
+) -> Response {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let result = handle_enroll_totp(&state, user).await;
+    let action = AuditAction::EnrollTotp;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_enroll_totp(
+    state: &AppState,
+    user: AuthenticatedUser,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(user.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let secret = TotpSecret::default();
+    let otpauth_uri = secret.otpauth_uri(&PRODUCT_NAME, &email);
+    let qr_code_svg = QrCode::new(otpauth_uri.as_bytes())
+        .map_err(|_| AuthAPIError::UnexpectedError)?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+
+    state
+        .totp_store
+        .write()
+        .await
+        .set_pending_secret(email, secret.clone())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(Json(TotpEnrollmentResponse {
+        secret: secret.as_ref().to_owned(),
+        otpauth_uri,
+        qr_code_svg,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ConfirmTotpRequest {
+    pub code: String,
+}
+
+// Switch the user to their authenticator app. They get a new set of recovery
+// codes along with it.
+pub async fn confirm_totp(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
+    Json(request): Json<ConfirmTotpRequest>,
+) -> Response {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let result = handle_confirm_totp(&state, user, request).await;
+    let action = AuditAction::ConfirmTotp;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_confirm_totp(
+    state: &AppState,
+    user: AuthenticatedUser,
+    request: ConfirmTotpRequest,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(user.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    let code = TwoFACode::parse(request.code).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    {
+        let mut totp_store = state.totp_store.write().await;
+
+        let secret = totp_store
+            .get_pending_secret(&email)
+            .await
+            .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+        let step = secret
+            .verify(&code, Utc::now().timestamp() as u64)
+            .ok_or(AuthAPIError::IncorrectCredentials)?;
+
+        totp_store
+            .confirm_secret(&email, step)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+    }
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_two_fa_method(&email, TwoFAMethod::Totp)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let recovery_codes = issue_recovery_codes(state, email).await?;
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
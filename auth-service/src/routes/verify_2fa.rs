@@ -1,6 +1,180 @@
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
 
-pub async fn verify_2fa() -> impl IntoResponse {
-    StatusCode::OK.into_response()
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditAction, AuthAPIError, Email, LoginAttemptId, RecoveryCode, RecoveryCodeStoreError,
+        TwoFACode, TwoFACodeStoreError, TwoFAMethod, UserStoreError,
+    },
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{start_session, ClientInfo},
+    },
+};
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+pub struct Verify2FARequest {
+    pub email: String,
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: String,
+    // Either the code from the user's second factor or one of their
+    // recovery codes
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: String,
+}
+
+enum SecondFactor {
+    Code(TwoFACode),
+    RecoveryCode(RecoveryCode),
+}
+
+pub async fn verify_2fa(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    jar: CookieJar,
+    Json(request): Json<Verify2FARequest>,
+) -> (CookieJar, Response) {
+    let subject = AuditSubject::email(&request.email);
+    let (jar, result) = handle_verify_2fa(&state, &client_info, jar, request).await;
+    let action = AuditAction::Verify2FA;
+    let response = audit(&state, action, &client_info, subject, result).await;
+    (jar, response)
+}
+
+async fn handle_verify_2fa(
+    state: &AppState,
+    client_info: &ClientInfo,
+    jar: CookieJar,
+    request: Verify2FARequest,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let email = match Email::parse(request.email) {
+        Ok(x) => x,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    let login_attempt_id = match LoginAttemptId::parse(request.login_attempt_id) {
+        Ok(x) => x,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    let second_factor = match TwoFACode::parse(request.two_fa_code.clone()) {
+        Ok(x) => SecondFactor::Code(x),
+        Err(_) => match RecoveryCode::parse(request.two_fa_code) {
+            Ok(x) => SecondFactor::RecoveryCode(x),
+            Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+        },
+    };
+
+    let user = match state.user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    // `start_session` checks this as well, but a recovery code must not be
+    // used up by a login that is refused anyway
+    if let Err(e) = user.check_status(Utc::now().timestamp()) {
+        return (jar, Err(e.into()));
+    }
+
+    // The store consumes the login attempt on success, so a code can't be
+    // used twice.
+    let mut recovery_code = None;
+    let result = match (second_factor, user.two_fa_method) {
+        (SecondFactor::RecoveryCode(code), _) => {
+            let code = code.hash();
+            let code_is_valid = match state
+                .recovery_code_store
+                .read()
+                .await
+                .has_code(&email, &code)
+                .await
+            {
+                Ok(x) => x,
+                Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+            };
+            recovery_code = Some(code);
+            state
+                .two_fa_code_store
+                .write()
+                .await
+                .verify_login_attempt(&email, &login_attempt_id, code_is_valid)
+                .await
+        }
+        (SecondFactor::Code(two_fa_code), TwoFAMethod::Totp) => {
+            let code_is_valid = verify_totp_code(state, &email, &two_fa_code).await;
+            state
+                .two_fa_code_store
+                .write()
+                .await
+                .verify_login_attempt(&email, &login_attempt_id, code_is_valid)
+                .await
+        }
+        (SecondFactor::Code(two_fa_code), _) => {
+            state
+                .two_fa_code_store
+                .write()
+                .await
+                .verify_code(&email, &login_attempt_id, &two_fa_code)
+                .await
+        }
+    };
+
+    match result {
+        Ok(()) => {}
+        Err(TwoFACodeStoreError::UnexpectedError) => {
+            return (jar, Err(AuthAPIError::UnexpectedError))
+        }
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    }
+
+    // Only used up once everything else checked out. Using it is the final
+    // check, in case another login took it in the meantime.
+    if let Some(code) = recovery_code {
+        match state
+            .recovery_code_store
+            .write()
+            .await
+            .use_code(&email, &code)
+            .await
+        {
+            Ok(()) => {}
+            Err(RecoveryCodeStoreError::CodeNotFound) => {
+                return (jar, Err(AuthAPIError::IncorrectCredentials))
+            }
+            Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+        }
+    }
+
+    let (auth_cookie, refresh_cookie) = match start_session(state, &email, client_info).await {
+        Ok(x) => x,
+        Err(e) => return (jar, Err(e.into())),
+    };
+
+    (jar.add(auth_cookie).add(refresh_cookie), Ok(StatusCode::OK))
+}
+
+// A TOTP code is valid if it matches the user's secret for a step that hasn't
+// been used yet. The step is used up even if the login attempt turns out to
+// be wrong, which only costs the user a 30 second wait.
+async fn verify_totp_code(state: &AppState, email: &Email, code: &TwoFACode) -> bool {
+    let mut totp_store = state.totp_store.write().await;
+
+    let secret = match totp_store.get_secret(email).await {
+        Ok(x) => x,
+        Err(_) => return false,
+    };
+
+    match secret.verify(code, Utc::now().timestamp() as u64) {
+        Some(step) => totp_store.use_step(email, step).await.is_ok(),
+        None => false,
+    }
 }
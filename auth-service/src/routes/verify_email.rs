@@ -0,0 +1,185 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuditAction, AuthAPIError, Email, EmailTokenId, EmailTokenPurpose, UserStoreError},
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{email_token_subject, generate_email_token, validate_email_token, ClientInfo},
+        constants::{
+            EMAIL_LINK_BASE_URL, EMAIL_VERIFICATION_RESEND_COOLDOWN_SECONDS,
+            EMAIL_VERIFICATION_TTL_SECONDS,
+        },
+        emails::verify_email_email,
+    },
+};
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailQuery {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyEmailResponse {
+    pub message: String,
+}
+
+// The target of the link emailed at signup. Each link works once, and only
+// the newest link sent to a user works at all.
+pub async fn verify_email(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    Query(query): Query<VerifyEmailQuery>,
+) -> Response {
+    let subject = AuditSubject {
+        email: email_token_subject(&query.token, EmailTokenPurpose::VerifyEmail),
+        actor: None,
+    };
+    let result = handle_verify_email(&state, query).await;
+    let action = AuditAction::VerifyEmail;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_verify_email(
+    state: &AppState,
+    query: VerifyEmailQuery,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (email, token_id) = validate_email_token(&query.token, EmailTokenPurpose::VerifyEmail)
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    state
+        .email_token_store
+        .write()
+        .await
+        .take_token(&email, EmailTokenPurpose::VerifyEmail, &token_id)
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_email_verified(&email)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            _ => AuthAPIError::UnexpectedError,
+        })?;
+
+    Ok(Json(VerifyEmailResponse {
+        message: "Email verified".to_owned(),
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ResendVerificationEmailRequest {
+    pub email: String,
+}
+
+// Send a new verification link, superseding the last one. Invalid, unknown
+// and already verified addresses, and asking again within the cooldown, get
+// the same answer without an email.
+pub async fn resend_verification_email(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    Json(request): Json<ResendVerificationEmailRequest>,
+) -> Response {
+    let subject = AuditSubject::email(&request.email);
+    let result = handle_resend_verification_email(&state, request).await;
+    let action = AuditAction::ResendVerificationEmail;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_resend_verification_email(
+    state: &AppState,
+    request: ResendVerificationEmailRequest,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    // Like password reset requests, the rest happens after answering, so
+    // neither the answer nor how long it takes depends on the account
+    if let Ok(email) = Email::parse(request.email) {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if resend_if_unverified(&state, &email).await.is_err() {
+                eprintln!("failed to resend verification email");
+            }
+        });
+    }
+
+    let response = Json(VerifyEmailResponse {
+        message: "If the account needs verifying, a new link is on its way".to_owned(),
+    });
+
+    Ok((StatusCode::ACCEPTED, response))
+}
+
+async fn resend_if_unverified(state: &AppState, email: &Email) -> Result<(), AuthAPIError> {
+    let needs_email = match state.user_store.read().await.get_user(email).await {
+        Ok(user) => !user.email_verified,
+        Err(UserStoreError::UserNotFound) => false,
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    if needs_email && !in_cooldown(state, email).await? {
+        send_verification_email(state, email).await?;
+    }
+
+    Ok(())
+}
+
+async fn in_cooldown(state: &AppState, email: &Email) -> Result<bool, AuthAPIError> {
+    let sent_at = state
+        .email_token_store
+        .read()
+        .await
+        .get_sent_at(email, EmailTokenPurpose::VerifyEmail)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(sent_at.is_some_and(|sent_at| {
+        Utc::now().timestamp() < sent_at + EMAIL_VERIFICATION_RESEND_COOLDOWN_SECONDS
+    }))
+}
+
+// Email `email` a new verification link. Only this link will work from now on.
+pub(crate) async fn send_verification_email(
+    state: &AppState,
+    email: &Email,
+) -> Result<(), AuthAPIError> {
+    let token_id = EmailTokenId::default();
+    let token = generate_email_token(
+        email,
+        EmailTokenPurpose::VerifyEmail,
+        &token_id,
+        EMAIL_VERIFICATION_TTL_SECONDS,
+    )
+    .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .email_token_store
+        .write()
+        .await
+        .add_token(
+            email.clone(),
+            EmailTokenPurpose::VerifyEmail,
+            token_id,
+            Utc::now().timestamp(),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let link = format!("{}/verify-email?token={}", *EMAIL_LINK_BASE_URL, token);
+    let message = verify_email_email(&link).map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .email_client
+        .send_html_email(email, &message.subject, &message.text, &message.html)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
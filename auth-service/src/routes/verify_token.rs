@@ -1,20 +1,50 @@
-use axum::{http::StatusCode, response::IntoResponse, Json};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
 
-use crate::{domain::AuthAPIError, utils::auth::validate_token};
+use crate::{
+    app_state::AppState,
+    domain::{AuditAction, AuthAPIError},
+    utils::{
+        audit::{audit_failure, AuditSubject},
+        auth::{token_subject, validate_token, ClientInfo},
+    },
+};
 
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
 pub struct VerifyTokenRequest {
     token: String,
 }
 
-pub async fn verify_token(Json(request): Json<VerifyTokenRequest>) -> impl IntoResponse {
-    println!("in verify_token: {}", request.token);
-
-    if let Err(_) = validate_token(&request.token).await {
-        println!("invalid token in verify_token: {}", request.token);
-        return Err(AuthAPIError::InvalidToken);
-    };
+// Other services call this on every request they handle, so only rejected
+// tokens are audited
+pub async fn verify_token(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    Json(request): Json<VerifyTokenRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    if let Err(e) = validate_token(
+        &request.token,
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.session_store.clone(),
+    )
+    .await
+    {
+        let error = e.into();
+        let subject = AuditSubject {
+            email: token_subject(&request.token),
+            actor: None,
+        };
+        audit_failure(
+            &state,
+            AuditAction::VerifyToken,
+            &client_info,
+            subject,
+            &error,
+        )
+        .await;
+        return Err(error);
+    }
 
     Ok(StatusCode::OK.into_response())
 }
@@ -0,0 +1,443 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditAction, AuthAPIError, AuthenticationCredential, Email, LoginAttemptId,
+        PasskeyStoreError, RegistrationCredential, TwoFACodeStoreError, WebAuthnCeremony,
+        WebAuthnChallenge, COSE_ALG_ES256,
+    },
+    utils::{
+        audit::{audit, AuditSubject},
+        auth::{start_session, AuthenticatedUser, ClientInfo},
+        constants::{WEBAUTHN_CHALLENGE_TTL_SECONDS, WEBAUTHN_RELYING_PARTY},
+    },
+};
+
+// The options below are handed to `navigator.credentials.create()` and
+// `navigator.credentials.get()` as `{ publicKey: options }`, with the
+// base64url strings decoded to ArrayBuffers.
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicKeyCredentialEntity {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RelyingPartyEntity {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserEntity {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub alg: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthenticatorSelection {
+    #[serde(rename = "residentKey")]
+    pub resident_key: String,
+    #[serde(rename = "userVerification")]
+    pub user_verification: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicKeyCredentialCreationOptions {
+    pub challenge: String,
+    pub rp: RelyingPartyEntity,
+    pub user: UserEntity,
+    #[serde(rename = "pubKeyCredParams")]
+    pub pub_key_cred_params: Vec<CredentialParameters>,
+    pub timeout: u64,
+    #[serde(rename = "excludeCredentials")]
+    pub exclude_credentials: Vec<PublicKeyCredentialEntity>,
+    #[serde(rename = "authenticatorSelection")]
+    pub authenticator_selection: AuthenticatorSelection,
+    pub attestation: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PublicKeyCredentialRequestOptions {
+    pub challenge: String,
+    #[serde(rename = "rpId")]
+    pub rp_id: String,
+    #[serde(rename = "allowCredentials")]
+    pub allow_credentials: Vec<PublicKeyCredentialEntity>,
+    pub timeout: u64,
+    #[serde(rename = "userVerification")]
+    pub user_verification: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebAuthnOptionsResponse<T> {
+    #[serde(rename = "publicKey")]
+    pub public_key: T,
+}
+
+fn public_key_entity(id: &str) -> PublicKeyCredentialEntity {
+    PublicKeyCredentialEntity {
+        kind: "public-key".to_owned(),
+        id: id.to_owned(),
+    }
+}
+
+lazy_static! {
+    static ref DUMMY_CREDENTIAL_KEY: [u8; 32] = rand::random();
+}
+
+// Made-up credential id for a user without passkeys. The same email gets the
+// same id for as long as the process runs, like a real passkey's would.
+fn dummy_credential_id(email: &Email) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(*DUMMY_CREDENTIAL_KEY);
+    hasher.update(email.as_ref().as_bytes());
+    URL_SAFE_NO_PAD.encode(hasher.finalize())
+}
+
+fn timeout_ms() -> u64 {
+    WEBAUTHN_CHALLENGE_TTL_SECONDS as u64 * 1000
+}
+
+// Start registering a passkey for the logged in user
+pub async fn start_passkey_registration(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
+) -> Response {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let result = handle_start_passkey_registration(&state, user).await;
+    let action = AuditAction::StartPasskeyRegistration;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_start_passkey_registration(
+    state: &AppState,
+    user: AuthenticatedUser,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(user.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let existing = state
+        .passkey_store
+        .read()
+        .await
+        .get_passkeys(&email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let challenge = WebAuthnChallenge::default();
+    state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .add_challenge(
+            email.clone(),
+            WebAuthnCeremony::Registration,
+            challenge.clone(),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let options = PublicKeyCredentialCreationOptions {
+        challenge: challenge.as_ref().to_owned(),
+        rp: RelyingPartyEntity {
+            id: WEBAUTHN_RELYING_PARTY.id.clone(),
+            name: WEBAUTHN_RELYING_PARTY.name.clone(),
+        },
+        // The user handle is stored on the authenticator, so it shouldn't
+        // be the email address itself.
+        user: UserEntity {
+            id: URL_SAFE_NO_PAD.encode(Sha256::digest(email.as_ref().as_bytes())),
+            name: email.as_ref().to_owned(),
+            display_name: email.as_ref().to_owned(),
+        },
+        pub_key_cred_params: vec![CredentialParameters {
+            kind: "public-key".to_owned(),
+            alg: COSE_ALG_ES256,
+        }],
+        timeout: timeout_ms(),
+        exclude_credentials: existing
+            .iter()
+            .map(|passkey| public_key_entity(passkey.credential_id.as_ref()))
+            .collect(),
+        authenticator_selection: AuthenticatorSelection {
+            resident_key: "preferred".to_owned(),
+            user_verification: "preferred".to_owned(),
+        },
+        attestation: "none".to_owned(),
+    };
+
+    Ok(Json(WebAuthnOptionsResponse {
+        public_key: options,
+    }))
+}
+
+pub async fn finish_passkey_registration(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    user: AuthenticatedUser,
+    Json(credential): Json<RegistrationCredential>,
+) -> Response {
+    let subject = AuditSubject::email(&user.claims.sub);
+    let result = handle_finish_passkey_registration(&state, user, credential).await;
+    let action = AuditAction::FinishPasskeyRegistration;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_finish_passkey_registration(
+    state: &AppState,
+    user: AuthenticatedUser,
+    credential: RegistrationCredential,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(user.claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let answered = credential
+        .challenge()
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    let challenge = state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .take_challenge(&email, WebAuthnCeremony::Registration, &answered)
+        .await
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    let passkey = WEBAUTHN_RELYING_PARTY
+        .verify_registration(&challenge, &credential, false)
+        .map_err(|_| AuthAPIError::IncorrectCredentials)?;
+
+    state
+        .passkey_store
+        .write()
+        .await
+        .add_passkey(email, passkey)
+        .await
+        .map_err(|e| match e {
+            PasskeyStoreError::CredentialAlreadyExists => AuthAPIError::InvalidCredentials,
+            _ => AuthAPIError::UnexpectedError,
+        })?;
+
+    Ok(StatusCode::CREATED)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PasskeyLoginStartRequest {
+    pub email: String,
+}
+
+// Start logging in with a passkey, either instead of a password or, after
+// `/login` asked for 2FA, instead of a 2FA code
+pub async fn start_passkey_login(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    Json(request): Json<PasskeyLoginStartRequest>,
+) -> Response {
+    let subject = AuditSubject::email(&request.email);
+    let result = handle_start_passkey_login(&state, request).await;
+    let action = AuditAction::StartPasskeyLogin;
+    audit(&state, action, &client_info, subject, result).await
+}
+
+async fn handle_start_passkey_login(
+    state: &AppState,
+    request: PasskeyLoginStartRequest,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let passkeys = state
+        .passkey_store
+        .read()
+        .await
+        .get_passkeys(&email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let challenge = WebAuthnChallenge::default();
+
+    // Users without passkeys, including ones that don't exist, get the same
+    // answer as a user with one, so it doesn't tell who has any. Their
+    // challenge isn't kept, so finishing fails like with a wrong passkey.
+    let allow_credentials = if passkeys.is_empty() {
+        vec![public_key_entity(&dummy_credential_id(&email))]
+    } else {
+        state
+            .webauthn_challenge_store
+            .write()
+            .await
+            .add_challenge(email, WebAuthnCeremony::Authentication, challenge.clone())
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+        passkeys
+            .iter()
+            .map(|passkey| public_key_entity(passkey.credential_id.as_ref()))
+            .collect()
+    };
+
+    let options = PublicKeyCredentialRequestOptions {
+        challenge: challenge.as_ref().to_owned(),
+        rp_id: WEBAUTHN_RELYING_PARTY.id.clone(),
+        allow_credentials,
+        timeout: timeout_ms(),
+        user_verification: "preferred".to_owned(),
+    };
+
+    Ok(Json(WebAuthnOptionsResponse {
+        public_key: options,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PasskeyLoginFinishRequest {
+    pub email: String,
+    // Set when the passkey stands in for the 2FA code of a password login
+    #[serde(rename = "loginAttemptId", default)]
+    pub login_attempt_id: Option<String>,
+    pub credential: AuthenticationCredential,
+}
+
+// Without a login attempt the passkey is the only factor, so the
+// authenticator must have verified the user (PIN or biometrics). As a second
+// factor being present is enough, like with any other 2FA code.
+pub async fn finish_passkey_login(
+    State(state): State<AppState>,
+    client_info: ClientInfo,
+    jar: CookieJar,
+    Json(request): Json<PasskeyLoginFinishRequest>,
+) -> (CookieJar, Response) {
+    let subject = AuditSubject::email(&request.email);
+    let (jar, result) = handle_finish_passkey_login(&state, &client_info, jar, request).await;
+    let action = AuditAction::FinishPasskeyLogin;
+    let response = audit(&state, action, &client_info, subject, result).await;
+    (jar, response)
+}
+
+async fn handle_finish_passkey_login(
+    state: &AppState,
+    client_info: &ClientInfo,
+    jar: CookieJar,
+    request: PasskeyLoginFinishRequest,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let email = match Email::parse(request.email) {
+        Ok(x) => x,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    let login_attempt_id = match request.login_attempt_id.map(LoginAttemptId::parse) {
+        Some(Ok(x)) => Some(x),
+        Some(Err(_)) => return (jar, Err(AuthAPIError::InvalidCredentials)),
+        None => None,
+    };
+
+    let passkey_is_valid = verify_passkey(
+        state,
+        &email,
+        &request.credential,
+        login_attempt_id.is_none(),
+    )
+    .await;
+
+    let result = match (login_attempt_id, passkey_is_valid) {
+        (Some(login_attempt_id), Ok(code_is_valid)) => state
+            .two_fa_code_store
+            .write()
+            .await
+            .verify_login_attempt(&email, &login_attempt_id, code_is_valid)
+            .await
+            .map_err(|e| match e {
+                TwoFACodeStoreError::UnexpectedError => AuthAPIError::UnexpectedError,
+                _ => AuthAPIError::IncorrectCredentials,
+            }),
+        (None, Ok(true)) => Ok(()),
+        (None, Ok(false)) => Err(AuthAPIError::IncorrectCredentials),
+        (_, Err(e)) => Err(e),
+    };
+
+    if let Err(e) = result {
+        return (jar, Err(e));
+    }
+
+    let (auth_cookie, refresh_cookie) = match start_session(state, &email, client_info).await {
+        Ok(x) => x,
+        Err(e) => return (jar, Err(e.into())),
+    };
+
+    (jar.add(auth_cookie).add(refresh_cookie), Ok(StatusCode::OK))
+}
+
+// Check an assertion against the challenge it answers and the user's passkeys.
+// Only store failures are errors; a bad assertion is Ok(false).
+async fn verify_passkey(
+    state: &AppState,
+    email: &Email,
+    credential: &AuthenticationCredential,
+    require_user_verification: bool,
+) -> Result<bool, AuthAPIError> {
+    let answered = match credential.challenge() {
+        Ok(x) => x,
+        Err(_) => return Ok(false),
+    };
+    let challenge = match state
+        .webauthn_challenge_store
+        .write()
+        .await
+        .take_challenge(email, WebAuthnCeremony::Authentication, &answered)
+        .await
+    {
+        Ok(x) => x,
+        Err(_) => return Ok(false),
+    };
+
+    let mut passkey_store = state.passkey_store.write().await;
+    let passkeys = passkey_store
+        .get_passkeys(email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let passkey = match passkeys
+        .iter()
+        .find(|passkey| passkey.credential_id.as_ref() == credential.id)
+    {
+        Some(x) => x,
+        None => return Ok(false),
+    };
+
+    let sign_count = match WEBAUTHN_RELYING_PARTY.verify_authentication(
+        &challenge,
+        passkey,
+        credential,
+        require_user_verification,
+    ) {
+        Ok(x) => x,
+        Err(_) => return Ok(false),
+    };
+
+    passkey_store
+        .update_sign_count(email, &passkey.credential_id, sign_count)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(true)
+}
@@ -0,0 +1,20 @@
+use sqlx::{
+    any::{install_default_drivers, AnyPoolOptions},
+    AnyPool,
+};
+
+// Connect to SQLite or Postgres, picked by the scheme of the database URL
+// (e.g. `sqlite://auth.db?mode=rwc` or `postgres://...`), and run any
+// pending migrations. The pool is shared by all SQL-backed stores.
+pub async fn get_database_pool(database_url: &str) -> Result<AnyPool, sqlx::Error> {
+    install_default_drivers();
+
+    let pool = AnyPoolOptions::new()
+        .max_connections(5)
+        .connect(database_url)
+        .await?;
+
+    sqlx::migrate!().run(&pool).await?;
+
+    Ok(pool)
+}
@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+};
+
+use crate::domain::{AuditEvent, AuditRecord, AuditSink, AuditSinkError};
+
+// Appends the audit log to a file, one JSON record per line. Only one process
+// may write to a file, or the chain forks.
+pub struct FileAuditSink {
+    path: PathBuf,
+    file: File,
+    last: Option<AuditRecord>,
+}
+
+impl FileAuditSink {
+    // Open the log at `path`, creating it if it doesn't exist yet. New records
+    // are chained onto the last one already in the file.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, AuditLogError> {
+        let path = path.as_ref().to_owned();
+
+        let last = match fs::metadata(&path).await {
+            Ok(_) => read_audit_log(&path).await?.pop(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(AuditLogError::Io(e)),
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(AuditLogError::Io)?;
+
+        Ok(Self { path, file, last })
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditSink for FileAuditSink {
+    async fn record(&mut self, event: AuditEvent, timestamp: i64) -> Result<(), AuditSinkError> {
+        let record = AuditRecord::new(event, timestamp, self.last.as_ref());
+
+        let mut line =
+            serde_json::to_string(&record).map_err(|_| AuditSinkError::UnexpectedError)?;
+        line.push('\n');
+
+        self.file
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|_| AuditSinkError::UnexpectedError)?;
+        self.file
+            .flush()
+            .await
+            .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        self.last = Some(record);
+        Ok(())
+    }
+
+    async fn get_records(&self) -> Result<Vec<AuditRecord>, AuditSinkError> {
+        read_audit_log(&self.path)
+            .await
+            .map_err(|_| AuditSinkError::UnexpectedError)
+    }
+}
+
+// Every record in the log at `path`, oldest first. Doesn't check the chain,
+// see `verify_chain`.
+pub async fn read_audit_log(path: impl AsRef<Path>) -> Result<Vec<AuditRecord>, AuditLogError> {
+    let contents = fs::read_to_string(path).await.map_err(AuditLogError::Io)?;
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|_| AuditLogError::Malformed { line: i + 1 })
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum AuditLogError {
+    Io(std::io::Error),
+    // Counted from 1
+    Malformed { line: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{verify_chain, AuditAction, AuditChainError, AuditOutcome};
+    use uuid::Uuid;
+
+    fn event(action: AuditAction) -> AuditEvent {
+        AuditEvent {
+            action,
+            outcome: AuditOutcome::Success,
+            status: 200,
+            error: None,
+            email: Some("test@example.com".to_owned()),
+            actor: None,
+            ip: Some("127.0.0.1".parse().unwrap()),
+            user_agent: Some("tests".to_owned()),
+        }
+    }
+
+    fn log_path() -> PathBuf {
+        std::env::temp_dir().join(format!("auth-service-audit-{}.log", Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_reopened_log_continues_the_chain() {
+        let path = log_path();
+
+        let mut sink = FileAuditSink::open(&path).await.unwrap();
+        sink.record(event(AuditAction::Signup), 1).await.unwrap();
+        sink.record(event(AuditAction::Login), 2).await.unwrap();
+        drop(sink);
+
+        let mut sink = FileAuditSink::open(&path).await.unwrap();
+        sink.record(event(AuditAction::Logout), 3).await.unwrap();
+
+        let records = sink.get_records().await.unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2].event.action, AuditAction::Logout);
+        assert_eq!(verify_chain(&records), Ok(3));
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_edited_log_is_detected() {
+        let path = log_path();
+
+        let mut sink = FileAuditSink::open(&path).await.unwrap();
+        for action in [AuditAction::Signup, AuditAction::Login, AuditAction::Logout] {
+            sink.record(event(action), 1).await.unwrap();
+        }
+        drop(sink);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, contents.replacen("\"login\"", "\"logout\"", 1)).unwrap();
+
+        let records = read_audit_log(&path).await.unwrap();
+        assert_eq!(
+            verify_chain(&records),
+            Err(AuditChainError::Broken { position: 1 })
+        );
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_line_is_reported() {
+        let path = log_path();
+
+        let mut sink = FileAuditSink::open(&path).await.unwrap();
+        sink.record(event(AuditAction::Signup), 1).await.unwrap();
+        drop(sink);
+
+        let mut contents = std::fs::read_to_string(&path).unwrap();
+        contents.push_str("not json\n");
+        std::fs::write(&path, contents).unwrap();
+
+        assert!(matches!(
+            read_audit_log(&path).await,
+            Err(AuditLogError::Malformed { line: 2 })
+        ));
+        assert!(FileAuditSink::open(&path).await.is_err());
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use crate::domain::{
+    Email, EmailTokenId, EmailTokenPurpose, EmailTokenStore, EmailTokenStoreError,
+};
+
+#[derive(Default)]
+pub struct HashmapEmailTokenStore {
+    tokens: HashMap<(Email, EmailTokenPurpose), (EmailTokenId, i64)>,
+}
+
+#[async_trait::async_trait]
+impl EmailTokenStore for HashmapEmailTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        purpose: EmailTokenPurpose,
+        token_id: EmailTokenId,
+        sent_at: i64,
+    ) -> Result<(), EmailTokenStoreError> {
+        self.tokens.insert((email, purpose), (token_id, sent_at));
+        Ok(())
+    }
+
+    async fn get_sent_at(
+        &self,
+        email: &Email,
+        purpose: EmailTokenPurpose,
+    ) -> Result<Option<i64>, EmailTokenStoreError> {
+        Ok(self
+            .tokens
+            .get(&(email.clone(), purpose))
+            .map(|(_, sent_at)| *sent_at))
+    }
+
+    async fn take_token(
+        &mut self,
+        email: &Email,
+        purpose: EmailTokenPurpose,
+        token_id: &EmailTokenId,
+    ) -> Result<(), EmailTokenStoreError> {
+        let key = (email.clone(), purpose);
+        match self.tokens.get(&key) {
+            Some((current, _)) if current == token_id => {
+                self.tokens.remove(&key);
+                Ok(())
+            }
+            _ => Err(EmailTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn delete_tokens(&mut self, email: &Email) -> Result<(), EmailTokenStoreError> {
+        self.tokens.retain(|(x, _), _| x != email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email() -> Email {
+        Email::parse("foo@example.com".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_token_can_only_be_taken_once() {
+        let mut store = HashmapEmailTokenStore::default();
+        let purpose = EmailTokenPurpose::VerifyEmail;
+        let token_id = EmailTokenId::default();
+        let _ = store
+            .add_token(email(), purpose, token_id.clone(), 1000)
+            .await;
+
+        assert_eq!(store.get_sent_at(&email(), purpose).await, Ok(Some(1000)));
+        assert_eq!(store.take_token(&email(), purpose, &token_id).await, Ok(()));
+        assert_eq!(
+            store.take_token(&email(), purpose, &token_id).await,
+            Err(EmailTokenStoreError::TokenNotFound)
+        );
+        assert_eq!(store.get_sent_at(&email(), purpose).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_new_token_supersedes_the_old_one() {
+        let mut store = HashmapEmailTokenStore::default();
+        let purpose = EmailTokenPurpose::VerifyEmail;
+        let old_id = EmailTokenId::default();
+        let new_id = EmailTokenId::default();
+        let _ = store
+            .add_token(email(), purpose, old_id.clone(), 1000)
+            .await;
+        let _ = store
+            .add_token(email(), purpose, new_id.clone(), 2000)
+            .await;
+
+        assert_eq!(store.get_sent_at(&email(), purpose).await, Ok(Some(2000)));
+        assert_eq!(
+            store.take_token(&email(), purpose, &old_id).await,
+            Err(EmailTokenStoreError::TokenNotFound)
+        );
+        assert_eq!(store.take_token(&email(), purpose, &new_id).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_delete_tokens() {
+        let mut store = HashmapEmailTokenStore::default();
+        for purpose in [
+            EmailTokenPurpose::VerifyEmail,
+            EmailTokenPurpose::ResetPassword,
+        ] {
+            let _ = store
+                .add_token(email(), purpose, EmailTokenId::default(), 1000)
+                .await;
+        }
+
+        assert_eq!(store.delete_tokens(&email()).await, Ok(()));
+        for purpose in [
+            EmailTokenPurpose::VerifyEmail,
+            EmailTokenPurpose::ResetPassword,
+        ] {
+            assert_eq!(store.get_sent_at(&email(), purpose).await, Ok(None));
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::domain::{FailedLogins, LoginThrottleStore, LoginThrottleStoreError, ThrottleKey};
+
+// Only suitable for a single instance; each process counts on its own.
+#[derive(Default)]
+pub struct HashmapLoginThrottleStore {
+    failures: HashMap<ThrottleKey, FailedLogins>,
+}
+
+#[async_trait::async_trait]
+impl LoginThrottleStore for HashmapLoginThrottleStore {
+    async fn get_failures(
+        &self,
+        key: &ThrottleKey,
+    ) -> Result<Option<FailedLogins>, LoginThrottleStoreError> {
+        Ok(self.failures.get(key).copied())
+    }
+
+    async fn record_failure(
+        &mut self,
+        key: ThrottleKey,
+        now: i64,
+        reset_after_seconds: i64,
+    ) -> Result<FailedLogins, LoginThrottleStoreError> {
+        let entry = self.failures.entry(key).or_insert(FailedLogins {
+            count: 0,
+            last_failure_at: now,
+        });
+
+        if now - entry.last_failure_at >= reset_after_seconds {
+            entry.count = 0;
+        }
+        entry.count = entry.count.saturating_add(1);
+        entry.last_failure_at = now;
+
+        Ok(*entry)
+    }
+
+    async fn clear_failures(&mut self, key: &ThrottleKey) -> Result<(), LoginThrottleStoreError> {
+        self.failures.remove(key);
+        Ok(())
+    }
+
+    async fn forgive_failure(
+        &mut self,
+        key: &ThrottleKey,
+        failed_at: i64,
+        previous_failure_at: Option<i64>,
+    ) -> Result<(), LoginThrottleStoreError> {
+        if let Some(entry) = self.failures.get_mut(key) {
+            entry.count = entry.count.saturating_sub(1);
+            if entry.last_failure_at == failed_at {
+                entry.last_failure_at = previous_failure_at.unwrap_or(failed_at);
+            }
+            if entry.count == 0 {
+                self.failures.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    async fn prune_expired(
+        &mut self,
+        last_failure_before: i64,
+    ) -> Result<(), LoginThrottleStoreError> {
+        self.failures
+            .retain(|_, failures| failures.last_failure_at >= last_failure_before);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Email;
+
+    fn key() -> ThrottleKey {
+        ThrottleKey::Email(Email::parse("foo@example.com".to_string()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_record_failure_counts_up() {
+        let mut store = HashmapLoginThrottleStore::default();
+        let _ = store.record_failure(key(), 100, 60).await;
+        let failures = store.record_failure(key(), 110, 60).await;
+
+        let expected = FailedLogins {
+            count: 2,
+            last_failure_at: 110,
+        };
+        assert_eq!(failures, Ok(expected));
+        assert_eq!(store.get_failures(&key()).await, Ok(Some(expected)));
+    }
+
+    #[tokio::test]
+    async fn test_old_failures_are_forgotten() {
+        let mut store = HashmapLoginThrottleStore::default();
+        let _ = store.record_failure(key(), 100, 60).await;
+        let _ = store.record_failure(key(), 110, 60).await;
+
+        let failures = store.record_failure(key(), 170, 60).await.unwrap();
+        assert_eq!(failures.count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_clear_failures() {
+        let mut store = HashmapLoginThrottleStore::default();
+        let _ = store.record_failure(key(), 100, 60).await;
+        let _ = store.clear_failures(&key()).await;
+
+        assert_eq!(store.get_failures(&key()).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_forgive_failure() {
+        let mut store = HashmapLoginThrottleStore::default();
+        let _ = store.record_failure(key(), 100, 60).await;
+        let _ = store.record_failure(key(), 110, 60).await;
+
+        // The last failure goes back to when it was before the attempt
+        let _ = store.forgive_failure(&key(), 110, Some(100)).await;
+        let expected = FailedLogins {
+            count: 1,
+            last_failure_at: 100,
+        };
+        assert_eq!(store.get_failures(&key()).await, Ok(Some(expected)));
+
+        let _ = store.forgive_failure(&key(), 100, None).await;
+        assert_eq!(store.get_failures(&key()).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_forgive_failure_keeps_later_failures() {
+        let mut store = HashmapLoginThrottleStore::default();
+        let _ = store.record_failure(key(), 100, 60).await;
+        let _ = store.record_failure(key(), 110, 60).await;
+        let _ = store.record_failure(key(), 120, 60).await;
+
+        let _ = store.forgive_failure(&key(), 110, Some(100)).await;
+        let expected = FailedLogins {
+            count: 2,
+            last_failure_at: 120,
+        };
+        assert_eq!(store.get_failures(&key()).await, Ok(Some(expected)));
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired() {
+        let mut store = HashmapLoginThrottleStore::default();
+        let ip = ThrottleKey::Ip("127.0.0.1".parse().unwrap());
+        let _ = store.record_failure(key(), 100, 60).await;
+        let _ = store.record_failure(ip.clone(), 200, 60).await;
+
+        let _ = store.prune_expired(150).await;
+        assert_eq!(store.get_failures(&key()).await, Ok(None));
+        assert!(store.get_failures(&ip).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_counted_separately() {
+        let mut store = HashmapLoginThrottleStore::default();
+        let ip = ThrottleKey::Ip("127.0.0.1".parse().unwrap());
+        let _ = store.record_failure(key(), 100, 60).await;
+
+        assert_eq!(store.get_failures(&ip).await, Ok(None));
+    }
+}
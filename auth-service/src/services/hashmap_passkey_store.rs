@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use crate::domain::{CredentialId, Email, Passkey, PasskeyStore, PasskeyStoreError};
+
+#[derive(Default)]
+pub struct HashmapPasskeyStore {
+    passkeys: HashMap<Email, Vec<Passkey>>,
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for HashmapPasskeyStore {
+    async fn add_passkey(
+        &mut self,
+        email: Email,
+        passkey: Passkey,
+    ) -> Result<(), PasskeyStoreError> {
+        // Credential ids are unique across all users
+        let taken = self
+            .passkeys
+            .values()
+            .flatten()
+            .any(|x| x.credential_id == passkey.credential_id);
+        if taken {
+            return Err(PasskeyStoreError::CredentialAlreadyExists);
+        }
+
+        self.passkeys.entry(email).or_default().push(passkey);
+        Ok(())
+    }
+
+    async fn get_passkeys(&self, email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError> {
+        Ok(self.passkeys.get(email).cloned().unwrap_or_default())
+    }
+
+    async fn update_sign_count(
+        &mut self,
+        email: &Email,
+        credential_id: &CredentialId,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError> {
+        let passkey = self
+            .passkeys
+            .get_mut(email)
+            .and_then(|passkeys| {
+                passkeys
+                    .iter_mut()
+                    .find(|x| &x.credential_id == credential_id)
+            })
+            .ok_or(PasskeyStoreError::CredentialNotFound)?;
+
+        passkey.sign_count = sign_count;
+        Ok(())
+    }
+
+    async fn delete_passkeys(&mut self, email: &Email) -> Result<(), PasskeyStoreError> {
+        self.passkeys.remove(email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(name: &str) -> Email {
+        Email::parse(format!("{}@example.com", name)).unwrap()
+    }
+
+    fn passkey(id: &str) -> Passkey {
+        Passkey {
+            credential_id: CredentialId::parse(id.to_owned()).unwrap(),
+            public_key: vec![4; 65],
+            sign_count: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_passkeys() {
+        let mut store = HashmapPasskeyStore::default();
+        let _ = store.add_passkey(email("foo"), passkey("AAAA")).await;
+        let _ = store.add_passkey(email("foo"), passkey("BBBB")).await;
+
+        assert_eq!(
+            store.get_passkeys(&email("foo")).await,
+            Ok(vec![passkey("AAAA"), passkey("BBBB")])
+        );
+        assert_eq!(store.get_passkeys(&email("bar")).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_credential_ids_are_unique() {
+        let mut store = HashmapPasskeyStore::default();
+        let _ = store.add_passkey(email("foo"), passkey("AAAA")).await;
+
+        assert_eq!(
+            store.add_passkey(email("bar"), passkey("AAAA")).await,
+            Err(PasskeyStoreError::CredentialAlreadyExists)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_sign_count() {
+        let mut store = HashmapPasskeyStore::default();
+        let _ = store.add_passkey(email("foo"), passkey("AAAA")).await;
+        let id = passkey("AAAA").credential_id;
+
+        assert_eq!(store.update_sign_count(&email("foo"), &id, 3).await, Ok(()));
+        assert_eq!(
+            store.get_passkeys(&email("foo")).await.unwrap()[0].sign_count,
+            3
+        );
+        assert_eq!(
+            store.update_sign_count(&email("bar"), &id, 4).await,
+            Err(PasskeyStoreError::CredentialNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_passkeys() {
+        let mut store = HashmapPasskeyStore::default();
+        let _ = store.add_passkey(email("foo"), passkey("AAAA")).await;
+        let _ = store.add_passkey(email("bar"), passkey("BBBB")).await;
+
+        assert_eq!(store.delete_passkeys(&email("foo")).await, Ok(()));
+        assert_eq!(store.get_passkeys(&email("foo")).await, Ok(vec![]));
+        assert_eq!(
+            store.get_passkeys(&email("bar")).await,
+            Ok(vec![passkey("BBBB")])
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::domain::{Email, HashedRecoveryCode, RecoveryCodeStore, RecoveryCodeStoreError};
+
+#[derive(Default)]
+pub struct HashmapRecoveryCodeStore {
+    codes: HashMap<Email, HashSet<HashedRecoveryCode>>,
+}
+
+#[async_trait::async_trait]
+impl RecoveryCodeStore for HashmapRecoveryCodeStore {
+    async fn set_codes(
+        &mut self,
+        email: Email,
+        codes: Vec<HashedRecoveryCode>,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        self.codes.insert(email, codes.into_iter().collect());
+        Ok(())
+    }
+
+    async fn has_code(
+        &self,
+        email: &Email,
+        code: &HashedRecoveryCode,
+    ) -> Result<bool, RecoveryCodeStoreError> {
+        Ok(self
+            .codes
+            .get(email)
+            .is_some_and(|codes| codes.contains(code)))
+    }
+
+    async fn use_code(
+        &mut self,
+        email: &Email,
+        code: &HashedRecoveryCode,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        let removed = self
+            .codes
+            .get_mut(email)
+            .is_some_and(|codes| codes.remove(code));
+
+        match removed {
+            true => Ok(()),
+            false => Err(RecoveryCodeStoreError::CodeNotFound),
+        }
+    }
+
+    async fn count_codes(&self, email: &Email) -> Result<usize, RecoveryCodeStoreError> {
+        Ok(self.codes.get(email).map_or(0, |codes| codes.len()))
+    }
+
+    async fn delete_codes(&mut self, email: &Email) -> Result<(), RecoveryCodeStoreError> {
+        self.codes.remove(email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::RecoveryCode;
+
+    fn email() -> Email {
+        Email::parse("foo@example.com".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_code_can_only_be_used_once() {
+        let mut store = HashmapRecoveryCodeStore::default();
+        let code = RecoveryCode::default().hash();
+        let _ = store
+            .set_codes(email(), vec![code.clone(), RecoveryCode::default().hash()])
+            .await;
+
+        assert_eq!(store.has_code(&email(), &code).await, Ok(true));
+        assert_eq!(store.use_code(&email(), &code).await, Ok(()));
+        assert_eq!(store.has_code(&email(), &code).await, Ok(false));
+        assert_eq!(
+            store.use_code(&email(), &code).await,
+            Err(RecoveryCodeStoreError::CodeNotFound)
+        );
+        assert_eq!(store.count_codes(&email()).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_set_codes_replaces_the_old_set() {
+        let mut store = HashmapRecoveryCodeStore::default();
+        let old = RecoveryCode::default().hash();
+        let _ = store.set_codes(email(), vec![old.clone()]).await;
+        let _ = store
+            .set_codes(email(), vec![RecoveryCode::default().hash()])
+            .await;
+
+        assert_eq!(
+            store.use_code(&email(), &old).await,
+            Err(RecoveryCodeStoreError::CodeNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_user_has_no_codes() {
+        let mut store = HashmapRecoveryCodeStore::default();
+
+        assert_eq!(store.count_codes(&email()).await, Ok(0));
+        assert_eq!(
+            store
+                .use_code(&email(), &RecoveryCode::default().hash())
+                .await,
+            Err(RecoveryCodeStoreError::CodeNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_codes() {
+        let mut store = HashmapRecoveryCodeStore::default();
+        let code = RecoveryCode::default().hash();
+        let _ = store.set_codes(email(), vec![code.clone()]).await;
+
+        assert_eq!(store.delete_codes(&email()).await, Ok(()));
+        assert_eq!(store.count_codes(&email()).await, Ok(0));
+        assert_eq!(
+            store.use_code(&email(), &code).await,
+            Err(RecoveryCodeStoreError::CodeNotFound)
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use chrono::Utc;
+use std::collections::HashMap;
+
+use crate::domain::{Email, RefreshToken, RefreshTokenStore, RefreshTokenStoreError, SessionId};
+
+#[derive(Clone, Debug)]
+struct RefreshTokenEntry {
+    email: Email,
+    session_id: SessionId,
+    expires_at: i64,
+    // Rotated tokens are kept until they expire so reuse can be detected.
+    rotated: bool,
+}
+
+#[derive(Default)]
+pub struct HashmapRefreshTokenStore {
+    tokens: HashMap<RefreshToken, RefreshTokenEntry>,
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for HashmapRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        session_id: SessionId,
+        token: RefreshToken,
+        expires_at: i64,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let entry = RefreshTokenEntry {
+            email,
+            session_id,
+            expires_at,
+            rotated: false,
+        };
+        self.tokens.insert(token, entry);
+        Ok(())
+    }
+
+    async fn rotate_token(
+        &mut self,
+        token: &RefreshToken,
+        new_token: RefreshToken,
+        expires_at: i64,
+    ) -> Result<(Email, SessionId), RefreshTokenStoreError> {
+        let entry = match self.tokens.get_mut(token) {
+            Some(x) => x,
+            None => return Err(RefreshTokenStoreError::TokenNotFound),
+        };
+
+        if entry.rotated {
+            return Err(RefreshTokenStoreError::TokenReused);
+        }
+
+        if entry.expires_at <= Utc::now().timestamp() {
+            self.tokens.remove(token);
+            return Err(RefreshTokenStoreError::TokenExpired);
+        }
+
+        entry.rotated = true;
+        let new_entry = RefreshTokenEntry {
+            email: entry.email.clone(),
+            session_id: entry.session_id.clone(),
+            expires_at,
+            rotated: false,
+        };
+        let result = (new_entry.email.clone(), new_entry.session_id.clone());
+        self.tokens.insert(new_token, new_entry);
+
+        Ok(result)
+    }
+
+    async fn revoke_family(
+        &mut self,
+        token: &RefreshToken,
+    ) -> Result<(Email, SessionId), RefreshTokenStoreError> {
+        let (email, session_id) = match self.tokens.get(token) {
+            Some(x) => (x.email.clone(), x.session_id.clone()),
+            None => return Err(RefreshTokenStoreError::TokenNotFound),
+        };
+        self.revoke_session(&session_id).await?;
+        Ok((email, session_id))
+    }
+
+    async fn revoke_session(
+        &mut self,
+        session_id: &SessionId,
+    ) -> Result<(), RefreshTokenStoreError> {
+        self.tokens.retain(|_, x| &x.session_id != session_id);
+        Ok(())
+    }
+
+    async fn revoke_user(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        self.tokens.retain(|_, x| &x.email != email);
+        Ok(())
+    }
+
+    async fn prune_expired(&mut self) -> Result<(), RefreshTokenStoreError> {
+        let now = Utc::now().timestamp();
+        self.tokens.retain(|_, x| x.expires_at > now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_one_hour() -> i64 {
+        Utc::now().timestamp() + 3600
+    }
+
+    fn email() -> Email {
+        Email::parse("foo@example.com".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_rotate_a_fresh_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+        let session_id = SessionId::default();
+        let _ = store
+            .add_token(email(), session_id.clone(), token.clone(), in_one_hour())
+            .await;
+
+        let result = store
+            .rotate_token(&token, RefreshToken::default(), in_one_hour())
+            .await;
+
+        assert_eq!(result, Ok((email(), session_id)));
+    }
+
+    #[tokio::test]
+    async fn it_should_error_rotating_an_unknown_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+
+        let result = store
+            .rotate_token(
+                &RefreshToken::default(),
+                RefreshToken::default(),
+                in_one_hour(),
+            )
+            .await;
+
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn it_should_error_rotating_an_expired_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+        let _ = store
+            .add_token(
+                email(),
+                SessionId::default(),
+                token.clone(),
+                Utc::now().timestamp() - 1,
+            )
+            .await;
+
+        let result = store
+            .rotate_token(&token, RefreshToken::default(), in_one_hour())
+            .await;
+
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenExpired));
+    }
+
+    #[tokio::test]
+    async fn it_should_revoke_the_family_of_a_reused_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let first = RefreshToken::default();
+        let second = RefreshToken::default();
+        let session_id = SessionId::default();
+        let _ = store
+            .add_token(email(), session_id.clone(), first.clone(), in_one_hour())
+            .await;
+        let _ = store
+            .rotate_token(&first, second.clone(), in_one_hour())
+            .await;
+
+        let result = store
+            .rotate_token(&first, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenReused));
+
+        let result = store.revoke_family(&first).await;
+        assert_eq!(result, Ok((email(), session_id)));
+
+        let result = store
+            .rotate_token(&second, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn it_should_leave_other_families_alone_when_revoking() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let first = RefreshToken::default();
+        let other = RefreshToken::default();
+        let _ = store
+            .add_token(email(), SessionId::default(), first.clone(), in_one_hour())
+            .await;
+        let _ = store
+            .add_token(email(), SessionId::default(), other.clone(), in_one_hour())
+            .await;
+
+        let result = store.revoke_family(&first).await;
+        assert!(result.is_ok());
+
+        let result = store
+            .rotate_token(&other, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result.map(|(email, _)| email), Ok(email()));
+    }
+
+    #[tokio::test]
+    async fn it_should_revoke_every_family_of_a_user() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let first = RefreshToken::default();
+        let second = RefreshToken::default();
+        let someone_else = RefreshToken::default();
+        let other_email = Email::parse("bar@example.com".to_string()).unwrap();
+        let _ = store
+            .add_token(email(), SessionId::default(), first.clone(), in_one_hour())
+            .await;
+        let _ = store
+            .add_token(email(), SessionId::default(), second.clone(), in_one_hour())
+            .await;
+        let _ = store
+            .add_token(
+                other_email.clone(),
+                SessionId::default(),
+                someone_else.clone(),
+                in_one_hour(),
+            )
+            .await;
+
+        let result = store.revoke_user(&email()).await;
+        assert!(result.is_ok());
+
+        for token in [first, second] {
+            let result = store
+                .rotate_token(&token, RefreshToken::default(), in_one_hour())
+                .await;
+            assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+        }
+        let result = store
+            .rotate_token(&someone_else, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result.map(|(email, _)| email), Ok(other_email));
+    }
+
+    #[tokio::test]
+    async fn it_should_revoke_a_session_by_id() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::default();
+        let session_id = SessionId::default();
+        let _ = store
+            .add_token(email(), session_id.clone(), token.clone(), in_one_hour())
+            .await;
+
+        assert_eq!(store.revoke_session(&session_id).await, Ok(()));
+
+        let result = store
+            .rotate_token(&token, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use crate::domain::{Email, Session, SessionId, SessionStore, SessionStoreError};
+
+// Only suitable for a single instance; restarting it logs everyone out.
+#[derive(Default)]
+pub struct HashmapSessionStore {
+    sessions: HashMap<SessionId, (Email, Session)>,
+}
+
+#[async_trait::async_trait]
+impl SessionStore for HashmapSessionStore {
+    async fn add_session(
+        &mut self,
+        email: Email,
+        session: Session,
+    ) -> Result<(), SessionStoreError> {
+        self.sessions.insert(session.id.clone(), (email, session));
+        Ok(())
+    }
+
+    async fn touch_session(
+        &mut self,
+        email: &Email,
+        id: &SessionId,
+        now: i64,
+    ) -> Result<(), SessionStoreError> {
+        match self.sessions.get_mut(id) {
+            Some((owner, session)) if owner == email => {
+                session.last_seen_at = session.last_seen_at.max(now);
+                Ok(())
+            }
+            _ => Err(SessionStoreError::SessionNotFound),
+        }
+    }
+
+    async fn get_session(
+        &self,
+        email: &Email,
+        id: &SessionId,
+    ) -> Result<Session, SessionStoreError> {
+        match self.sessions.get(id) {
+            Some((owner, session)) if owner == email => Ok(session.clone()),
+            _ => Err(SessionStoreError::SessionNotFound),
+        }
+    }
+
+    async fn get_sessions(&self, email: &Email) -> Result<Vec<Session>, SessionStoreError> {
+        let mut sessions: Vec<Session> = self
+            .sessions
+            .values()
+            .filter(|(owner, _)| owner == email)
+            .map(|(_, session)| session.clone())
+            .collect();
+        sessions.sort_by_key(|session| session.created_at);
+        Ok(sessions)
+    }
+
+    async fn remove_session(
+        &mut self,
+        email: &Email,
+        id: &SessionId,
+    ) -> Result<(), SessionStoreError> {
+        match self.sessions.get(id) {
+            Some((owner, _)) if owner == email => {
+                self.sessions.remove(id);
+                Ok(())
+            }
+            _ => Err(SessionStoreError::SessionNotFound),
+        }
+    }
+
+    async fn remove_sessions(&mut self, email: &Email) -> Result<(), SessionStoreError> {
+        self.sessions.retain(|_, (owner, _)| owner != email);
+        Ok(())
+    }
+
+    async fn prune_idle(&mut self, last_seen_before: i64) -> Result<(), SessionStoreError> {
+        self.sessions
+            .retain(|_, (_, session)| session.last_seen_at >= last_seen_before);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(name: &str) -> Email {
+        Email::parse(format!("{}@example.com", name)).unwrap()
+    }
+
+    fn session(created_at: i64) -> Session {
+        Session::new(
+            Some("Firefox".to_owned()),
+            Some("127.0.0.1".parse().unwrap()),
+            created_at,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_listed_oldest_first() {
+        let mut store = HashmapSessionStore::default();
+        let (newer, older) = (session(2000), session(1000));
+        let _ = store.add_session(email("foo"), newer.clone()).await;
+        let _ = store.add_session(email("foo"), older.clone()).await;
+        let _ = store.add_session(email("bar"), session(1500)).await;
+
+        assert_eq!(
+            store.get_sessions(&email("foo")).await,
+            Ok(vec![older, newer])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_touch_session() {
+        let mut store = HashmapSessionStore::default();
+        let session = session(1000);
+        let _ = store.add_session(email("foo"), session.clone()).await;
+
+        assert_eq!(
+            store.touch_session(&email("foo"), &session.id, 1500).await,
+            Ok(())
+        );
+        let session = store.get_session(&email("foo"), &session.id).await.unwrap();
+        assert_eq!(session.last_seen_at, 1500);
+
+        // Someone else's session is as good as a missing one
+        assert_eq!(
+            store.touch_session(&email("bar"), &session.id, 1500).await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_removed_session_can_not_be_touched() {
+        let mut store = HashmapSessionStore::default();
+        let session = session(1000);
+        let _ = store.add_session(email("foo"), session.clone()).await;
+
+        assert_eq!(
+            store.remove_session(&email("bar"), &session.id).await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+        assert_eq!(
+            store.remove_session(&email("foo"), &session.id).await,
+            Ok(())
+        );
+        assert_eq!(
+            store.touch_session(&email("foo"), &session.id, 1500).await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+        assert_eq!(
+            store.get_session(&email("foo"), &session.id).await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_sessions_of_one_user() {
+        let mut store = HashmapSessionStore::default();
+        let _ = store.add_session(email("foo"), session(1000)).await;
+        let _ = store.add_session(email("foo"), session(2000)).await;
+        let other = session(1000);
+        let _ = store.add_session(email("bar"), other.clone()).await;
+
+        assert_eq!(store.remove_sessions(&email("foo")).await, Ok(()));
+        assert_eq!(store.get_sessions(&email("foo")).await, Ok(vec![]));
+        assert_eq!(store.get_sessions(&email("bar")).await, Ok(vec![other]));
+    }
+
+    #[tokio::test]
+    async fn test_prune_idle() {
+        let mut store = HashmapSessionStore::default();
+        let (idle, active) = (session(1000), session(2000));
+        let _ = store.add_session(email("foo"), idle).await;
+        let _ = store.add_session(email("foo"), active.clone()).await;
+
+        assert_eq!(store.prune_idle(1500).await, Ok(()));
+        assert_eq!(store.get_sessions(&email("foo")).await, Ok(vec![active]));
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use crate::domain::{Email, TotpSecret, TotpStore, TotpStoreError};
+
+#[derive(Clone, Debug, Default)]
+struct TotpEntry {
+    secret: Option<TotpSecret>,
+    pending_secret: Option<TotpSecret>,
+    last_used_step: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct HashmapTotpStore {
+    secrets: HashMap<Email, TotpEntry>,
+}
+
+#[async_trait::async_trait]
+impl TotpStore for HashmapTotpStore {
+    async fn set_pending_secret(
+        &mut self,
+        email: Email,
+        secret: TotpSecret,
+    ) -> Result<(), TotpStoreError> {
+        self.secrets.entry(email).or_default().pending_secret = Some(secret);
+        Ok(())
+    }
+
+    async fn get_pending_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError> {
+        self.secrets
+            .get(email)
+            .and_then(|x| x.pending_secret.clone())
+            .ok_or(TotpStoreError::SecretNotFound)
+    }
+
+    async fn confirm_secret(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError> {
+        let entry = match self.secrets.get_mut(email) {
+            Some(x) if x.pending_secret.is_some() => x,
+            _ => return Err(TotpStoreError::SecretNotFound),
+        };
+
+        entry.secret = entry.pending_secret.take();
+        entry.last_used_step = Some(step);
+        Ok(())
+    }
+
+    async fn get_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError> {
+        self.secrets
+            .get(email)
+            .and_then(|x| x.secret.clone())
+            .ok_or(TotpStoreError::SecretNotFound)
+    }
+
+    async fn use_step(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError> {
+        let entry = match self.secrets.get_mut(email) {
+            Some(x) if x.secret.is_some() => x,
+            _ => return Err(TotpStoreError::SecretNotFound),
+        };
+
+        if entry.last_used_step.is_some_and(|last| step <= last) {
+            return Err(TotpStoreError::StepAlreadyUsed);
+        }
+
+        entry.last_used_step = Some(step);
+        Ok(())
+    }
+
+    async fn delete_secrets(&mut self, email: &Email) -> Result<(), TotpStoreError> {
+        self.secrets.remove(email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email() -> Email {
+        Email::parse("foo@example.com".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_only_use_a_secret_once_confirmed() {
+        let mut store = HashmapTotpStore::default();
+        let secret = TotpSecret::default();
+        let _ = store.set_pending_secret(email(), secret.clone()).await;
+
+        assert_eq!(store.get_pending_secret(&email()).await, Ok(secret.clone()));
+        assert_eq!(
+            store.get_secret(&email()).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+
+        assert_eq!(store.confirm_secret(&email(), 10).await, Ok(()));
+        assert_eq!(store.get_secret(&email()).await, Ok(secret));
+        assert_eq!(
+            store.get_pending_secret(&email()).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_the_old_secret_while_a_new_one_is_pending() {
+        let mut store = HashmapTotpStore::default();
+        let old = TotpSecret::default();
+        let _ = store.set_pending_secret(email(), old.clone()).await;
+        let _ = store.confirm_secret(&email(), 10).await;
+
+        let _ = store
+            .set_pending_secret(email(), TotpSecret::default())
+            .await;
+
+        assert_eq!(store.get_secret(&email()).await, Ok(old));
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_steps_already_used() {
+        let mut store = HashmapTotpStore::default();
+        let _ = store
+            .set_pending_secret(email(), TotpSecret::default())
+            .await;
+        let _ = store.confirm_secret(&email(), 10).await;
+
+        assert_eq!(
+            store.use_step(&email(), 10).await,
+            Err(TotpStoreError::StepAlreadyUsed)
+        );
+        assert_eq!(store.use_step(&email(), 11).await, Ok(()));
+        assert_eq!(
+            store.use_step(&email(), 11).await,
+            Err(TotpStoreError::StepAlreadyUsed)
+        );
+        assert_eq!(
+            store.use_step(&email(), 9).await,
+            Err(TotpStoreError::StepAlreadyUsed)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_error_without_a_secret() {
+        let mut store = HashmapTotpStore::default();
+
+        assert_eq!(
+            store.confirm_secret(&email(), 10).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+        assert_eq!(
+            store.use_step(&email(), 10).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_delete_both_secrets() {
+        let mut store = HashmapTotpStore::default();
+        let _ = store
+            .set_pending_secret(email(), TotpSecret::default())
+            .await;
+        let _ = store.confirm_secret(&email(), 10).await;
+        let _ = store
+            .set_pending_secret(email(), TotpSecret::default())
+            .await;
+
+        assert_eq!(store.delete_secrets(&email()).await, Ok(()));
+        assert_eq!(
+            store.get_secret(&email()).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+        assert_eq!(
+            store.get_pending_secret(&email()).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+    }
+}
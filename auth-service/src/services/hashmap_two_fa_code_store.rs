@@ -1,16 +1,63 @@
+use chrono::Utc;
 use std::collections::HashMap;
 
-use crate::domain::{
-    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
-    email::Email,
+use crate::{
+    domain::{
+        data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+        email::Email,
+    },
+    utils::constants::{TWO_FA_CODE_MAX_ATTEMPTS, TWO_FA_CODE_TTL_SECONDS},
 };
 
-#[derive(Default)]
+#[derive(Clone, Debug)]
+struct TwoFACodeEntry {
+    login_attempt_id: LoginAttemptId,
+    // None when the code comes from somewhere else, e.g. an authenticator app
+    code: Option<TwoFACode>,
+    created_at: i64,
+    ttl_seconds: i64,
+    failed_attempts: u32,
+}
+
+impl TwoFACodeEntry {
+    fn is_expired(&self) -> bool {
+        Utc::now().timestamp() >= self.created_at + self.ttl_seconds
+    }
+}
+
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    codes: HashMap<Email, TwoFACodeEntry>,
+    ttl_seconds: i64,
+    max_attempts: u32,
+}
+
+impl HashmapTwoFACodeStore {
+    pub fn new(ttl_seconds: i64, max_attempts: u32) -> Self {
+        Self {
+            codes: HashMap::new(),
+            ttl_seconds,
+            max_attempts,
+        }
+    }
+
+    fn insert(&mut self, email: Email, login_attempt_id: LoginAttemptId, code: Option<TwoFACode>) {
+        let entry = TwoFACodeEntry {
+            login_attempt_id,
+            code,
+            created_at: Utc::now().timestamp(),
+            ttl_seconds: self.ttl_seconds,
+            failed_attempts: 0,
+        };
+        self.codes.insert(email, entry);
+    }
+}
+
+impl Default for HashmapTwoFACodeStore {
+    fn default() -> Self {
+        Self::new(TWO_FA_CODE_TTL_SECONDS, TWO_FA_CODE_MAX_ATTEMPTS)
+    }
 }
 
-// TODO: implement TwoFACodeStore for HashmapTwoFACodeStore
 #[async_trait::async_trait]
 impl TwoFACodeStore for HashmapTwoFACodeStore {
     async fn add_code(
@@ -19,7 +66,16 @@ impl TwoFACodeStore for HashmapTwoFACodeStore {
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        self.codes.insert(email, (login_attempt_id, code));
+        self.insert(email, login_attempt_id, Some(code));
+        Ok(())
+    }
+
+    async fn add_login_attempt(
+        &mut self,
+        email: Email,
+        login_attempt_id: LoginAttemptId,
+    ) -> Result<(), TwoFACodeStoreError> {
+        self.insert(email, login_attempt_id, None);
         Ok(())
     }
 
@@ -35,10 +91,59 @@ impl TwoFACodeStore for HashmapTwoFACodeStore {
         email: &Email,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
         match self.codes.get(email) {
-            Some(x) => Ok(x.clone()),
+            Some(x) if x.is_expired() => Err(TwoFACodeStoreError::CodeExpired),
+            Some(x) => match &x.code {
+                Some(code) => Ok((x.login_attempt_id.clone(), code.clone())),
+                None => Err(TwoFACodeStoreError::UnexpectedError),
+            },
             None => Err(TwoFACodeStoreError::UnexpectedError),
         }
     }
+
+    async fn verify_code(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: &TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let code_is_valid = match self.codes.get(email) {
+            Some(entry) => entry.code.as_ref() == Some(code),
+            None => return Err(TwoFACodeStoreError::EmailNotFound),
+        };
+
+        self.verify_login_attempt(email, login_attempt_id, code_is_valid)
+            .await
+    }
+
+    async fn verify_login_attempt(
+        &mut self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code_is_valid: bool,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let entry = match self.codes.get_mut(email) {
+            Some(x) => x,
+            None => return Err(TwoFACodeStoreError::EmailNotFound),
+        };
+
+        if entry.is_expired() {
+            self.codes.remove(email);
+            return Err(TwoFACodeStoreError::CodeExpired);
+        }
+
+        if &entry.login_attempt_id == login_attempt_id && code_is_valid {
+            self.codes.remove(email);
+            return Ok(());
+        }
+
+        entry.failed_attempts += 1;
+        if entry.failed_attempts >= self.max_attempts {
+            self.codes.remove(email);
+            return Err(TwoFACodeStoreError::TooManyAttempts);
+        }
+
+        Err(TwoFACodeStoreError::IncorrectCode)
+    }
 }
 
 #[cfg(test)]
@@ -100,4 +205,116 @@ mod tests {
 
         assert_eq!(actual.is_err(), expected);
     }
+
+    fn code(s: &str) -> TwoFACode {
+        TwoFACode::parse(s.to_owned()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_should_verify_a_correct_code_only_once() {
+        let mut twofa_store = HashmapTwoFACodeStore::default();
+        let email = Email::parse("foo@example.com".to_string()).unwrap();
+        let login_attempt_id = LoginAttemptId::default();
+
+        let _ = twofa_store
+            .add_code(email.clone(), login_attempt_id.clone(), code("123456"))
+            .await;
+
+        let result = twofa_store
+            .verify_code(&email, &login_attempt_id, &code("123456"))
+            .await;
+        assert_eq!(result, Ok(()));
+
+        let result = twofa_store
+            .verify_code(&email, &login_attempt_id, &code("123456"))
+            .await;
+        assert_eq!(result, Err(TwoFACodeStoreError::EmailNotFound));
+    }
+
+    #[tokio::test]
+    async fn it_should_count_a_wrong_login_attempt_id_as_a_failure() {
+        let mut twofa_store = HashmapTwoFACodeStore::new(600, 1);
+        let email = Email::parse("foo@example.com".to_string()).unwrap();
+
+        let _ = twofa_store
+            .add_code(email.clone(), LoginAttemptId::default(), code("123456"))
+            .await;
+
+        let result = twofa_store
+            .verify_code(&email, &LoginAttemptId::default(), &code("123456"))
+            .await;
+        assert_eq!(result, Err(TwoFACodeStoreError::TooManyAttempts));
+    }
+
+    #[tokio::test]
+    async fn it_should_drop_the_code_after_too_many_wrong_guesses() {
+        let mut twofa_store = HashmapTwoFACodeStore::new(600, 3);
+        let email = Email::parse("foo@example.com".to_string()).unwrap();
+        let login_attempt_id = LoginAttemptId::default();
+
+        let _ = twofa_store
+            .add_code(email.clone(), login_attempt_id.clone(), code("123456"))
+            .await;
+
+        for _ in 0..2 {
+            let result = twofa_store
+                .verify_code(&email, &login_attempt_id, &code("000000"))
+                .await;
+            assert_eq!(result, Err(TwoFACodeStoreError::IncorrectCode));
+        }
+
+        let result = twofa_store
+            .verify_code(&email, &login_attempt_id, &code("000000"))
+            .await;
+        assert_eq!(result, Err(TwoFACodeStoreError::TooManyAttempts));
+
+        // the right code is no good once the limit has been hit
+        let result = twofa_store
+            .verify_code(&email, &login_attempt_id, &code("123456"))
+            .await;
+        assert_eq!(result, Err(TwoFACodeStoreError::EmailNotFound));
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_an_expired_code() {
+        let mut twofa_store = HashmapTwoFACodeStore::new(0, 5);
+        let email = Email::parse("foo@example.com".to_string()).unwrap();
+        let login_attempt_id = LoginAttemptId::default();
+
+        let _ = twofa_store
+            .add_code(email.clone(), login_attempt_id.clone(), code("123456"))
+            .await;
+
+        assert_eq!(
+            twofa_store.get_code(&email).await,
+            Err(TwoFACodeStoreError::CodeExpired)
+        );
+
+        let result = twofa_store
+            .verify_code(&email, &login_attempt_id, &code("123456"))
+            .await;
+        assert_eq!(result, Err(TwoFACodeStoreError::CodeExpired));
+    }
+
+    #[tokio::test]
+    async fn it_should_let_the_caller_decide_for_login_attempts_without_a_code() {
+        let mut twofa_store = HashmapTwoFACodeStore::new(600, 5);
+        let email = Email::parse("foo@example.com".to_string()).unwrap();
+        let login_attempt_id = LoginAttemptId::default();
+
+        let _ = twofa_store
+            .add_login_attempt(email.clone(), login_attempt_id.clone())
+            .await;
+
+        // there is no code to guess
+        let result = twofa_store
+            .verify_code(&email, &login_attempt_id, &code("123456"))
+            .await;
+        assert_eq!(result, Err(TwoFACodeStoreError::IncorrectCode));
+
+        let result = twofa_store
+            .verify_login_attempt(&email, &login_attempt_id, true)
+            .await;
+        assert_eq!(result, Ok(()));
+    }
 }
@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-use crate::domain::{Email, Password, User, UserStore, UserStoreError};
+use crate::domain::{
+    validate_user_password, AccountStatus, Email, HashedPassword, Password, Role, TwoFAMethod,
+    User, UserStore, UserStoreError,
+};
 
 #[derive(Debug, Default)]
 pub struct HashmapUserStore {
@@ -27,15 +30,121 @@ impl UserStore for HashmapUserStore {
     async fn validate_user(
         &self,
         email: &Email,
-        password: &Password,
+        raw_password: &Password,
     ) -> Result<(), UserStoreError> {
-        match self.users.get(email) {
+        validate_user_password(self.get_user(email).await, raw_password)
+            .await
+            .map(|_| ())
+    }
+
+    async fn upgrade_password(
+        &mut self,
+        email: &Email,
+        old: &HashedPassword,
+        new: HashedPassword,
+    ) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
             Some(user) => {
-                if user.password.eq(password) {
-                    Ok(())
-                } else {
-                    Err(UserStoreError::InvalidCredentials)
+                if user.password == *old {
+                    user.password = new;
                 }
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn set_two_fa_method(
+        &mut self,
+        email: &Email,
+        two_fa_method: TwoFAMethod,
+    ) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.two_fa_method = two_fa_method;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn set_password(
+        &mut self,
+        email: &Email,
+        password: HashedPassword,
+    ) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.password = password;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn set_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.email_verified = true;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        match self.users.remove(email) {
+            Some(_) => Ok(()),
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn bump_token_version(&mut self, email: &Email) -> Result<u64, UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.token_version += 1;
+                Ok(user.token_version)
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn list_users(
+        &self,
+        email_prefix: &str,
+        after: Option<&Email>,
+        limit: usize,
+    ) -> Result<Vec<User>, UserStoreError> {
+        let mut users: Vec<&User> = self
+            .users
+            .values()
+            .filter(|user| user.email.as_ref().starts_with(email_prefix))
+            .filter(|user| after.is_none_or(|after| user.email.as_ref() > after.as_ref()))
+            .collect();
+        users.sort_by(|a, b| a.email.as_ref().cmp(b.email.as_ref()));
+
+        Ok(users.into_iter().take(limit).cloned().collect())
+    }
+
+    async fn set_role(&mut self, email: &Email, role: Role) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.role = role;
+                Ok(())
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn set_status(
+        &mut self,
+        email: &Email,
+        status: AccountStatus,
+    ) -> Result<(), UserStoreError> {
+        match self.users.get_mut(email) {
+            Some(user) => {
+                user.status = status;
+                Ok(())
             }
             None => Err(UserStoreError::UserNotFound),
         }
@@ -45,14 +154,22 @@ impl UserStore for HashmapUserStore {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::{AccountStatusError, PasswordHashingParams};
+    use chrono::Utc;
 
     #[tokio::test]
     async fn test_add_user() {
         let mut user_store = HashmapUserStore::default();
         let user = User {
             email: Email::parse("test@example.com".to_owned()).unwrap(),
-            password: Password::parse("password".to_owned()).unwrap(),
-            requires_2fa: false,
+            password: HashedPassword::parse(Password::parse("password".to_owned()).unwrap())
+                .await
+                .unwrap(),
+            two_fa_method: TwoFAMethod::None,
+            email_verified: false,
+            token_version: 0,
+            role: Role::User,
+            status: AccountStatus::Active,
         };
 
         // Test adding a new user
@@ -71,8 +188,14 @@ mod tests {
 
         let user = User {
             email: email.clone(),
-            password: Password::parse("password".to_owned()).unwrap(),
-            requires_2fa: false,
+            password: HashedPassword::parse(Password::parse("password".to_owned()).unwrap())
+                .await
+                .unwrap(),
+            two_fa_method: TwoFAMethod::None,
+            email_verified: false,
+            token_version: 0,
+            role: Role::User,
+            status: AccountStatus::Active,
         };
 
         // Test getting a user that exists
@@ -96,8 +219,12 @@ mod tests {
 
         let user = User {
             email: email.clone(),
-            password: password.clone(),
-            requires_2fa: false,
+            password: HashedPassword::parse(password.clone()).await.unwrap(),
+            two_fa_method: TwoFAMethod::None,
+            email_verified: false,
+            token_version: 0,
+            role: Role::User,
+            status: AccountStatus::Active,
         };
 
         // Test validating a user that exists with correct password
@@ -120,4 +247,285 @@ mod tests {
 
         assert_eq!(result, Err(UserStoreError::UserNotFound));
     }
+
+    #[tokio::test]
+    async fn test_upgrade_password() {
+        let mut user_store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = Password::parse("password".to_owned()).unwrap();
+
+        let outdated_params = PasswordHashingParams {
+            memory_cost_kib: 1024,
+            time_cost: 1,
+            parallelism: 1,
+        };
+        let outdated_hash = HashedPassword::parse_with_params(password.clone(), outdated_params)
+            .await
+            .unwrap();
+        assert!(outdated_hash.needs_rehash());
+
+        let user = User::new(email.clone(), outdated_hash.clone(), TwoFAMethod::None);
+        user_store.users.insert(email.clone(), user);
+
+        let upgraded = HashedPassword::parse(password.clone()).await.unwrap();
+        let result = user_store
+            .upgrade_password(&email, &outdated_hash, upgraded.clone())
+            .await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            user_store.get_user(&email).await.unwrap().password,
+            upgraded
+        );
+
+        // The password has changed since `outdated_hash`, so it stays
+        let result = user_store
+            .upgrade_password(&email, &outdated_hash, outdated_hash.clone())
+            .await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            user_store.get_user(&email).await.unwrap().password,
+            upgraded
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_two_fa_method() {
+        let mut user_store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = HashedPassword::parse(Password::parse("password".to_owned()).unwrap())
+            .await
+            .unwrap();
+        user_store
+            .add_user(User::new(email.clone(), password, TwoFAMethod::Email))
+            .await
+            .unwrap();
+
+        let result = user_store
+            .set_two_fa_method(&email, TwoFAMethod::Totp)
+            .await;
+        assert_eq!(result, Ok(()));
+        let user = user_store.get_user(&email).await.unwrap();
+        assert_eq!(user.two_fa_method, TwoFAMethod::Totp);
+
+        let result = user_store
+            .set_two_fa_method(
+                &Email::parse("nonexistent@example.com".to_owned()).unwrap(),
+                TwoFAMethod::Totp,
+            )
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_set_email_verified() {
+        let mut user_store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = HashedPassword::parse(Password::parse("password".to_owned()).unwrap())
+            .await
+            .unwrap();
+        user_store
+            .add_user(User::new(email.clone(), password, TwoFAMethod::None))
+            .await
+            .unwrap();
+        assert!(!user_store.get_user(&email).await.unwrap().email_verified);
+
+        assert_eq!(user_store.set_email_verified(&email).await, Ok(()));
+        assert!(user_store.get_user(&email).await.unwrap().email_verified);
+
+        let result = user_store
+            .set_email_verified(&Email::parse("nonexistent@example.com".to_owned()).unwrap())
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_set_password() {
+        let mut user_store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = HashedPassword::parse(Password::parse("password".to_owned()).unwrap())
+            .await
+            .unwrap();
+        user_store
+            .add_user(User::new(email.clone(), password, TwoFAMethod::None))
+            .await
+            .unwrap();
+
+        let new_password = Password::parse("new password".to_owned()).unwrap();
+        let hash = HashedPassword::parse(new_password.clone()).await.unwrap();
+        assert_eq!(user_store.set_password(&email, hash).await, Ok(()));
+
+        let result = user_store.validate_user(&email, &new_password).await;
+        assert_eq!(result, Ok(()));
+        let old_password = Password::parse("password".to_owned()).unwrap();
+        let result = user_store.validate_user(&email, &old_password).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+
+        let hash = HashedPassword::parse(new_password).await.unwrap();
+        let result = user_store
+            .set_password(
+                &Email::parse("nonexistent@example.com".to_owned()).unwrap(),
+                hash,
+            )
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let mut user_store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let user = User::new(
+            email.clone(),
+            HashedPassword::parse(Password::parse("password".to_owned()).unwrap())
+                .await
+                .unwrap(),
+            TwoFAMethod::None,
+        );
+        user_store.add_user(user).await.unwrap();
+
+        assert_eq!(user_store.delete_user(&email).await, Ok(()));
+        assert_eq!(
+            user_store.get_user(&email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            user_store.delete_user(&email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bump_token_version() {
+        let mut user_store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let user = User::new(
+            email.clone(),
+            HashedPassword::parse(Password::parse("password".to_owned()).unwrap())
+                .await
+                .unwrap(),
+            TwoFAMethod::None,
+        );
+        user_store.add_user(user).await.unwrap();
+
+        assert_eq!(user_store.bump_token_version(&email).await, Ok(1));
+        assert_eq!(user_store.bump_token_version(&email).await, Ok(2));
+        assert_eq!(
+            user_store
+                .get_user(&email)
+                .await
+                .map(|user| user.token_version),
+            Ok(2)
+        );
+
+        let result = user_store
+            .bump_token_version(&Email::parse("nonexistent@example.com".to_owned()).unwrap())
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    async fn test_user(email: &str) -> User {
+        User::new(
+            Email::parse(email.to_owned()).unwrap(),
+            HashedPassword::parse(Password::parse("password".to_owned()).unwrap())
+                .await
+                .unwrap(),
+            TwoFAMethod::None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_list_users() {
+        let mut user_store = HashmapUserStore::default();
+        for email in [
+            "b@example.com",
+            "a@example.com",
+            "c@other.com",
+            "ab@example.com",
+        ] {
+            user_store.add_user(test_user(email).await).await.unwrap();
+        }
+        let emails = |users: Vec<User>| {
+            users
+                .into_iter()
+                .map(|user| user.email.as_ref().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let page = user_store.list_users("", None, 2).await.unwrap();
+        assert_eq!(emails(page.clone()), ["a@example.com", "ab@example.com"]);
+
+        let after = page.last().map(|user| user.email.clone());
+        let page = user_store.list_users("", after.as_ref(), 2).await.unwrap();
+        assert_eq!(emails(page), ["b@example.com", "c@other.com"]);
+
+        let page = user_store.list_users("a", None, 10).await.unwrap();
+        assert_eq!(emails(page), ["a@example.com", "ab@example.com"]);
+    }
+
+    #[tokio::test]
+    async fn test_validate_user_of_an_inactive_account() {
+        let mut user_store = HashmapUserStore::default();
+        let user = test_user("test@example.com").await;
+        user_store.add_user(user.clone()).await.unwrap();
+        let password = Password::parse("password".to_owned()).unwrap();
+        let now = Utc::now().timestamp();
+
+        let status = AccountStatus::LockedUntil(now + 60);
+        user_store.set_status(&user.email, status).await.unwrap();
+        let result = user_store.validate_user(&user.email, &password).await;
+        assert_eq!(
+            result,
+            Err(UserStoreError::AccountInactive(
+                AccountStatusError::Locked { until: now + 60 }
+            ))
+        );
+
+        // A wrong password doesn't get to learn about the lock
+        let wrong_password = Password::parse("wrongpassword".to_owned()).unwrap();
+        let result = user_store.validate_user(&user.email, &wrong_password).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+
+        let status = AccountStatus::LockedUntil(now - 1);
+        user_store.set_status(&user.email, status).await.unwrap();
+        let result = user_store.validate_user(&user.email, &password).await;
+        assert_eq!(result, Ok(()));
+
+        let status = AccountStatus::Disabled("Compromised".to_owned());
+        user_store.set_status(&user.email, status).await.unwrap();
+        let result = user_store.validate_user(&user.email, &password).await;
+        assert_eq!(
+            result,
+            Err(UserStoreError::AccountInactive(
+                AccountStatusError::Disabled {
+                    reason: "Compromised".to_owned()
+                }
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_role_and_status() {
+        let mut user_store = HashmapUserStore::default();
+        let user = test_user("test@example.com").await;
+        user_store.add_user(user.clone()).await.unwrap();
+
+        let status = AccountStatus::Disabled("Compromised".to_owned());
+        assert_eq!(user_store.set_role(&user.email, Role::Admin).await, Ok(()));
+        assert_eq!(
+            user_store.set_status(&user.email, status.clone()).await,
+            Ok(())
+        );
+        let stored = user_store.get_user(&user.email).await.unwrap();
+        assert_eq!((stored.role, stored.status), (Role::Admin, status));
+
+        let nobody = Email::parse("nonexistent@example.com".to_owned()).unwrap();
+        assert_eq!(
+            user_store.set_role(&nobody, Role::Admin).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            user_store.set_status(&nobody, AccountStatus::Active).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
 }
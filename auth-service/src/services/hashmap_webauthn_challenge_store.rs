@@ -0,0 +1,182 @@
+use chrono::Utc;
+use std::collections::HashMap;
+
+use crate::{
+    domain::{
+        Email, WebAuthnCeremony, WebAuthnChallenge, WebAuthnChallengeStore,
+        WebAuthnChallengeStoreError,
+    },
+    utils::constants::{WEBAUTHN_CHALLENGE_TTL_SECONDS, WEBAUTHN_MAX_PENDING_CHALLENGES},
+};
+
+pub struct HashmapWebAuthnChallengeStore {
+    // Challenges with the time they were issued, oldest first
+    challenges: HashMap<(Email, WebAuthnCeremony), Vec<(WebAuthnChallenge, i64)>>,
+    ttl_seconds: i64,
+    max_pending: usize,
+}
+
+impl HashmapWebAuthnChallengeStore {
+    pub fn new(ttl_seconds: i64, max_pending: usize) -> Self {
+        Self {
+            challenges: HashMap::new(),
+            ttl_seconds,
+            max_pending,
+        }
+    }
+}
+
+impl Default for HashmapWebAuthnChallengeStore {
+    fn default() -> Self {
+        Self::new(
+            WEBAUTHN_CHALLENGE_TTL_SECONDS,
+            WEBAUTHN_MAX_PENDING_CHALLENGES,
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl WebAuthnChallengeStore for HashmapWebAuthnChallengeStore {
+    async fn add_challenge(
+        &mut self,
+        email: Email,
+        ceremony: WebAuthnCeremony,
+        challenge: WebAuthnChallenge,
+    ) -> Result<(), WebAuthnChallengeStoreError> {
+        let now = Utc::now().timestamp();
+        let pending = self.challenges.entry((email, ceremony)).or_default();
+
+        pending.retain(|(_, issued_at)| now < issued_at + self.ttl_seconds);
+        if pending.len() >= self.max_pending {
+            pending.drain(..pending.len() + 1 - self.max_pending);
+        }
+        pending.push((challenge, now));
+
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &mut self,
+        email: &Email,
+        ceremony: WebAuthnCeremony,
+        challenge: &str,
+    ) -> Result<WebAuthnChallenge, WebAuthnChallengeStoreError> {
+        let key = (email.clone(), ceremony);
+        let pending = self
+            .challenges
+            .get_mut(&key)
+            .ok_or(WebAuthnChallengeStoreError::ChallengeNotFound)?;
+
+        let position = pending
+            .iter()
+            .position(|(x, _)| x.as_ref() == challenge)
+            .ok_or(WebAuthnChallengeStoreError::ChallengeNotFound)?;
+        let (challenge, issued_at) = pending.remove(position);
+        if pending.is_empty() {
+            self.challenges.remove(&key);
+        }
+
+        if Utc::now().timestamp() >= issued_at + self.ttl_seconds {
+            return Err(WebAuthnChallengeStoreError::ChallengeExpired);
+        }
+
+        Ok(challenge)
+    }
+
+    async fn delete_challenges(
+        &mut self,
+        email: &Email,
+    ) -> Result<(), WebAuthnChallengeStoreError> {
+        self.challenges.retain(|(x, _), _| x != email);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email() -> Email {
+        Email::parse("foo@example.com".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_challenge_can_only_be_taken_once() {
+        let mut store = HashmapWebAuthnChallengeStore::default();
+        let challenge = WebAuthnChallenge::default();
+        let _ = store
+            .add_challenge(email(), WebAuthnCeremony::Registration, challenge.clone())
+            .await;
+
+        assert_eq!(
+            store
+                .take_challenge(
+                    &email(),
+                    WebAuthnCeremony::Authentication,
+                    challenge.as_ref()
+                )
+                .await,
+            Err(WebAuthnChallengeStoreError::ChallengeNotFound)
+        );
+        assert_eq!(
+            store
+                .take_challenge(&email(), WebAuthnCeremony::Registration, challenge.as_ref())
+                .await,
+            Ok(challenge.clone())
+        );
+        assert_eq!(
+            store
+                .take_challenge(&email(), WebAuthnCeremony::Registration, challenge.as_ref())
+                .await,
+            Err(WebAuthnChallengeStoreError::ChallengeNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_new_challenge_leaves_earlier_ones_be() {
+        let mut store = HashmapWebAuthnChallengeStore::new(300, 2);
+        let ceremony = WebAuthnCeremony::Authentication;
+        let challenges: Vec<_> = (0..3).map(|_| WebAuthnChallenge::default()).collect();
+        for challenge in &challenges {
+            let _ = store
+                .add_challenge(email(), ceremony, challenge.clone())
+                .await;
+        }
+
+        // Only the oldest made way
+        assert_eq!(
+            store
+                .take_challenge(&email(), ceremony, challenges[0].as_ref())
+                .await,
+            Err(WebAuthnChallengeStoreError::ChallengeNotFound)
+        );
+        for challenge in &challenges[1..] {
+            assert_eq!(
+                store
+                    .take_challenge(&email(), ceremony, challenge.as_ref())
+                    .await,
+                Ok(challenge.clone())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expired_challenge_is_rejected() {
+        let mut store = HashmapWebAuthnChallengeStore::new(0, 1);
+        let challenge = WebAuthnChallenge::default();
+        let _ = store
+            .add_challenge(email(), WebAuthnCeremony::Authentication, challenge.clone())
+            .await;
+
+        assert_eq!(
+            store
+                .take_challenge(
+                    &email(),
+                    WebAuthnCeremony::Authentication,
+                    challenge.as_ref()
+                )
+                .await,
+            Err(WebAuthnChallengeStoreError::ChallengeExpired)
+        );
+    }
+}
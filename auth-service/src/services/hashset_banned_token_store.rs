@@ -1,26 +1,33 @@
-use std::collections::HashSet;
+use chrono::Utc;
+use std::collections::HashMap;
 
 use crate::domain::{BannedTokenStore, BannedTokenStoreError};
 
 #[derive(Clone, Default, Eq, PartialEq, Debug)]
 pub struct HashsetBannedTokenStore {
-    pub tokens: HashSet<String>,
+    // token -> expiry as a unix timestamp
+    pub tokens: HashMap<String, usize>,
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore {
-    async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
-        println!("in add_token");
-        println!("adding token : {:?}", token);
-        self.tokens.insert(token.clone());
-        println!("add tokens NOW {:?}", self.tokens);
+    async fn add_token(
+        &mut self,
+        token: String,
+        expires_at: usize,
+    ) -> Result<(), BannedTokenStoreError> {
+        self.tokens.insert(token, expires_at);
         Ok(())
     }
 
-    async fn get_token(&self, token: &String) -> bool {
-        println!("            tokens: {:?}", self.tokens);
-        println!("         get token: {:?}", token);
-        self.tokens.contains(token)
+    async fn get_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
+        Ok(self.tokens.contains_key(token))
+    }
+
+    async fn prune_expired(&mut self) -> Result<(), BannedTokenStoreError> {
+        let now = Utc::now().timestamp() as usize;
+        self.tokens.retain(|_, expires_at| *expires_at > now);
+        Ok(())
     }
 }
 
@@ -28,13 +35,19 @@ impl BannedTokenStore for HashsetBannedTokenStore {
 mod tests {
     use super::*;
 
+    fn in_one_hour() -> usize {
+        (Utc::now().timestamp() + 3600) as usize
+    }
+
     #[tokio::test]
     async fn test_add_token() {
         let mut banned_tokens_store = HashsetBannedTokenStore::default();
         let token = "one_used_token".to_string();
 
         // Test adding a new user
-        let result = banned_tokens_store.add_token(token.clone()).await;
+        let result = banned_tokens_store
+            .add_token(token.clone(), in_one_hour())
+            .await;
 
         assert!(result.is_ok());
     }
@@ -43,16 +56,36 @@ mod tests {
     async fn test_get_existing_token() {
         let mut banned_tokens_store = HashsetBannedTokenStore::default();
         let token = "another_used_token".to_string();
-        let _ = banned_tokens_store.add_token(token.clone()).await;
+        let _ = banned_tokens_store
+            .add_token(token.clone(), in_one_hour())
+            .await;
 
         let result = banned_tokens_store.get_token(&token).await;
-        assert_eq!(result, true);
+        assert_eq!(result, Ok(true));
     }
 
     #[tokio::test]
     async fn test_get_nonexistent_token() {
         let banned_tokens_store = HashsetBannedTokenStore::default();
-        let result = banned_tokens_store.get_token(&"foo".to_string()).await;
-        assert_eq!(result, false);
+        let result = banned_tokens_store.get_token("foo").await;
+        assert_eq!(result, Ok(false));
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired_keeps_live_tokens() {
+        let mut banned_tokens_store = HashsetBannedTokenStore::default();
+        let expired_at = (Utc::now().timestamp() - 1) as usize;
+        let _ = banned_tokens_store
+            .add_token("expired".to_string(), expired_at)
+            .await;
+        let _ = banned_tokens_store
+            .add_token("live".to_string(), in_one_hour())
+            .await;
+
+        let result = banned_tokens_store.prune_expired().await;
+        assert!(result.is_ok());
+
+        assert_eq!(banned_tokens_store.get_token("expired").await, Ok(false));
+        assert_eq!(banned_tokens_store.get_token("live").await, Ok(true));
     }
 }
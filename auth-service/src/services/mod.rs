@@ -1,4 +1,25 @@
+pub mod database;
+pub mod file_audit_sink;
+pub mod hashmap_email_token_store;
+pub mod hashmap_login_throttle_store;
+pub mod hashmap_passkey_store;
+pub mod hashmap_recovery_code_store;
+pub mod hashmap_refresh_token_store;
+pub mod hashmap_session_store;
+pub mod hashmap_totp_store;
 pub mod hashmap_two_fa_code_store;
 pub mod hashmap_user_store;
+pub mod hashmap_webauthn_challenge_store;
 pub mod hashset_banned_token_store;
 pub mod mock_email_client;
+pub mod smtp_email_client;
+pub mod sql_banned_token_store;
+pub mod sql_email_token_store;
+pub mod sql_login_throttle_store;
+pub mod sql_passkey_store;
+pub mod sql_recovery_code_store;
+pub mod sql_refresh_token_store;
+pub mod sql_session_store;
+pub mod sql_totp_store;
+pub mod sql_user_store;
+pub mod vec_audit_sink;
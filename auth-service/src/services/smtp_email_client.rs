@@ -0,0 +1,194 @@
+use lettre::{
+    message::{header::ContentType, Mailbox, MultiPart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::time::Duration;
+
+use crate::domain::{Email, EmailClient};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmtpTls {
+    // Plain text, only meant for local relays and tests
+    None,
+    // Connect in plain text and upgrade with STARTTLS, usually on port 587
+    StartTls,
+    // TLS from the first byte, usually on port 465
+    Implicit,
+}
+
+impl SmtpTls {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "starttls" => Ok(Self::StartTls),
+            "implicit" | "tls" => Ok(Self::Implicit),
+            other => Err(format!("unknown SMTP TLS mode: {}", other)),
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Self::None => 25,
+            Self::StartTls => 587,
+            Self::Implicit => 465,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub sender: String,
+    pub timeout: Duration,
+    // Extra attempts after the first one when the server fails temporarily
+    pub max_retries: u32,
+    // Wait before the first retry, doubled for every retry after that
+    pub retry_backoff: Duration,
+}
+
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+    max_retries: u32,
+    retry_backoff: Duration,
+}
+
+impl SmtpEmailClient {
+    pub fn new(settings: SmtpSettings) -> Result<Self, String> {
+        let sender = settings
+            .sender
+            .parse::<Mailbox>()
+            .map_err(|e| format!("invalid sender address: {}", e))?;
+
+        let builder = match settings.tls {
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host)
+            }
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+                    .map_err(|e| e.to_string())?
+            }
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)
+                .map_err(|e| e.to_string())?,
+        };
+
+        let mut builder = builder.port(settings.port).timeout(Some(settings.timeout));
+
+        if let (Some(username), Some(password)) = (settings.username, settings.password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+            max_retries: settings.max_retries,
+            retry_backoff: settings.retry_backoff,
+        })
+    }
+
+    fn message(
+        &self,
+        recipient: &Email,
+        subject: &str,
+    ) -> Result<lettre::message::MessageBuilder, String> {
+        let recipient = recipient
+            .as_ref()
+            .parse::<Mailbox>()
+            .map_err(|e| e.to_string())?;
+
+        Ok(Message::builder()
+            .from(self.sender.clone())
+            .to(recipient)
+            .subject(subject))
+    }
+
+    async fn send(&self, message: Message) -> Result<(), String> {
+        let mut backoff = self.retry_backoff;
+        let mut attempt = 0;
+        loop {
+            match self.transport.send(message.clone()).await {
+                Ok(_) => return Ok(()),
+                // A 5xx reply won't change on a second try, anything else
+                // (4xx replies, timeouts, dropped connections) might.
+                Err(e) if e.is_permanent() || attempt >= self.max_retries => {
+                    return Err(e.to_string())
+                }
+                Err(_) => {
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for SmtpEmailClient {
+    async fn send_email(
+        &self,
+        recipient: &Email,
+        subject: &str,
+        content: &str,
+    ) -> Result<(), String> {
+        let message = self
+            .message(recipient, subject)?
+            .header(ContentType::TEXT_PLAIN)
+            .body(content.to_owned())
+            .map_err(|e| e.to_string())?;
+
+        self.send(message).await
+    }
+
+    async fn send_html_email(
+        &self,
+        recipient: &Email,
+        subject: &str,
+        text_content: &str,
+        html_content: &str,
+    ) -> Result<(), String> {
+        let message = self
+            .message(recipient, subject)?
+            .multipart(MultiPart::alternative_plain_html(
+                text_content.to_owned(),
+                html_content.to_owned(),
+            ))
+            .map_err(|e| e.to_string())?;
+
+        self.send(message).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tls_mode() {
+        assert_eq!(SmtpTls::parse("STARTTLS"), Ok(SmtpTls::StartTls));
+        assert_eq!(SmtpTls::parse("implicit"), Ok(SmtpTls::Implicit));
+        assert_eq!(SmtpTls::parse("none"), Ok(SmtpTls::None));
+        assert!(SmtpTls::parse("ssl3").is_err());
+    }
+
+    #[test]
+    fn test_invalid_sender_is_rejected() {
+        let settings = SmtpSettings {
+            host: "localhost".to_owned(),
+            port: 25,
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            sender: "not an address".to_owned(),
+            timeout: Duration::from_secs(1),
+            max_retries: 0,
+            retry_backoff: Duration::from_millis(1),
+        };
+        assert!(SmtpEmailClient::new(settings).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use chrono::Utc;
+use sqlx::AnyPool;
+
+use crate::domain::{BannedTokenStore, BannedTokenStoreError};
+
+// BannedTokenStore backed by the same database as SqlUserStore, so logouts
+// survive a restart.
+#[derive(Debug, Clone)]
+pub struct SqlBannedTokenStore {
+    pool: AnyPool,
+}
+
+impl SqlBannedTokenStore {
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl BannedTokenStore for SqlBannedTokenStore {
+    async fn add_token(
+        &mut self,
+        token: String,
+        expires_at: usize,
+    ) -> Result<(), BannedTokenStoreError> {
+        let expires_at: i64 = expires_at
+            .try_into()
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        sqlx::query(
+            "INSERT INTO banned_tokens (token, expires_at) VALUES ($1, $2) \
+             ON CONFLICT (token) DO NOTHING",
+        )
+        .bind(token)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn get_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
+        let row = sqlx::query("SELECT token FROM banned_tokens WHERE token = $1")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(row.is_some())
+    }
+
+    async fn prune_expired(&mut self) -> Result<(), BannedTokenStoreError> {
+        sqlx::query("DELETE FROM banned_tokens WHERE expires_at <= $1")
+            .bind(Utc::now().timestamp())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::get_database_pool;
+
+    async fn test_pool() -> AnyPool {
+        let path = std::env::temp_dir().join(format!("auth-service-{}.db", uuid::Uuid::new_v4()));
+        get_database_pool(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .expect("Failed to open test database")
+    }
+
+    fn in_one_hour() -> usize {
+        (Utc::now().timestamp() + 3600) as usize
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_token() {
+        let mut banned_tokens_store = SqlBannedTokenStore::new(test_pool().await);
+
+        let result = banned_tokens_store
+            .add_token("used_token".to_string(), in_one_hour())
+            .await;
+        assert!(result.is_ok());
+
+        assert_eq!(banned_tokens_store.get_token("used_token").await, Ok(true));
+        assert_eq!(banned_tokens_store.get_token("foo").await, Ok(false));
+    }
+
+    #[tokio::test]
+    async fn test_add_token_twice() {
+        let mut banned_tokens_store = SqlBannedTokenStore::new(test_pool().await);
+
+        let _ = banned_tokens_store
+            .add_token("used_token".to_string(), in_one_hour())
+            .await;
+        let result = banned_tokens_store
+            .add_token("used_token".to_string(), in_one_hour())
+            .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tokens_survive_a_new_store() {
+        let pool = test_pool().await;
+        let mut banned_tokens_store = SqlBannedTokenStore::new(pool.clone());
+        let _ = banned_tokens_store
+            .add_token("used_token".to_string(), in_one_hour())
+            .await;
+
+        let banned_tokens_store = SqlBannedTokenStore::new(pool);
+        assert_eq!(banned_tokens_store.get_token("used_token").await, Ok(true));
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired_keeps_live_tokens() {
+        let mut banned_tokens_store = SqlBannedTokenStore::new(test_pool().await);
+        let expired_at = (Utc::now().timestamp() - 1) as usize;
+        let _ = banned_tokens_store
+            .add_token("expired".to_string(), expired_at)
+            .await;
+        let _ = banned_tokens_store
+            .add_token("live".to_string(), in_one_hour())
+            .await;
+
+        let result = banned_tokens_store.prune_expired().await;
+        assert!(result.is_ok());
+
+        assert_eq!(banned_tokens_store.get_token("expired").await, Ok(false));
+        assert_eq!(banned_tokens_store.get_token("live").await, Ok(true));
+    }
+}
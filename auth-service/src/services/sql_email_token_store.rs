@@ -0,0 +1,171 @@
+use sqlx::{AnyPool, Row};
+
+use crate::domain::{
+    Email, EmailTokenId, EmailTokenPurpose, EmailTokenStore, EmailTokenStoreError,
+};
+
+#[derive(Debug, Clone)]
+pub struct SqlEmailTokenStore {
+    pool: AnyPool,
+}
+
+impl SqlEmailTokenStore {
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTokenStore for SqlEmailTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        purpose: EmailTokenPurpose,
+        token_id: EmailTokenId,
+        sent_at: i64,
+    ) -> Result<(), EmailTokenStoreError> {
+        sqlx::query(
+            "INSERT INTO email_tokens (email, purpose, token_id, sent_at) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (email, purpose) \
+             DO UPDATE SET token_id = excluded.token_id, sent_at = excluded.sent_at",
+        )
+        .bind(email.as_ref())
+        .bind(purpose.as_ref())
+        .bind(token_id.as_ref())
+        .bind(sent_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| EmailTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn get_sent_at(
+        &self,
+        email: &Email,
+        purpose: EmailTokenPurpose,
+    ) -> Result<Option<i64>, EmailTokenStoreError> {
+        let row = sqlx::query("SELECT sent_at FROM email_tokens WHERE email = $1 AND purpose = $2")
+            .bind(email.as_ref())
+            .bind(purpose.as_ref())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| EmailTokenStoreError::UnexpectedError)?;
+
+        row.map(|row| row.try_get("sent_at"))
+            .transpose()
+            .map_err(|_| EmailTokenStoreError::UnexpectedError)
+    }
+
+    // A single DELETE, so two requests with the same link can't both succeed
+    async fn take_token(
+        &mut self,
+        email: &Email,
+        purpose: EmailTokenPurpose,
+        token_id: &EmailTokenId,
+    ) -> Result<(), EmailTokenStoreError> {
+        let result = sqlx::query(
+            "DELETE FROM email_tokens WHERE email = $1 AND purpose = $2 AND token_id = $3",
+        )
+        .bind(email.as_ref())
+        .bind(purpose.as_ref())
+        .bind(token_id.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| EmailTokenStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(EmailTokenStoreError::TokenNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_tokens(&mut self, email: &Email) -> Result<(), EmailTokenStoreError> {
+        sqlx::query("DELETE FROM email_tokens WHERE email = $1")
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| EmailTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::get_database_pool;
+
+    async fn test_store() -> SqlEmailTokenStore {
+        let path = std::env::temp_dir().join(format!("auth-service-{}.db", uuid::Uuid::new_v4()));
+        let pool = get_database_pool(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .expect("Failed to open test database");
+        SqlEmailTokenStore::new(pool)
+    }
+
+    fn email() -> Email {
+        Email::parse("foo@example.com".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_token_can_only_be_taken_once() {
+        let mut store = test_store().await;
+        let purpose = EmailTokenPurpose::VerifyEmail;
+        let token_id = EmailTokenId::default();
+        let _ = store
+            .add_token(email(), purpose, token_id.clone(), 1000)
+            .await;
+
+        assert_eq!(store.get_sent_at(&email(), purpose).await, Ok(Some(1000)));
+        assert_eq!(store.take_token(&email(), purpose, &token_id).await, Ok(()));
+        assert_eq!(
+            store.take_token(&email(), purpose, &token_id).await,
+            Err(EmailTokenStoreError::TokenNotFound)
+        );
+        assert_eq!(store.get_sent_at(&email(), purpose).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_new_token_supersedes_the_old_one() {
+        let mut store = test_store().await;
+        let purpose = EmailTokenPurpose::VerifyEmail;
+        let old_id = EmailTokenId::default();
+        let new_id = EmailTokenId::default();
+        let _ = store
+            .add_token(email(), purpose, old_id.clone(), 1000)
+            .await;
+        let _ = store
+            .add_token(email(), purpose, new_id.clone(), 2000)
+            .await;
+
+        assert_eq!(store.get_sent_at(&email(), purpose).await, Ok(Some(2000)));
+        assert_eq!(
+            store.take_token(&email(), purpose, &old_id).await,
+            Err(EmailTokenStoreError::TokenNotFound)
+        );
+        assert_eq!(store.take_token(&email(), purpose, &new_id).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_delete_tokens() {
+        let mut store = test_store().await;
+        for purpose in [
+            EmailTokenPurpose::VerifyEmail,
+            EmailTokenPurpose::ResetPassword,
+        ] {
+            let _ = store
+                .add_token(email(), purpose, EmailTokenId::default(), 1000)
+                .await;
+        }
+
+        assert_eq!(store.delete_tokens(&email()).await, Ok(()));
+        for purpose in [
+            EmailTokenPurpose::VerifyEmail,
+            EmailTokenPurpose::ResetPassword,
+        ] {
+            assert_eq!(store.get_sent_at(&email(), purpose).await, Ok(None));
+        }
+    }
+}
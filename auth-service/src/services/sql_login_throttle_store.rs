@@ -0,0 +1,203 @@
+use sqlx::{AnyPool, Row};
+
+use crate::domain::{FailedLogins, LoginThrottleStore, LoginThrottleStoreError, ThrottleKey};
+
+// LoginThrottleStore shared by every instance using the same database
+#[derive(Debug, Clone)]
+pub struct SqlLoginThrottleStore {
+    pool: AnyPool,
+}
+
+impl SqlLoginThrottleStore {
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl LoginThrottleStore for SqlLoginThrottleStore {
+    async fn get_failures(
+        &self,
+        key: &ThrottleKey,
+    ) -> Result<Option<FailedLogins>, LoginThrottleStoreError> {
+        let row = sqlx::query(
+            "SELECT failures, last_failure_at FROM login_failures WHERE throttle_key = $1",
+        )
+        .bind(key.to_key_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| LoginThrottleStoreError::UnexpectedError)?;
+
+        row.map(|row| failed_logins_from_row(&row)).transpose()
+    }
+
+    async fn record_failure(
+        &mut self,
+        key: ThrottleKey,
+        now: i64,
+        reset_after_seconds: i64,
+    ) -> Result<FailedLogins, LoginThrottleStoreError> {
+        // One statement, so failures from instances racing each other all
+        // get counted.
+        let row = sqlx::query(
+            "INSERT INTO login_failures (throttle_key, failures, last_failure_at) \
+             VALUES ($1, 1, $2) \
+             ON CONFLICT (throttle_key) DO UPDATE SET \
+             failures = CASE \
+                 WHEN excluded.last_failure_at - login_failures.last_failure_at >= $3 THEN 1 \
+                 ELSE login_failures.failures + 1 \
+             END, \
+             last_failure_at = excluded.last_failure_at \
+             RETURNING failures, last_failure_at",
+        )
+        .bind(key.to_key_string())
+        .bind(now)
+        .bind(reset_after_seconds)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| LoginThrottleStoreError::UnexpectedError)?;
+
+        failed_logins_from_row(&row)
+    }
+
+    async fn clear_failures(&mut self, key: &ThrottleKey) -> Result<(), LoginThrottleStoreError> {
+        sqlx::query("DELETE FROM login_failures WHERE throttle_key = $1")
+            .bind(key.to_key_string())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| LoginThrottleStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn forgive_failure(
+        &mut self,
+        key: &ThrottleKey,
+        failed_at: i64,
+        previous_failure_at: Option<i64>,
+    ) -> Result<(), LoginThrottleStoreError> {
+        sqlx::query(
+            "UPDATE login_failures SET failures = failures - 1, \
+             last_failure_at = CASE \
+                 WHEN last_failure_at = $2 THEN COALESCE($3, last_failure_at) \
+                 ELSE last_failure_at \
+             END \
+             WHERE throttle_key = $1 AND failures > 0",
+        )
+        .bind(key.to_key_string())
+        .bind(failed_at)
+        .bind(previous_failure_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| LoginThrottleStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn prune_expired(
+        &mut self,
+        last_failure_before: i64,
+    ) -> Result<(), LoginThrottleStoreError> {
+        sqlx::query("DELETE FROM login_failures WHERE last_failure_at < $1")
+            .bind(last_failure_before)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| LoginThrottleStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+fn failed_logins_from_row(
+    row: &sqlx::any::AnyRow,
+) -> Result<FailedLogins, LoginThrottleStoreError> {
+    let count: i64 = row
+        .try_get("failures")
+        .map_err(|_| LoginThrottleStoreError::UnexpectedError)?;
+    let last_failure_at: i64 = row
+        .try_get("last_failure_at")
+        .map_err(|_| LoginThrottleStoreError::UnexpectedError)?;
+
+    Ok(FailedLogins {
+        count: count.try_into().unwrap_or(u32::MAX),
+        last_failure_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Email;
+    use crate::services::database::get_database_pool;
+
+    async fn test_store() -> SqlLoginThrottleStore {
+        let path = std::env::temp_dir().join(format!("auth-service-{}.db", uuid::Uuid::new_v4()));
+        let pool = get_database_pool(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .expect("Failed to open test database");
+        SqlLoginThrottleStore::new(pool)
+    }
+
+    fn key() -> ThrottleKey {
+        ThrottleKey::Email(Email::parse("foo@example.com".to_string()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_record_failure_counts_up() {
+        let mut store = test_store().await;
+        let _ = store.record_failure(key(), 100, 60).await;
+        let failures = store.record_failure(key(), 110, 60).await;
+
+        let expected = FailedLogins {
+            count: 2,
+            last_failure_at: 110,
+        };
+        assert_eq!(failures, Ok(expected));
+        assert_eq!(store.get_failures(&key()).await, Ok(Some(expected)));
+    }
+
+    #[tokio::test]
+    async fn test_old_failures_are_forgotten() {
+        let mut store = test_store().await;
+        let _ = store.record_failure(key(), 100, 60).await;
+        let _ = store.record_failure(key(), 110, 60).await;
+
+        let failures = store.record_failure(key(), 170, 60).await.unwrap();
+        assert_eq!(failures.count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_clear_failures() {
+        let mut store = test_store().await;
+        let _ = store.record_failure(key(), 100, 60).await;
+        let _ = store.clear_failures(&key()).await;
+
+        assert_eq!(store.get_failures(&key()).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_forgive_failure() {
+        let mut store = test_store().await;
+        let _ = store.record_failure(key(), 100, 60).await;
+        let _ = store.record_failure(key(), 110, 60).await;
+        let _ = store.forgive_failure(&key(), 110, Some(100)).await;
+
+        let expected = FailedLogins {
+            count: 1,
+            last_failure_at: 100,
+        };
+        assert_eq!(store.get_failures(&key()).await, Ok(Some(expected)));
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired() {
+        let mut store = test_store().await;
+        let ip = ThrottleKey::Ip("127.0.0.1".parse().unwrap());
+        let _ = store.record_failure(key(), 100, 60).await;
+        let _ = store.record_failure(ip.clone(), 200, 60).await;
+
+        let _ = store.prune_expired(150).await;
+        assert_eq!(store.get_failures(&key()).await, Ok(None));
+        assert!(store.get_failures(&ip).await.unwrap().is_some());
+    }
+}
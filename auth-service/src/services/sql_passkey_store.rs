@@ -0,0 +1,192 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sqlx::{any::AnyRow, AnyPool, Row};
+
+use crate::domain::{CredentialId, Email, Passkey, PasskeyStore, PasskeyStoreError};
+
+#[derive(Debug, Clone)]
+pub struct SqlPasskeyStore {
+    pool: AnyPool,
+}
+
+impl SqlPasskeyStore {
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn passkey_from_row(row: &AnyRow) -> Result<Passkey, PasskeyStoreError> {
+    let credential_id: String = row
+        .try_get("credential_id")
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+    let public_key: String = row
+        .try_get("public_key")
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+    let sign_count: i64 = row
+        .try_get("sign_count")
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+    Ok(Passkey {
+        credential_id: CredentialId::parse(credential_id)
+            .map_err(|_| PasskeyStoreError::UnexpectedError)?,
+        public_key: URL_SAFE_NO_PAD
+            .decode(public_key)
+            .map_err(|_| PasskeyStoreError::UnexpectedError)?,
+        sign_count: sign_count
+            .try_into()
+            .map_err(|_| PasskeyStoreError::UnexpectedError)?,
+    })
+}
+
+#[async_trait::async_trait]
+impl PasskeyStore for SqlPasskeyStore {
+    async fn add_passkey(
+        &mut self,
+        email: Email,
+        passkey: Passkey,
+    ) -> Result<(), PasskeyStoreError> {
+        let result = sqlx::query(
+            "INSERT INTO passkeys (credential_id, email, public_key, sign_count) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (credential_id) DO NOTHING",
+        )
+        .bind(passkey.credential_id.as_ref())
+        .bind(email.as_ref())
+        .bind(URL_SAFE_NO_PAD.encode(&passkey.public_key))
+        .bind(i64::from(passkey.sign_count))
+        .execute(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(PasskeyStoreError::CredentialAlreadyExists);
+        }
+
+        Ok(())
+    }
+
+    async fn get_passkeys(&self, email: &Email) -> Result<Vec<Passkey>, PasskeyStoreError> {
+        let rows = sqlx::query(
+            "SELECT credential_id, public_key, sign_count FROM passkeys \
+             WHERE email = $1 ORDER BY credential_id",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        rows.iter().map(passkey_from_row).collect()
+    }
+
+    async fn update_sign_count(
+        &mut self,
+        email: &Email,
+        credential_id: &CredentialId,
+        sign_count: u32,
+    ) -> Result<(), PasskeyStoreError> {
+        let result = sqlx::query(
+            "UPDATE passkeys SET sign_count = $1 WHERE credential_id = $2 AND email = $3",
+        )
+        .bind(i64::from(sign_count))
+        .bind(credential_id.as_ref())
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(PasskeyStoreError::CredentialNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_passkeys(&mut self, email: &Email) -> Result<(), PasskeyStoreError> {
+        sqlx::query("DELETE FROM passkeys WHERE email = $1")
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| PasskeyStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::get_database_pool;
+
+    async fn test_store() -> SqlPasskeyStore {
+        let path = std::env::temp_dir().join(format!("auth-service-{}.db", uuid::Uuid::new_v4()));
+        let pool = get_database_pool(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .expect("Failed to open test database");
+        SqlPasskeyStore::new(pool)
+    }
+
+    fn email(name: &str) -> Email {
+        Email::parse(format!("{}@example.com", name)).unwrap()
+    }
+
+    fn passkey(id: &str) -> Passkey {
+        Passkey {
+            credential_id: CredentialId::parse(id.to_owned()).unwrap(),
+            public_key: vec![4; 65],
+            sign_count: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_passkeys() {
+        let mut store = test_store().await;
+        let _ = store.add_passkey(email("foo"), passkey("AAAA")).await;
+        let _ = store.add_passkey(email("foo"), passkey("BBBB")).await;
+
+        assert_eq!(
+            store.get_passkeys(&email("foo")).await,
+            Ok(vec![passkey("AAAA"), passkey("BBBB")])
+        );
+        assert_eq!(store.get_passkeys(&email("bar")).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_credential_ids_are_unique() {
+        let mut store = test_store().await;
+        let _ = store.add_passkey(email("foo"), passkey("AAAA")).await;
+
+        assert_eq!(
+            store.add_passkey(email("bar"), passkey("AAAA")).await,
+            Err(PasskeyStoreError::CredentialAlreadyExists)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_sign_count() {
+        let mut store = test_store().await;
+        let _ = store.add_passkey(email("foo"), passkey("AAAA")).await;
+        let id = passkey("AAAA").credential_id;
+
+        assert_eq!(store.update_sign_count(&email("foo"), &id, 3).await, Ok(()));
+        assert_eq!(
+            store.get_passkeys(&email("foo")).await.unwrap()[0].sign_count,
+            3
+        );
+        assert_eq!(
+            store.update_sign_count(&email("bar"), &id, 4).await,
+            Err(PasskeyStoreError::CredentialNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_passkeys() {
+        let mut store = test_store().await;
+        let _ = store.add_passkey(email("foo"), passkey("AAAA")).await;
+        let _ = store.add_passkey(email("bar"), passkey("BBBB")).await;
+
+        assert_eq!(store.delete_passkeys(&email("foo")).await, Ok(()));
+        assert_eq!(store.get_passkeys(&email("foo")).await, Ok(vec![]));
+        assert_eq!(
+            store.get_passkeys(&email("bar")).await,
+            Ok(vec![passkey("BBBB")])
+        );
+    }
+}
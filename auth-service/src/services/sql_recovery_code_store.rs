@@ -0,0 +1,181 @@
+use sqlx::{AnyPool, Row};
+
+use crate::domain::{Email, HashedRecoveryCode, RecoveryCodeStore, RecoveryCodeStoreError};
+
+#[derive(Debug, Clone)]
+pub struct SqlRecoveryCodeStore {
+    pool: AnyPool,
+}
+
+impl SqlRecoveryCodeStore {
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RecoveryCodeStore for SqlRecoveryCodeStore {
+    async fn set_codes(
+        &mut self,
+        email: Email,
+        codes: Vec<HashedRecoveryCode>,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        // Swap the whole set at once so a failure can't leave the user with
+        // half of the old codes and half of the new ones.
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        sqlx::query("DELETE FROM recovery_codes WHERE email = $1")
+            .bind(email.as_ref())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        for code in codes {
+            sqlx::query("INSERT INTO recovery_codes (email, code_hash) VALUES ($1, $2)")
+                .bind(email.as_ref())
+                .bind(code.as_ref())
+                .execute(&mut *transaction)
+                .await
+                .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| RecoveryCodeStoreError::UnexpectedError)
+    }
+
+    async fn has_code(
+        &self,
+        email: &Email,
+        code: &HashedRecoveryCode,
+    ) -> Result<bool, RecoveryCodeStoreError> {
+        let row =
+            sqlx::query("SELECT code_hash FROM recovery_codes WHERE email = $1 AND code_hash = $2")
+                .bind(email.as_ref())
+                .bind(code.as_ref())
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        Ok(row.is_some())
+    }
+
+    async fn use_code(
+        &mut self,
+        email: &Email,
+        code: &HashedRecoveryCode,
+    ) -> Result<(), RecoveryCodeStoreError> {
+        // Deleting is the check, so two requests with the same code can't
+        // both succeed.
+        let result = sqlx::query("DELETE FROM recovery_codes WHERE email = $1 AND code_hash = $2")
+            .bind(email.as_ref())
+            .bind(code.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(RecoveryCodeStoreError::CodeNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn count_codes(&self, email: &Email) -> Result<usize, RecoveryCodeStoreError> {
+        let row = sqlx::query("SELECT COUNT(*) AS count FROM recovery_codes WHERE email = $1")
+            .bind(email.as_ref())
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        let count: i64 = row
+            .try_get("count")
+            .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        count
+            .try_into()
+            .map_err(|_| RecoveryCodeStoreError::UnexpectedError)
+    }
+
+    async fn delete_codes(&mut self, email: &Email) -> Result<(), RecoveryCodeStoreError> {
+        sqlx::query("DELETE FROM recovery_codes WHERE email = $1")
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| RecoveryCodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::RecoveryCode;
+    use crate::services::database::get_database_pool;
+
+    async fn test_store() -> SqlRecoveryCodeStore {
+        let path = std::env::temp_dir().join(format!("auth-service-{}.db", uuid::Uuid::new_v4()));
+        let pool = get_database_pool(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .expect("Failed to open test database");
+        SqlRecoveryCodeStore::new(pool)
+    }
+
+    fn email() -> Email {
+        Email::parse("foo@example.com".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_code_can_only_be_used_once() {
+        let mut store = test_store().await;
+        let code = RecoveryCode::default().hash();
+        let _ = store
+            .set_codes(email(), vec![code.clone(), RecoveryCode::default().hash()])
+            .await;
+
+        assert_eq!(store.has_code(&email(), &code).await, Ok(true));
+        assert_eq!(store.use_code(&email(), &code).await, Ok(()));
+        assert_eq!(store.has_code(&email(), &code).await, Ok(false));
+        assert_eq!(
+            store.use_code(&email(), &code).await,
+            Err(RecoveryCodeStoreError::CodeNotFound)
+        );
+        assert_eq!(store.count_codes(&email()).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_set_codes_replaces_the_old_set() {
+        let mut store = test_store().await;
+        let old = RecoveryCode::default().hash();
+        let _ = store.set_codes(email(), vec![old.clone()]).await;
+        let _ = store
+            .set_codes(email(), vec![RecoveryCode::default().hash()])
+            .await;
+
+        assert_eq!(
+            store.use_code(&email(), &old).await,
+            Err(RecoveryCodeStoreError::CodeNotFound)
+        );
+        assert_eq!(store.count_codes(&email()).await, Ok(1));
+    }
+
+    #[tokio::test]
+    async fn test_delete_codes() {
+        let mut store = test_store().await;
+        let code = RecoveryCode::default().hash();
+        let _ = store.set_codes(email(), vec![code.clone()]).await;
+
+        assert_eq!(store.delete_codes(&email()).await, Ok(()));
+        assert_eq!(store.count_codes(&email()).await, Ok(0));
+        assert_eq!(
+            store.use_code(&email(), &code).await,
+            Err(RecoveryCodeStoreError::CodeNotFound)
+        );
+    }
+}
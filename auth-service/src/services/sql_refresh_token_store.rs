@@ -0,0 +1,348 @@
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use sqlx::{any::AnyRow, AnyPool, Row};
+
+use crate::domain::{Email, RefreshToken, RefreshTokenStore, RefreshTokenStoreError, SessionId};
+
+// RefreshTokenStore shared by every instance using the same database. Only
+// hashes of the tokens are stored, so a leaked table can't be used to refresh.
+#[derive(Debug, Clone)]
+pub struct SqlRefreshTokenStore {
+    pool: AnyPool,
+}
+
+impl SqlRefreshTokenStore {
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for SqlRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        email: Email,
+        session_id: SessionId,
+        token: RefreshToken,
+        expires_at: i64,
+    ) -> Result<(), RefreshTokenStoreError> {
+        sqlx::query(
+            "INSERT INTO refresh_tokens (token_hash, email, session_id, expires_at) \
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(token_hash(&token))
+        .bind(email.as_ref())
+        .bind(session_id.as_ref())
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn rotate_token(
+        &mut self,
+        token: &RefreshToken,
+        new_token: RefreshToken,
+        expires_at: i64,
+    ) -> Result<(Email, SessionId), RefreshTokenStoreError> {
+        let now = Utc::now().timestamp();
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        // Marking the token rotated is the check, so two requests with the
+        // same token can't both get a new one
+        let row = sqlx::query(
+            "UPDATE refresh_tokens SET rotated_at = $1 \
+             WHERE token_hash = $2 AND rotated_at IS NULL AND expires_at > $1 \
+             RETURNING email, session_id",
+        )
+        .bind(now)
+        .bind(token_hash(token))
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        let row = match row {
+            Some(row) => row,
+            None => {
+                transaction
+                    .rollback()
+                    .await
+                    .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+                return Err(self.rotate_failure(token, now).await);
+            }
+        };
+        let (email, session_id) = family_from_row(&row)?;
+
+        sqlx::query(
+            "INSERT INTO refresh_tokens (token_hash, email, session_id, expires_at) \
+             VALUES ($1, $2, $3, $4)",
+        )
+        .bind(token_hash(&new_token))
+        .bind(email.as_ref())
+        .bind(session_id.as_ref())
+        .bind(expires_at)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok((email, session_id))
+    }
+
+    async fn revoke_family(
+        &mut self,
+        token: &RefreshToken,
+    ) -> Result<(Email, SessionId), RefreshTokenStoreError> {
+        let row = sqlx::query("SELECT email, session_id FROM refresh_tokens WHERE token_hash = $1")
+            .bind(token_hash(token))
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?
+            .ok_or(RefreshTokenStoreError::TokenNotFound)?;
+        let (email, session_id) = family_from_row(&row)?;
+
+        self.revoke_session(&session_id).await?;
+        Ok((email, session_id))
+    }
+
+    async fn revoke_session(
+        &mut self,
+        session_id: &SessionId,
+    ) -> Result<(), RefreshTokenStoreError> {
+        sqlx::query("DELETE FROM refresh_tokens WHERE session_id = $1")
+            .bind(session_id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn revoke_user(&mut self, email: &Email) -> Result<(), RefreshTokenStoreError> {
+        sqlx::query("DELETE FROM refresh_tokens WHERE email = $1")
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn prune_expired(&mut self) -> Result<(), RefreshTokenStoreError> {
+        sqlx::query("DELETE FROM refresh_tokens WHERE expires_at <= $1")
+            .bind(Utc::now().timestamp())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+impl SqlRefreshTokenStore {
+    // Why `token` couldn't be rotated at `now`
+    async fn rotate_failure(&self, token: &RefreshToken, now: i64) -> RefreshTokenStoreError {
+        let row =
+            sqlx::query("SELECT expires_at, rotated_at FROM refresh_tokens WHERE token_hash = $1")
+                .bind(token_hash(token))
+                .fetch_optional(&self.pool)
+                .await;
+
+        let row = match row {
+            Ok(Some(row)) => row,
+            Ok(None) => return RefreshTokenStoreError::TokenNotFound,
+            Err(_) => return RefreshTokenStoreError::UnexpectedError,
+        };
+
+        let rotated_at: Option<i64> = match row.try_get("rotated_at") {
+            Ok(x) => x,
+            Err(_) => return RefreshTokenStoreError::UnexpectedError,
+        };
+        if rotated_at.is_some() {
+            return RefreshTokenStoreError::TokenReused;
+        }
+
+        let expires_at: i64 = match row.try_get("expires_at") {
+            Ok(x) => x,
+            Err(_) => return RefreshTokenStoreError::UnexpectedError,
+        };
+        if expires_at <= now {
+            let _ = sqlx::query("DELETE FROM refresh_tokens WHERE token_hash = $1")
+                .bind(token_hash(token))
+                .execute(&self.pool)
+                .await;
+            return RefreshTokenStoreError::TokenExpired;
+        }
+
+        RefreshTokenStoreError::UnexpectedError
+    }
+}
+
+// Refresh tokens are 256 random bits, so a fast unsalted hash is enough
+fn token_hash(token: &RefreshToken) -> String {
+    Sha256::digest(token.as_ref().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn family_from_row(row: &AnyRow) -> Result<(Email, SessionId), RefreshTokenStoreError> {
+    let email: String = row
+        .try_get("email")
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+    let session_id: String = row
+        .try_get("session_id")
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+    Ok((
+        Email::parse(email).map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+        SessionId::parse(session_id).map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::get_database_pool;
+
+    async fn test_store() -> SqlRefreshTokenStore {
+        let path = std::env::temp_dir().join(format!("auth-service-{}.db", uuid::Uuid::new_v4()));
+        let pool = get_database_pool(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .expect("Failed to open test database");
+        SqlRefreshTokenStore::new(pool)
+    }
+
+    fn in_one_hour() -> i64 {
+        Utc::now().timestamp() + 3600
+    }
+
+    fn email() -> Email {
+        Email::parse("foo@example.com".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rotate_token() {
+        let mut store = test_store().await;
+        let token = RefreshToken::default();
+        let session_id = SessionId::default();
+        let _ = store
+            .add_token(email(), session_id.clone(), token.clone(), in_one_hour())
+            .await;
+
+        let result = store
+            .rotate_token(&token, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result, Ok((email(), session_id)));
+
+        let result = store
+            .rotate_token(
+                &RefreshToken::default(),
+                RefreshToken::default(),
+                in_one_hour(),
+            )
+            .await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_rotate_expired_token() {
+        let mut store = test_store().await;
+        let token = RefreshToken::default();
+        let _ = store
+            .add_token(
+                email(),
+                SessionId::default(),
+                token.clone(),
+                Utc::now().timestamp() - 1,
+            )
+            .await;
+
+        let result = store
+            .rotate_token(&token, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenExpired));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_family_of_reused_token() {
+        let mut store = test_store().await;
+        let first = RefreshToken::default();
+        let second = RefreshToken::default();
+        let session_id = SessionId::default();
+        let _ = store
+            .add_token(email(), session_id.clone(), first.clone(), in_one_hour())
+            .await;
+        let _ = store
+            .rotate_token(&first, second.clone(), in_one_hour())
+            .await;
+
+        let result = store
+            .rotate_token(&first, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenReused));
+
+        let result = store.revoke_family(&first).await;
+        assert_eq!(result, Ok((email(), session_id)));
+
+        let result = store
+            .rotate_token(&second, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_user() {
+        let mut store = test_store().await;
+        let token = RefreshToken::default();
+        let someone_else = RefreshToken::default();
+        let other_email = Email::parse("bar@example.com".to_string()).unwrap();
+        let _ = store
+            .add_token(email(), SessionId::default(), token.clone(), in_one_hour())
+            .await;
+        let _ = store
+            .add_token(
+                other_email.clone(),
+                SessionId::default(),
+                someone_else.clone(),
+                in_one_hour(),
+            )
+            .await;
+
+        assert_eq!(store.revoke_user(&email()).await, Ok(()));
+
+        let result = store
+            .rotate_token(&token, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+        let result = store
+            .rotate_token(&someone_else, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result.map(|(email, _)| email), Ok(other_email));
+    }
+
+    #[tokio::test]
+    async fn test_tokens_survive_a_new_store() {
+        let mut store = test_store().await;
+        let token = RefreshToken::default();
+        let _ = store
+            .add_token(email(), SessionId::default(), token.clone(), in_one_hour())
+            .await;
+
+        let mut store = SqlRefreshTokenStore::new(store.pool.clone());
+        let result = store
+            .rotate_token(&token, RefreshToken::default(), in_one_hour())
+            .await;
+        assert_eq!(result.map(|(email, _)| email), Ok(email()));
+    }
+}
@@ -0,0 +1,271 @@
+use sqlx::{any::AnyRow, AnyPool, Row};
+
+use crate::domain::{Email, Session, SessionId, SessionStore, SessionStoreError};
+
+// SessionStore shared by every instance using the same database, so sessions
+// outlive restarts of the auth service
+#[derive(Debug, Clone)]
+pub struct SqlSessionStore {
+    pool: AnyPool,
+}
+
+impl SqlSessionStore {
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for SqlSessionStore {
+    async fn add_session(
+        &mut self,
+        email: Email,
+        session: Session,
+    ) -> Result<(), SessionStoreError> {
+        sqlx::query(
+            "INSERT INTO sessions (id, email, user_agent, ip, created_at, last_seen_at) \
+             VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(session.id.as_ref())
+        .bind(email.as_ref())
+        .bind(session.user_agent)
+        .bind(session.ip.map(|ip| ip.to_string()))
+        .bind(session.created_at)
+        .bind(session.last_seen_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn touch_session(
+        &mut self,
+        email: &Email,
+        id: &SessionId,
+        now: i64,
+    ) -> Result<(), SessionStoreError> {
+        let result = sqlx::query(
+            "UPDATE sessions SET last_seen_at = \
+             CASE WHEN last_seen_at < $1 THEN $1 ELSE last_seen_at END \
+             WHERE id = $2 AND email = $3",
+        )
+        .bind(now)
+        .bind(id.as_ref())
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(SessionStoreError::SessionNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn get_session(
+        &self,
+        email: &Email,
+        id: &SessionId,
+    ) -> Result<Session, SessionStoreError> {
+        let row = sqlx::query(
+            "SELECT id, user_agent, ip, created_at, last_seen_at FROM sessions \
+             WHERE id = $1 AND email = $2",
+        )
+        .bind(id.as_ref())
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?
+        .ok_or(SessionStoreError::SessionNotFound)?;
+
+        session_from_row(&row)
+    }
+
+    async fn get_sessions(&self, email: &Email) -> Result<Vec<Session>, SessionStoreError> {
+        let rows = sqlx::query(
+            "SELECT id, user_agent, ip, created_at, last_seen_at FROM sessions \
+             WHERE email = $1 ORDER BY created_at",
+        )
+        .bind(email.as_ref())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        rows.iter().map(session_from_row).collect()
+    }
+
+    async fn remove_session(
+        &mut self,
+        email: &Email,
+        id: &SessionId,
+    ) -> Result<(), SessionStoreError> {
+        let result = sqlx::query("DELETE FROM sessions WHERE id = $1 AND email = $2")
+            .bind(id.as_ref())
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(SessionStoreError::SessionNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn remove_sessions(&mut self, email: &Email) -> Result<(), SessionStoreError> {
+        sqlx::query("DELETE FROM sessions WHERE email = $1")
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn prune_idle(&mut self, last_seen_before: i64) -> Result<(), SessionStoreError> {
+        sqlx::query("DELETE FROM sessions WHERE last_seen_at < $1")
+            .bind(last_seen_before)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+fn session_from_row(row: &AnyRow) -> Result<Session, SessionStoreError> {
+    let id: String = row
+        .try_get("id")
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+    let user_agent: Option<String> = row
+        .try_get("user_agent")
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+    let ip: Option<String> = row
+        .try_get("ip")
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+    let created_at: i64 = row
+        .try_get("created_at")
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+    let last_seen_at: i64 = row
+        .try_get("last_seen_at")
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+    Ok(Session {
+        id: SessionId::parse(id).map_err(|_| SessionStoreError::UnexpectedError)?,
+        user_agent,
+        ip: ip
+            .map(|ip| ip.parse())
+            .transpose()
+            .map_err(|_| SessionStoreError::UnexpectedError)?,
+        created_at,
+        last_seen_at,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::get_database_pool;
+
+    async fn test_store() -> SqlSessionStore {
+        let path = std::env::temp_dir().join(format!("auth-service-{}.db", uuid::Uuid::new_v4()));
+        let pool = get_database_pool(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .expect("Failed to open test database");
+        SqlSessionStore::new(pool)
+    }
+
+    fn email(name: &str) -> Email {
+        Email::parse(format!("{}@example.com", name)).unwrap()
+    }
+
+    fn session(created_at: i64) -> Session {
+        Session::new(
+            Some("Firefox".to_owned()),
+            Some("127.0.0.1".parse().unwrap()),
+            created_at,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_listed_oldest_first() {
+        let mut store = test_store().await;
+        let (newer, older) = (session(2000), session(1000));
+        let _ = store.add_session(email("foo"), newer.clone()).await;
+        let _ = store.add_session(email("foo"), older.clone()).await;
+        let _ = store.add_session(email("bar"), session(1500)).await;
+
+        assert_eq!(
+            store.get_sessions(&email("foo")).await,
+            Ok(vec![older, newer])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_touch_session() {
+        let mut store = test_store().await;
+        let session = Session::new(None, None, 1000);
+        let _ = store.add_session(email("foo"), session.clone()).await;
+
+        assert_eq!(
+            store.touch_session(&email("foo"), &session.id, 1500).await,
+            Ok(())
+        );
+        // Never moves back
+        let _ = store.touch_session(&email("foo"), &session.id, 1200).await;
+        let session = store.get_session(&email("foo"), &session.id).await.unwrap();
+        assert_eq!(session.last_seen_at, 1500);
+        assert_eq!(session.ip, None);
+
+        assert_eq!(
+            store.touch_session(&email("bar"), &session.id, 1500).await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_removed_session_is_gone() {
+        let mut store = test_store().await;
+        let session = session(1000);
+        let _ = store.add_session(email("foo"), session.clone()).await;
+
+        assert_eq!(
+            store.remove_session(&email("bar"), &session.id).await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+        assert_eq!(
+            store.remove_session(&email("foo"), &session.id).await,
+            Ok(())
+        );
+        assert_eq!(
+            store.get_session(&email("foo"), &session.id).await,
+            Err(SessionStoreError::SessionNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_sessions_of_one_user() {
+        let mut store = test_store().await;
+        let _ = store.add_session(email("foo"), session(1000)).await;
+        let other = session(1000);
+        let _ = store.add_session(email("bar"), other.clone()).await;
+
+        assert_eq!(store.remove_sessions(&email("foo")).await, Ok(()));
+        assert_eq!(store.get_sessions(&email("foo")).await, Ok(vec![]));
+        assert_eq!(store.get_sessions(&email("bar")).await, Ok(vec![other]));
+    }
+
+    #[tokio::test]
+    async fn test_prune_idle() {
+        let mut store = test_store().await;
+        let (idle, active) = (session(1000), session(2000));
+        let _ = store.add_session(email("foo"), idle).await;
+        let _ = store.add_session(email("foo"), active.clone()).await;
+
+        assert_eq!(store.prune_idle(1500).await, Ok(()));
+        assert_eq!(store.get_sessions(&email("foo")).await, Ok(vec![active]));
+    }
+}
@@ -0,0 +1,224 @@
+use sqlx::{AnyPool, Row};
+
+use crate::domain::{Email, TotpSecret, TotpStore, TotpStoreError};
+
+// TotpStore backed by the same database as SqlUserStore. Secrets outlive any
+// single login, so they have to survive a restart.
+#[derive(Debug, Clone)]
+pub struct SqlTotpStore {
+    pool: AnyPool,
+}
+
+impl SqlTotpStore {
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool }
+    }
+
+    async fn get_column(&self, email: &Email, column: &str) -> Result<TotpSecret, TotpStoreError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} AS secret FROM totp_secrets WHERE email = $1",
+            column
+        ))
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| TotpStoreError::UnexpectedError)?
+        .ok_or(TotpStoreError::SecretNotFound)?;
+
+        let secret: Option<String> = row
+            .try_get("secret")
+            .map_err(|_| TotpStoreError::UnexpectedError)?;
+
+        match secret {
+            Some(secret) => TotpSecret::parse(secret).map_err(|_| TotpStoreError::UnexpectedError),
+            None => Err(TotpStoreError::SecretNotFound),
+        }
+    }
+}
+
+fn to_i64(step: u64) -> Result<i64, TotpStoreError> {
+    step.try_into().map_err(|_| TotpStoreError::UnexpectedError)
+}
+
+#[async_trait::async_trait]
+impl TotpStore for SqlTotpStore {
+    async fn set_pending_secret(
+        &mut self,
+        email: Email,
+        secret: TotpSecret,
+    ) -> Result<(), TotpStoreError> {
+        sqlx::query(
+            "INSERT INTO totp_secrets (email, pending_secret) VALUES ($1, $2) \
+             ON CONFLICT (email) DO UPDATE SET pending_secret = excluded.pending_secret",
+        )
+        .bind(email.as_ref())
+        .bind(secret.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| TotpStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn get_pending_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError> {
+        self.get_column(email, "pending_secret").await
+    }
+
+    async fn confirm_secret(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError> {
+        let result = sqlx::query(
+            "UPDATE totp_secrets \
+             SET secret = pending_secret, pending_secret = NULL, last_used_step = $1 \
+             WHERE email = $2 AND pending_secret IS NOT NULL",
+        )
+        .bind(to_i64(step)?)
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| TotpStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(TotpStoreError::SecretNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn get_secret(&self, email: &Email) -> Result<TotpSecret, TotpStoreError> {
+        self.get_column(email, "secret").await
+    }
+
+    async fn use_step(&mut self, email: &Email, step: u64) -> Result<(), TotpStoreError> {
+        // Check and record in one statement so two requests racing with the
+        // same code can't both get through.
+        let result = sqlx::query(
+            "UPDATE totp_secrets SET last_used_step = $1 \
+             WHERE email = $2 AND secret IS NOT NULL \
+             AND (last_used_step IS NULL OR last_used_step < $1)",
+        )
+        .bind(to_i64(step)?)
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| TotpStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            // Tell a replay apart from a user without a secret
+            self.get_secret(email).await?;
+            return Err(TotpStoreError::StepAlreadyUsed);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_secrets(&mut self, email: &Email) -> Result<(), TotpStoreError> {
+        sqlx::query("DELETE FROM totp_secrets WHERE email = $1")
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| TotpStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::database::get_database_pool;
+
+    async fn test_store() -> SqlTotpStore {
+        let path = std::env::temp_dir().join(format!("auth-service-{}.db", uuid::Uuid::new_v4()));
+        let pool = get_database_pool(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .expect("Failed to open test database");
+        SqlTotpStore::new(pool)
+    }
+
+    fn email() -> Email {
+        Email::parse("foo@example.com".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_secret_is_only_used_once_confirmed() {
+        let mut store = test_store().await;
+        let secret = TotpSecret::default();
+        let _ = store.set_pending_secret(email(), secret.clone()).await;
+
+        assert_eq!(store.get_pending_secret(&email()).await, Ok(secret.clone()));
+        assert_eq!(
+            store.get_secret(&email()).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+
+        assert_eq!(store.confirm_secret(&email(), 10).await, Ok(()));
+        assert_eq!(store.get_secret(&email()).await, Ok(secret));
+        assert_eq!(
+            store.get_pending_secret(&email()).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_new_pending_secret_keeps_the_old_one() {
+        let mut store = test_store().await;
+        let old = TotpSecret::default();
+        let _ = store.set_pending_secret(email(), old.clone()).await;
+        let _ = store.confirm_secret(&email(), 10).await;
+
+        let _ = store
+            .set_pending_secret(email(), TotpSecret::default())
+            .await;
+
+        assert_eq!(store.get_secret(&email()).await, Ok(old));
+    }
+
+    #[tokio::test]
+    async fn test_use_step_rejects_replays() {
+        let mut store = test_store().await;
+        let _ = store
+            .set_pending_secret(email(), TotpSecret::default())
+            .await;
+        let _ = store.confirm_secret(&email(), 10).await;
+
+        assert_eq!(
+            store.use_step(&email(), 10).await,
+            Err(TotpStoreError::StepAlreadyUsed)
+        );
+        assert_eq!(store.use_step(&email(), 11).await, Ok(()));
+        assert_eq!(
+            store.use_step(&email(), 11).await,
+            Err(TotpStoreError::StepAlreadyUsed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_use_step_without_a_secret() {
+        let mut store = test_store().await;
+        assert_eq!(
+            store.use_step(&email(), 10).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_secrets() {
+        let mut store = test_store().await;
+        let _ = store
+            .set_pending_secret(email(), TotpSecret::default())
+            .await;
+        let _ = store.confirm_secret(&email(), 10).await;
+        let _ = store
+            .set_pending_secret(email(), TotpSecret::default())
+            .await;
+
+        assert_eq!(store.delete_secrets(&email()).await, Ok(()));
+        assert_eq!(
+            store.get_secret(&email()).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+        assert_eq!(
+            store.get_pending_secret(&email()).await,
+            Err(TotpStoreError::SecretNotFound)
+        );
+    }
+}
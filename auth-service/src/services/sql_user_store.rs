@@ -0,0 +1,671 @@
+use chrono::Utc;
+use sqlx::{any::AnyRow, AnyPool, Row};
+
+use crate::domain::{
+    validate_user_password, AccountStatus, Email, HashedPassword, Password, Role, TwoFAMethod,
+    User, UserStore, UserStoreError,
+};
+
+const USER_COLUMNS: &str = "email, password_hash, two_fa_method, email_verified_at, \
+                            token_version, role, locked_until, disabled_reason";
+
+#[derive(Debug, Clone)]
+pub struct SqlUserStore {
+    pool: AnyPool,
+}
+
+impl SqlUserStore {
+    pub fn new(pool: AnyPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl UserStore for SqlUserStore {
+    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
+        let now = Utc::now().timestamp();
+        let email_verified_at = user.email_verified.then_some(now);
+        let (locked_until, disabled_reason) = status_columns(&user.status);
+        let token_version: i64 = user
+            .token_version
+            .try_into()
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        sqlx::query(
+            "INSERT INTO users (email, password_hash, two_fa_method, email_verified_at, \
+             token_version, role, locked_until, disabled_reason) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(user.email.as_ref())
+        .bind(user.password.as_ref())
+        .bind(user.two_fa_method.as_ref())
+        .bind(email_verified_at)
+        .bind(token_version)
+        .bind(user.role.as_ref())
+        .bind(locked_until)
+        .bind(disabled_reason)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => UserStoreError::UserAlreadyExists,
+            _ => UserStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM users WHERE email = $1",
+            USER_COLUMNS
+        ))
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        user_from_row(&row)
+    }
+
+    async fn validate_user(
+        &self,
+        email: &Email,
+        raw_password: &Password,
+    ) -> Result<(), UserStoreError> {
+        validate_user_password(self.get_user(email).await, raw_password)
+            .await
+            .map(|_| ())
+    }
+
+    async fn upgrade_password(
+        &mut self,
+        email: &Email,
+        old: &HashedPassword,
+        new: HashedPassword,
+    ) -> Result<(), UserStoreError> {
+        sqlx::query("UPDATE users SET password_hash = $1 WHERE email = $2 AND password_hash = $3")
+            .bind(new.as_ref())
+            .bind(email.as_ref())
+            .bind(old.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn set_two_fa_method(
+        &mut self,
+        email: &Email,
+        two_fa_method: TwoFAMethod,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET two_fa_method = $1 WHERE email = $2")
+            .bind(two_fa_method.as_ref())
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn set_password(
+        &mut self,
+        email: &Email,
+        password: HashedPassword,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET password_hash = $1 WHERE email = $2")
+            .bind(password.as_ref())
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    // Verifying again keeps the original timestamp
+    async fn set_email_verified(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query(
+            "UPDATE users SET email_verified_at = COALESCE(email_verified_at, $1) \
+             WHERE email = $2",
+        )
+        .bind(Utc::now().timestamp())
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn delete_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query("DELETE FROM users WHERE email = $1")
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    // Incremented in the database so concurrent bumps from other instances
+    // aren't lost
+    async fn bump_token_version(&mut self, email: &Email) -> Result<u64, UserStoreError> {
+        let row = sqlx::query(
+            "UPDATE users SET token_version = token_version + 1 WHERE email = $1 \
+             RETURNING token_version",
+        )
+        .bind(email.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        let token_version: i64 = row
+            .try_get("token_version")
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        token_version
+            .try_into()
+            .map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    // The prefix is compared with SUBSTR rather than LIKE, so `%` and `_` in
+    // it match literally. Postgres has no SUBSTR taking a bigint length, hence
+    // the cast.
+    async fn list_users(
+        &self,
+        email_prefix: &str,
+        after: Option<&Email>,
+        limit: usize,
+    ) -> Result<Vec<User>, UserStoreError> {
+        let prefix_length: i64 = email_prefix
+            .chars()
+            .count()
+            .try_into()
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+        let limit: i64 = limit
+            .try_into()
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM users WHERE SUBSTR(email, 1, CAST($1 AS INTEGER)) = $2 \
+             AND email > $3 \
+             ORDER BY email LIMIT $4",
+            USER_COLUMNS
+        ))
+        .bind(prefix_length)
+        .bind(email_prefix)
+        .bind(after.map_or("", |email| email.as_ref()))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        rows.iter().map(user_from_row).collect()
+    }
+
+    async fn set_role(&mut self, email: &Email, role: Role) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET role = $1 WHERE email = $2")
+            .bind(role.as_ref())
+            .bind(email.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn set_status(
+        &mut self,
+        email: &Email,
+        status: AccountStatus,
+    ) -> Result<(), UserStoreError> {
+        let (locked_until, disabled_reason) = status_columns(&status);
+
+        let result = sqlx::query(
+            "UPDATE users SET locked_until = $1, disabled_reason = $2 WHERE email = $3",
+        )
+        .bind(locked_until)
+        .bind(disabled_reason)
+        .bind(email.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+}
+
+// `locked_until` and `disabled_reason`, in that order
+fn status_columns(status: &AccountStatus) -> (Option<i64>, Option<&str>) {
+    match status {
+        AccountStatus::Active => (None, None),
+        AccountStatus::LockedUntil(until) => (Some(*until), None),
+        AccountStatus::Disabled(reason) => (None, Some(reason)),
+    }
+}
+
+fn user_from_row(row: &AnyRow) -> Result<User, UserStoreError> {
+    let email: String = row
+        .try_get("email")
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+    let password_hash: String = row
+        .try_get("password_hash")
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+    let two_fa_method: String = row
+        .try_get("two_fa_method")
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+    let email_verified_at: Option<i64> = row
+        .try_get("email_verified_at")
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+    let token_version: i64 = row
+        .try_get("token_version")
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+    let role: String = row
+        .try_get("role")
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+    let locked_until: Option<i64> = row
+        .try_get("locked_until")
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+    let disabled_reason: Option<String> = row
+        .try_get("disabled_reason")
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+    let status = match (disabled_reason, locked_until) {
+        (Some(reason), _) => AccountStatus::Disabled(reason),
+        (None, Some(until)) => AccountStatus::LockedUntil(until),
+        (None, None) => AccountStatus::Active,
+    };
+
+    Ok(User {
+        email_verified: email_verified_at.is_some(),
+        token_version: token_version
+            .try_into()
+            .map_err(|_| UserStoreError::UnexpectedError)?,
+        role: Role::parse(&role).map_err(|_| UserStoreError::UnexpectedError)?,
+        status,
+        ..User::new(
+            Email::parse(email).map_err(|_| UserStoreError::UnexpectedError)?,
+            HashedPassword::parse_password_hash(password_hash)
+                .map_err(|_| UserStoreError::UnexpectedError)?,
+            TwoFAMethod::parse(&two_fa_method).map_err(|_| UserStoreError::UnexpectedError)?,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{AccountStatusError, PasswordHashingParams};
+    use crate::services::database::get_database_pool;
+
+    async fn test_store() -> SqlUserStore {
+        let path = std::env::temp_dir().join(format!("auth-service-{}.db", uuid::Uuid::new_v4()));
+        let pool = get_database_pool(&format!("sqlite://{}?mode=rwc", path.display()))
+            .await
+            .expect("Failed to open test database");
+        SqlUserStore::new(pool)
+    }
+
+    async fn test_user(email: &str, password: &str) -> User {
+        User::new(
+            Email::parse(email.to_owned()).unwrap(),
+            HashedPassword::parse(Password::parse(password.to_owned()).unwrap())
+                .await
+                .unwrap(),
+            TwoFAMethod::Email,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_add_user() {
+        let mut user_store = test_store().await;
+        let user = test_user("test@example.com", "password").await;
+
+        let result = user_store.add_user(user.clone()).await;
+        assert!(result.is_ok());
+
+        let result = user_store.add_user(user).await;
+        assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
+    }
+
+    #[tokio::test]
+    async fn test_get_user() {
+        let mut user_store = test_store().await;
+        let user = test_user("test@example.com", "password").await;
+        user_store.add_user(user.clone()).await.unwrap();
+
+        let result = user_store.get_user(&user.email).await;
+        assert_eq!(result, Ok(user));
+
+        let result = user_store
+            .get_user(&Email::parse("nonexistent@example.com".to_owned()).unwrap())
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_validate_user() {
+        let mut user_store = test_store().await;
+        let user = test_user("test@example.com", "password").await;
+        user_store.add_user(user.clone()).await.unwrap();
+
+        let password = Password::parse("password".to_owned()).unwrap();
+        let result = user_store.validate_user(&user.email, &password).await;
+        assert_eq!(result, Ok(()));
+
+        let wrong_password = Password::parse("wrongpassword".to_owned()).unwrap();
+        let result = user_store.validate_user(&user.email, &wrong_password).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+
+        let result = user_store
+            .validate_user(
+                &Email::parse("nonexistent@example.com".to_owned()).unwrap(),
+                &password,
+            )
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_password() {
+        let mut user_store = test_store().await;
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = Password::parse("password".to_owned()).unwrap();
+
+        let outdated_params = PasswordHashingParams {
+            memory_cost_kib: 1024,
+            time_cost: 1,
+            parallelism: 1,
+        };
+        let outdated_hash = HashedPassword::parse_with_params(password.clone(), outdated_params)
+            .await
+            .unwrap();
+        user_store
+            .add_user(User::new(
+                email.clone(),
+                outdated_hash.clone(),
+                TwoFAMethod::None,
+            ))
+            .await
+            .unwrap();
+
+        let upgraded = HashedPassword::parse(password).await.unwrap();
+        let result = user_store
+            .upgrade_password(&email, &outdated_hash, upgraded.clone())
+            .await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            user_store.get_user(&email).await.unwrap().password,
+            upgraded
+        );
+
+        // The password has changed since `outdated_hash`, so it stays
+        let result = user_store
+            .upgrade_password(&email, &outdated_hash, outdated_hash.clone())
+            .await;
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            user_store.get_user(&email).await.unwrap().password,
+            upgraded
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_two_fa_method() {
+        let mut user_store = test_store().await;
+        let user = test_user("test@example.com", "password").await;
+        user_store.add_user(user.clone()).await.unwrap();
+
+        let result = user_store
+            .set_two_fa_method(&user.email, TwoFAMethod::Totp)
+            .await;
+        assert_eq!(result, Ok(()));
+        let stored = user_store.get_user(&user.email).await.unwrap();
+        assert_eq!(stored.two_fa_method, TwoFAMethod::Totp);
+
+        let result = user_store
+            .set_two_fa_method(
+                &Email::parse("nonexistent@example.com".to_owned()).unwrap(),
+                TwoFAMethod::Totp,
+            )
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_set_email_verified() {
+        let mut user_store = test_store().await;
+        let user = test_user("test@example.com", "password").await;
+        user_store.add_user(user.clone()).await.unwrap();
+        assert!(
+            !user_store
+                .get_user(&user.email)
+                .await
+                .unwrap()
+                .email_verified
+        );
+
+        assert_eq!(user_store.set_email_verified(&user.email).await, Ok(()));
+        assert!(
+            user_store
+                .get_user(&user.email)
+                .await
+                .unwrap()
+                .email_verified
+        );
+
+        let result = user_store
+            .set_email_verified(&Email::parse("nonexistent@example.com".to_owned()).unwrap())
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_set_password() {
+        let mut user_store = test_store().await;
+        let user = test_user("test@example.com", "password").await;
+        user_store.add_user(user.clone()).await.unwrap();
+        let email = user.email;
+
+        let new_password = Password::parse("new password".to_owned()).unwrap();
+        let hash = HashedPassword::parse(new_password.clone()).await.unwrap();
+        assert_eq!(user_store.set_password(&email, hash).await, Ok(()));
+
+        let result = user_store.validate_user(&email, &new_password).await;
+        assert_eq!(result, Ok(()));
+        let old_password = Password::parse("password".to_owned()).unwrap();
+        let result = user_store.validate_user(&email, &old_password).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+
+        let hash = HashedPassword::parse(new_password).await.unwrap();
+        let result = user_store
+            .set_password(
+                &Email::parse("nonexistent@example.com".to_owned()).unwrap(),
+                hash,
+            )
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let mut user_store = test_store().await;
+        let user = test_user("test@example.com", "password").await;
+        user_store.add_user(user.clone()).await.unwrap();
+
+        assert_eq!(user_store.delete_user(&user.email).await, Ok(()));
+        assert_eq!(
+            user_store.get_user(&user.email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            user_store.delete_user(&user.email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bump_token_version() {
+        let mut user_store = test_store().await;
+        let user = test_user("test@example.com", "password").await;
+        user_store.add_user(user.clone()).await.unwrap();
+        assert_eq!(user.token_version, 0);
+
+        assert_eq!(user_store.bump_token_version(&user.email).await, Ok(1));
+        assert_eq!(user_store.bump_token_version(&user.email).await, Ok(2));
+        assert_eq!(
+            user_store
+                .get_user(&user.email)
+                .await
+                .map(|user| user.token_version),
+            Ok(2)
+        );
+
+        let result = user_store
+            .bump_token_version(&Email::parse("nonexistent@example.com".to_owned()).unwrap())
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_list_users() {
+        let mut user_store = test_store().await;
+        for email in [
+            "b@example.com",
+            "a@example.com",
+            "c@other.com",
+            "a_b@example.com",
+        ] {
+            user_store
+                .add_user(test_user(email, "password").await)
+                .await
+                .unwrap();
+        }
+        let emails = |users: Vec<User>| {
+            users
+                .into_iter()
+                .map(|user| user.email.as_ref().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let page = user_store.list_users("", None, 2).await.unwrap();
+        assert_eq!(emails(page.clone()), ["a@example.com", "a_b@example.com"]);
+
+        let after = page.last().map(|user| user.email.clone());
+        let page = user_store.list_users("", after.as_ref(), 2).await.unwrap();
+        assert_eq!(emails(page), ["b@example.com", "c@other.com"]);
+
+        // `_` isn't a wildcard
+        let page = user_store.list_users("a_", None, 10).await.unwrap();
+        assert_eq!(emails(page), ["a_b@example.com"]);
+    }
+
+    // SQLite converts the types of bound values freely and Postgres doesn't,
+    // so the prefix filter also runs against Postgres when
+    // `TEST_POSTGRES_URL` names a database
+    #[tokio::test]
+    async fn test_list_users_by_prefix_on_postgres() {
+        let Ok(database_url) = std::env::var("TEST_POSTGRES_URL") else {
+            return;
+        };
+        let pool = get_database_pool(&database_url)
+            .await
+            .expect("Failed to open test database");
+        let mut user_store = SqlUserStore::new(pool);
+
+        // The database outlives the test, so the users are made unique
+        let prefix = format!("{}_", uuid::Uuid::new_v4().simple());
+        for name in ["a", "b"] {
+            let email = format!("{}{}@example.com", prefix, name);
+            user_store
+                .add_user(test_user(&email, "password").await)
+                .await
+                .unwrap();
+        }
+
+        let page = user_store.list_users(&prefix, None, 10).await.unwrap();
+        assert_eq!(page.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_validate_user_of_an_inactive_account() {
+        let mut user_store = test_store().await;
+        let user = test_user("test@example.com", "password").await;
+        user_store.add_user(user.clone()).await.unwrap();
+        let password = Password::parse("password".to_owned()).unwrap();
+        let now = Utc::now().timestamp();
+
+        let status = AccountStatus::LockedUntil(now + 60);
+        user_store.set_status(&user.email, status).await.unwrap();
+        let result = user_store.validate_user(&user.email, &password).await;
+        assert_eq!(
+            result,
+            Err(UserStoreError::AccountInactive(
+                AccountStatusError::Locked { until: now + 60 }
+            ))
+        );
+
+        let status = AccountStatus::LockedUntil(now - 1);
+        user_store.set_status(&user.email, status).await.unwrap();
+        let result = user_store.validate_user(&user.email, &password).await;
+        assert_eq!(result, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_set_role_and_status() {
+        let mut user_store = test_store().await;
+        let user = test_user("test@example.com", "password").await;
+        user_store.add_user(user.clone()).await.unwrap();
+
+        assert_eq!(user_store.set_role(&user.email, Role::Admin).await, Ok(()));
+        for status in [
+            AccountStatus::LockedUntil(1_700_000_000),
+            AccountStatus::Disabled("Compromised".to_owned()),
+            AccountStatus::Active,
+        ] {
+            assert_eq!(
+                user_store.set_status(&user.email, status.clone()).await,
+                Ok(())
+            );
+            let stored = user_store.get_user(&user.email).await.unwrap();
+            assert_eq!((stored.role, stored.status), (Role::Admin, status));
+        }
+
+        let nobody = Email::parse("nonexistent@example.com".to_owned()).unwrap();
+        assert_eq!(
+            user_store.set_role(&nobody, Role::Admin).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            user_store.set_status(&nobody, AccountStatus::Active).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+}
@@ -0,0 +1,53 @@
+use crate::domain::{AuditEvent, AuditRecord, AuditSink, AuditSinkError};
+
+// Keeps the audit log in memory, for tests. Nothing survives a restart and
+// nothing is ever dropped, so it's no use for a running service.
+#[derive(Default)]
+pub struct VecAuditSink {
+    records: Vec<AuditRecord>,
+}
+
+#[async_trait::async_trait]
+impl AuditSink for VecAuditSink {
+    async fn record(&mut self, event: AuditEvent, timestamp: i64) -> Result<(), AuditSinkError> {
+        let record = AuditRecord::new(event, timestamp, self.records.last());
+        self.records.push(record);
+        Ok(())
+    }
+
+    async fn get_records(&self) -> Result<Vec<AuditRecord>, AuditSinkError> {
+        Ok(self.records.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{verify_chain, AuditAction, AuditOutcome};
+
+    fn event(action: AuditAction) -> AuditEvent {
+        AuditEvent {
+            action,
+            outcome: AuditOutcome::Failure,
+            status: 401,
+            error: Some("Incorrect credentials".to_owned()),
+            email: Some("test@example.com".to_owned()),
+            actor: None,
+            ip: None,
+            user_agent: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_are_chained() {
+        let mut sink = VecAuditSink::default();
+        sink.record(event(AuditAction::Login), 1).await.unwrap();
+        sink.record(event(AuditAction::Verify2FA), 2).await.unwrap();
+
+        let records = sink.get_records().await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].event.action, AuditAction::Verify2FA);
+        assert_eq!(records[1].timestamp, 2);
+        assert_eq!(verify_chain(&records), Ok(2));
+    }
+}
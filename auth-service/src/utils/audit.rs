@@ -0,0 +1,101 @@
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuditAction, AuditEvent, AuditOutcome, AuthAPIError},
+};
+
+use super::auth::ClientInfo;
+
+// Who a request was about and who made it, as far as the route knows
+#[derive(Debug, Default)]
+pub struct AuditSubject {
+    pub email: Option<String>,
+    // Set for admin routes, where `email` is the user being managed
+    pub actor: Option<String>,
+}
+
+impl AuditSubject {
+    pub fn email(email: impl Into<String>) -> Self {
+        Self {
+            email: Some(email.into()),
+            actor: None,
+        }
+    }
+}
+
+// Turn a route's result into its response, recording what came of the
+// request in the audit log on the way
+pub async fn audit<T: IntoResponse>(
+    state: &AppState,
+    action: AuditAction,
+    client: &ClientInfo,
+    subject: AuditSubject,
+    result: Result<T, AuthAPIError>,
+) -> Response {
+    let (response, error) = match result {
+        Ok(x) => (x.into_response(), None),
+        Err(e) => {
+            let (_, message) = e.status_and_message();
+            (e.into_response(), Some(message.to_owned()))
+        }
+    };
+
+    let event = audit_event(action, client, subject, response.status().as_u16(), error);
+    record_event(state, event).await;
+
+    response
+}
+
+// Record a request that failed with `error`
+pub async fn audit_failure(
+    state: &AppState,
+    action: AuditAction,
+    client: &ClientInfo,
+    subject: AuditSubject,
+    error: &AuthAPIError,
+) {
+    let (status, message) = error.status_and_message();
+    let event = audit_event(
+        action,
+        client,
+        subject,
+        status.as_u16(),
+        Some(message.to_owned()),
+    );
+    record_event(state, event).await;
+}
+
+fn audit_event(
+    action: AuditAction,
+    client: &ClientInfo,
+    subject: AuditSubject,
+    status: u16,
+    error: Option<String>,
+) -> AuditEvent {
+    AuditEvent {
+        action,
+        outcome: AuditOutcome::from_status(status),
+        status,
+        error,
+        email: subject.email,
+        actor: subject.actor,
+        ip: client.ip,
+        user_agent: client.user_agent.clone(),
+    }
+}
+
+// The request has been handled by now, so a failed write doesn't fail it
+async fn record_event(state: &AppState, event: AuditEvent) {
+    if state
+        .audit_sink
+        .write()
+        .await
+        .record(event, Utc::now().timestamp())
+        .await
+        .is_err()
+    {
+        eprintln!("failed to write audit record");
+    }
+}
@@ -1,15 +1,86 @@
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts},
+};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
 use chrono::Utc;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
-use serde::{Deserialize, Serialize};
+use jsonwebtoken::{decode, decode_header, encode, jwk::JwkSet, Header, Validation};
+use lazy_static::lazy_static;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    env as std_env,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, RwLock as StdRwLock},
+};
 
-use crate::domain::email::Email;
+use crate::{
+    app_state::{
+        AppState, BannedtokenStoreType, RefreshTokenStoreType, SessionStoreType, UserStoreType,
+    },
+    domain::{
+        email::Email, AccountStatusError, AuditAction, AuthAPIError, EmailTokenId,
+        EmailTokenPurpose, RefreshToken, Role, Session, SessionId, SessionStoreError, User,
+        UserStoreError,
+    },
+};
 
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
+use super::{
+    audit::{audit_failure, AuditSubject},
+    constants::{
+        env, JWT_COOKIE_NAME, JWT_KEYRING_PATH, JWT_SECRET, REFRESH_TOKEN_COOKIE_NAME,
+        SESSION_TOUCH_INTERVAL_SECONDS,
+    },
+    jwt_keys::{JwtKey, JwtKeyError, JwtKeySpec},
+};
 
-// Create cookie with a new JWT auth token
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>, GenerateTokenError> {
-    let token = generate_auth_token(email)?;
+// Start a session for `email` on the client's device and issue its cookies:
+// a jwt for the session and the first refresh token of its family
+pub async fn start_session(
+    state: &AppState,
+    email: &Email,
+    client: &ClientInfo,
+) -> Result<(Cookie<'static>, Cookie<'static>), GenerateTokenError> {
+    let session = Session::new(client.user_agent.clone(), client.ip, Utc::now().timestamp());
+
+    let user = state
+        .user_store
+        .read()
+        .await
+        .get_user(email)
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    // Checked here so no way of logging in can get around it
+    user.check_status(Utc::now().timestamp())
+        .map_err(GenerateTokenError::AccountInactive)?;
+
+    let auth_cookie = generate_auth_cookie(&user, &session.id)?;
+    let refresh_cookie =
+        generate_refresh_cookie(email, &session.id, &state.refresh_token_store).await?;
+
+    state
+        .session_store
+        .write()
+        .await
+        .add_session(email.clone(), session)
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    Ok((auth_cookie, refresh_cookie))
+}
+
+// Create cookie with a new JWT auth token for the user's session
+pub fn generate_auth_cookie(
+    user: &User,
+    session_id: &SessionId,
+) -> Result<Cookie<'static>, GenerateTokenError> {
+    let token = generate_auth_token(user, session_id)?;
     Ok(create_auth_cookie(token))
 }
 
@@ -24,22 +95,82 @@ fn create_auth_cookie(token: String) -> Cookie<'static> {
     cookie
 }
 
+// Start the refresh token family of a session and wrap its first token in a
+// cookie
+async fn generate_refresh_cookie(
+    email: &Email,
+    session_id: &SessionId,
+    refresh_token_store: &RefreshTokenStoreType,
+) -> Result<Cookie<'static>, GenerateTokenError> {
+    let token = RefreshToken::default();
+
+    refresh_token_store
+        .write()
+        .await
+        .add_token(
+            email.clone(),
+            session_id.clone(),
+            token.clone(),
+            refresh_token_expiry(),
+        )
+        .await
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    Ok(create_refresh_cookie(token))
+}
+
+// Create cookie and set the value to the passed-in refresh token
+pub fn create_refresh_cookie(token: RefreshToken) -> Cookie<'static> {
+    Cookie::build((REFRESH_TOKEN_COOKIE_NAME, token.as_ref().to_owned()))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::seconds(REFRESH_TOKEN_TTL_SECONDS))
+        .build()
+}
+
+// Expiry of a refresh token issued now, as a unix timestamp
+pub fn refresh_token_expiry() -> i64 {
+    Utc::now().timestamp() + REFRESH_TOKEN_TTL_SECONDS
+}
+
 #[derive(Debug)]
 pub enum GenerateTokenError {
     TokenError(jsonwebtoken::errors::Error),
+    AccountInactive(AccountStatusError),
     UnexpectedError,
 }
 
+impl From<GenerateTokenError> for AuthAPIError {
+    fn from(error: GenerateTokenError) -> Self {
+        match error {
+            GenerateTokenError::AccountInactive(e) => e.into(),
+            GenerateTokenError::TokenError(_) | GenerateTokenError::UnexpectedError => {
+                AuthAPIError::UnexpectedError
+            }
+        }
+    }
+}
+
 // This value determines how long the JWT auth token is valid for
 pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
 
-// Create JWT auth token
-fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError> {
+// This value determines how long a refresh token can be used to get a new JWT
+pub const REFRESH_TOKEN_TTL_SECONDS: i64 = 14 * 24 * 60 * 60; // 14 days
+
+// Create JWT auth token carrying the user's current token version and role
+fn generate_auth_token(user: &User, session_id: &SessionId) -> Result<String, GenerateTokenError> {
     let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
         .ok_or(GenerateTokenError::UnexpectedError)?;
 
+    let now = Utc::now();
+    let iat: usize = now
+        .timestamp()
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
     // Create JWT expiration time
-    let exp = Utc::now()
+    let exp = now
         .checked_add_signed(delta)
         .ok_or(GenerateTokenError::UnexpectedError)?
         .timestamp();
@@ -49,49 +180,598 @@ fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError> {
         .try_into()
         .map_err(|_| GenerateTokenError::UnexpectedError)?;
 
-    let sub = email.as_ref().to_owned();
+    let sub = user.email.as_ref().to_owned();
+
+    let claims = Claims {
+        sub,
+        exp,
+        iat,
+        jti: session_id.as_ref().to_owned(),
+        ver: user.token_version,
+        role: user.role,
+    };
+
+    create_token(&claims).map_err(GenerateTokenError::TokenError)
+}
+
+// Create the token for a link emailed to the user. `token_id` lets the
+// caller make the link single use, see `EmailTokenStore`.
+pub fn generate_email_token(
+    email: &Email,
+    purpose: EmailTokenPurpose,
+    token_id: &EmailTokenId,
+    ttl_seconds: i64,
+) -> Result<String, GenerateTokenError> {
+    let exp: usize = (Utc::now().timestamp() + ttl_seconds)
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
 
-    let claims = Claims { sub, exp };
+    let claims = EmailTokenClaims {
+        sub: email.as_ref().to_owned(),
+        exp,
+        aud: purpose.as_ref().to_owned(),
+        jti: token_id.as_ref().to_owned(),
+    };
 
     create_token(&claims).map_err(GenerateTokenError::TokenError)
 }
 
-// Check if JWT auth token is valid by decoding it using the JWT secret
-pub async fn validate_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-    let x = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
-        &Validation::default(),
-    )
-    .map(|data| data.claims);
+// Check the signature, expiry and purpose of an emailed link's token. Whether
+// the link was already used is up to the caller.
+pub fn validate_email_token(
+    token: &str,
+    purpose: EmailTokenPurpose,
+) -> Result<(Email, EmailTokenId), ValidateTokenError> {
+    let claims: EmailTokenClaims = decode_token(token, &jwt_keyring(), Some(purpose.as_ref()))?;
 
-    println!("validate_token: {:?}", x);
-    x
+    let email = Email::parse(claims.sub).map_err(|_| ValidateTokenError::UnexpectedError)?;
+    let token_id =
+        EmailTokenId::parse(claims.jti).map_err(|_| ValidateTokenError::UnexpectedError)?;
+
+    Ok((email, token_id))
+}
+
+// Whose link a token belongs to, if it's one of ours, for the audit log.
+// Works on links that were already used.
+pub fn email_token_subject(token: &str, purpose: EmailTokenPurpose) -> Option<String> {
+    validate_email_token(token, purpose)
+        .ok()
+        .map(|(email, _)| email.as_ref().to_owned())
+}
+
+#[derive(Debug)]
+pub enum ValidateTokenError {
+    TokenError(jsonwebtoken::errors::Error),
+    UnknownKey,
+    BannedToken,
+    // Issued before the user's token version was bumped
+    StaleToken,
+    SessionNotFound,
+    // The user's account was locked or disabled since the token was issued
+    AccountInactive(AccountStatusError),
+    UnexpectedError,
+}
+
+// Only accept tokens signed by a key in the keyring: the `kid` picks the key
+// and the algorithm is pinned to that key's, whatever the token header claims.
+// Auth tokens have no audience and tokens with one are rejected without an
+// expected `audience`, so emailed link tokens can't be used to log in.
+fn decode_token<T: DeserializeOwned>(
+    token: &str,
+    keyring: &JwtKeyring,
+    audience: Option<&str>,
+) -> Result<T, ValidateTokenError> {
+    let header = decode_header(token).map_err(ValidateTokenError::TokenError)?;
+    let key = header
+        .kid
+        .as_deref()
+        .and_then(|kid| keyring.get(kid))
+        .ok_or(ValidateTokenError::UnknownKey)?;
+
+    let mut validation = Validation::new(key.algorithm);
+    if let Some(audience) = audience {
+        validation.set_audience(&[audience]);
+        validation.set_required_spec_claims(&["exp", "aud"]);
+    }
+
+    decode::<T>(token, key.decoding_key(), &validation)
+        .map(|data| data.claims)
+        .map_err(ValidateTokenError::TokenError)
+}
+
+// Subject of a correctly signed, unexpired token, skipping the revocation
+// check. Good enough to decide whose rate limit a request counts against.
+pub fn token_subject(token: &str) -> Option<String> {
+    decode_token::<Claims>(token, &jwt_keyring(), None)
+        .ok()
+        .map(|claims| claims.sub)
+}
+
+// Check if JWT auth token is valid by decoding it using the keyring, making
+// sure it hasn't been banned, that its user is active and it carries their
+// current token version, and that its session is still live. The session's
+// last seen time is updated on the way once it is a minute or so out of date.
+pub async fn validate_token(
+    token: &str,
+    banned_token_store: BannedtokenStoreType,
+    user_store: UserStoreType,
+    session_store: SessionStoreType,
+) -> Result<Claims, ValidateTokenError> {
+    let claims: Claims = decode_token(token, &jwt_keyring(), None)?;
+
+    match banned_token_store.read().await.get_token(token).await {
+        Ok(false) => {}
+        Ok(true) => return Err(ValidateTokenError::BannedToken),
+        Err(_) => return Err(ValidateTokenError::UnexpectedError),
+    }
+
+    let email =
+        Email::parse(claims.sub.clone()).map_err(|_| ValidateTokenError::UnexpectedError)?;
+
+    // Deleted users have no version, so their tokens are stale too. So are
+    // tokens from before a role change, which mustn't keep rights the user
+    // lost. Inactive users are told why, even though locking them out also
+    // made their tokens stale.
+    let user = match user_store.read().await.get_user(&email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Err(ValidateTokenError::StaleToken),
+        Err(_) => return Err(ValidateTokenError::UnexpectedError),
+    };
+    user.check_status(Utc::now().timestamp())
+        .map_err(ValidateTokenError::AccountInactive)?;
+    if user.token_version != claims.ver || user.role != claims.role {
+        return Err(ValidateTokenError::StaleToken);
+    }
+    let session_id =
+        SessionId::parse(claims.jti.clone()).map_err(|_| ValidateTokenError::SessionNotFound)?;
+
+    let session_error = |e| match e {
+        SessionStoreError::SessionNotFound => ValidateTokenError::SessionNotFound,
+        _ => ValidateTokenError::UnexpectedError,
+    };
+    let session = session_store
+        .read()
+        .await
+        .get_session(&email, &session_id)
+        .await
+        .map_err(session_error)?;
+
+    let now = Utc::now().timestamp();
+    if now - session.last_seen_at >= SESSION_TOUCH_INTERVAL_SECONDS {
+        session_store
+            .write()
+            .await
+            .touch_session(&email, &session_id, now)
+            .await
+            .map_err(session_error)?;
+    }
+
+    Ok(claims)
+}
+
+// Log `email` out everywhere: bump their token version, end every session
+// and revoke every refresh token they hold right now. Sessions started
+// afterwards carry the new version and aren't affected.
+pub async fn revoke_user_tokens(
+    email: &Email,
+    user_store: &UserStoreType,
+    refresh_token_store: &RefreshTokenStoreType,
+    session_store: &SessionStoreType,
+) -> Result<(), RevokeTokensError> {
+    user_store
+        .write()
+        .await
+        .bump_token_version(email)
+        .await
+        .map_err(|_| RevokeTokensError::UnexpectedError)?;
+
+    refresh_token_store
+        .write()
+        .await
+        .revoke_user(email)
+        .await
+        .map_err(|_| RevokeTokensError::UnexpectedError)?;
+
+    session_store
+        .write()
+        .await
+        .remove_sessions(email)
+        .await
+        .map_err(|_| RevokeTokensError::UnexpectedError)
 }
 
-// Create JWT auth token by encoding claims using the JWT secret
-fn create_token(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
-    encode(
-        &jsonwebtoken::Header::default(),
-        &claims,
-        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
-    )
+#[derive(Debug)]
+pub enum RevokeTokensError {
+    UnexpectedError,
+}
+
+impl From<ValidateTokenError> for AuthAPIError {
+    fn from(error: ValidateTokenError) -> Self {
+        match error {
+            ValidateTokenError::TokenError(_)
+            | ValidateTokenError::UnknownKey
+            | ValidateTokenError::BannedToken
+            | ValidateTokenError::StaleToken
+            | ValidateTokenError::SessionNotFound => AuthAPIError::InvalidToken,
+            ValidateTokenError::AccountInactive(e) => e.into(),
+            ValidateTokenError::UnexpectedError => AuthAPIError::UnexpectedError,
+        }
+    }
+}
+
+// Extractor for routes that need a logged in user. It reads the `jwt` cookie
+// and runs it through `validate_token`, so every authenticated route rejects
+// missing, invalid and revoked tokens the same way. Rejected tokens are
+// audited here, since the route never gets to run.
+#[derive(Debug)]
+pub struct AuthenticatedUser {
+    pub token: String,
+    pub claims: Claims,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthenticatedUser {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let jar = CookieJar::from_headers(&parts.headers);
+
+        let token = match jar.get(JWT_COOKIE_NAME) {
+            Some(cookie) => cookie.value().to_owned(),
+            None => return Err(AuthAPIError::MissingToken),
+        };
+
+        let claims = match validate_token(
+            &token,
+            state.banned_token_store.clone(),
+            state.user_store.clone(),
+            state.session_store.clone(),
+        )
+        .await
+        {
+            Ok(claims) => claims,
+            Err(e) => {
+                let error = e.into();
+                let client = client_info(parts).await;
+                let subject = AuditSubject {
+                    email: token_subject(&token),
+                    actor: None,
+                };
+                audit_failure(state, AuditAction::VerifyToken, &client, subject, &error).await;
+                return Err(error);
+            }
+        };
+
+        Ok(Self { token, claims })
+    }
+}
+
+// Extractor for routes only admins may use. Other logged in users are
+// refused with 403, which is audited.
+pub struct AdminUser(pub AuthenticatedUser);
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+
+        match user.claims.role {
+            Role::Admin => Ok(Self(user)),
+            Role::User => {
+                let client = client_info(parts).await;
+                let subject = AuditSubject::email(user.claims.sub);
+                let error = AuthAPIError::Forbidden;
+                audit_failure(state, AuditAction::AdminAccess, &client, subject, &error).await;
+                Err(error)
+            }
+        }
+    }
+}
+
+// Longer user agents are cut off before they are stored with a session
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+// Where a request came from, recorded with the sessions it starts
+#[derive(Clone, Debug, Default)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LENGTH).collect());
+
+        Ok(Self { ip, user_agent })
+    }
+}
+
+async fn client_info(parts: &mut Parts) -> ClientInfo {
+    match ClientInfo::from_request_parts(parts, &()).await {
+        Ok(client) => client,
+        Err(infallible) => match infallible {},
+    }
+}
+
+// Create JWT by encoding claims using the active signing key
+fn create_token<T: Serialize>(claims: &T) -> Result<String, jsonwebtoken::errors::Error> {
+    sign_token(claims, jwt_keyring().active())
+}
+
+fn sign_token<T: Serialize>(
+    claims: &T,
+    key: &JwtKey,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid.clone());
+
+    let encoding_key = key
+        .encoding_key()
+        .ok_or(jsonwebtoken::errors::ErrorKind::InvalidKeyFormat)?;
+
+    encode(&header, &claims, encoding_key)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    // Tokens from before `iat` existed count as issued at 0
+    #[serde(default)]
+    pub iat: usize,
+    // The session the token was issued for. Tokens refreshed within a
+    // session share it.
+    pub jti: String,
+    // The user's token version when the token was issued
+    pub ver: u64,
+    pub role: Role,
+}
+
+// Claims of the token in an emailed link. The audience is the link's purpose.
+#[derive(Debug, Serialize, Deserialize)]
+struct EmailTokenClaims {
+    sub: String,
+    exp: usize,
+    aud: String,
+    jti: String,
+}
+
+// The keys tokens are signed and verified with: one active key that signs new
+// tokens plus retired keys that still verify the tokens they signed, so a key
+// can be rotated without logging everybody out.
+pub struct JwtKeyring {
+    active: JwtKey,
+    verification_keys: Vec<JwtKey>,
+}
+
+#[derive(Deserialize)]
+struct JwtKeyringFile {
+    active: String,
+    keys: Vec<JwtKeySpec>,
+}
+
+impl JwtKeyring {
+    pub fn new(active: JwtKey, verification_keys: Vec<JwtKey>) -> Result<Self, JwtKeyError> {
+        if active.encoding_key().is_none() {
+            return Err(JwtKeyError::InvalidKeyring(format!(
+                "active key {} cannot sign",
+                active.kid
+            )));
+        }
+
+        let mut kids = HashSet::from([active.kid.as_str()]);
+        for key in &verification_keys {
+            if !kids.insert(key.kid.as_str()) {
+                return Err(JwtKeyError::InvalidKeyring(format!(
+                    "duplicate key id {}",
+                    key.kid
+                )));
+            }
+        }
+
+        Ok(Self {
+            active,
+            verification_keys,
+        })
+    }
+
+    // Reads the keyring file named by JWT_KEYRING_PATH, or falls back to the
+    // single key described by the JWT_* variables.
+    pub fn load() -> Result<Self, JwtKeyError> {
+        match JWT_KEYRING_PATH.as_deref() {
+            Some(path) => Self::from_file(path),
+            None => Self::from_env(),
+        }
+    }
+
+    // JSON file naming the active key and listing every key:
+    // `{"active": "2026-10", "keys": [{"kid": "2026-10", "algorithm": "EdDSA",
+    // "private_key_path": "...", "public_key_path": "..."}, ...]}`
+    pub fn from_file(path: &str) -> Result<Self, JwtKeyError> {
+        let contents = std::fs::read(path).map_err(JwtKeyError::Io)?;
+        let file: JwtKeyringFile = serde_json::from_slice(&contents)
+            .map_err(|e| JwtKeyError::InvalidKeyring(e.to_string()))?;
+
+        let mut active = None;
+        let mut verification_keys = Vec::new();
+        for spec in &file.keys {
+            let key = spec.load()?;
+            if key.kid == file.active && active.is_none() {
+                active = Some(key);
+            } else {
+                verification_keys.push(key);
+            }
+        }
+
+        let active = active.ok_or_else(|| {
+            JwtKeyError::InvalidKeyring(format!("active key {} is not listed", file.active))
+        })?;
+
+        Self::new(active, verification_keys)
+    }
+
+    // HS256 with JWT_SECRET unless JWT_ALGORITHM asks for an RS256 or EdDSA
+    // key pair, which is then read from the PEM files named by the key path
+    // variables.
+    fn from_env() -> Result<Self, JwtKeyError> {
+        let kid = std_env::var(env::JWT_KEY_ID_ENV_VAR).unwrap_or_else(|_| "default".to_owned());
+        let algorithm =
+            std_env::var(env::JWT_ALGORITHM_ENV_VAR).unwrap_or_else(|_| "HS256".to_owned());
+
+        let key = if algorithm == "HS256" {
+            JwtKey::from_secret(&kid, JWT_SECRET.as_bytes())
+        } else {
+            let read = |name: &str| {
+                std_env::var(name)
+                    .map_err(|_| JwtKeyError::InvalidKeyring(format!("{} must be set", name)))
+            };
+            JwtKey::from_pem_files(
+                &kid,
+                &algorithm,
+                &read(env::JWT_PRIVATE_KEY_PATH_ENV_VAR)?,
+                &read(env::JWT_PUBLIC_KEY_PATH_ENV_VAR)?,
+            )?
+        };
+
+        Self::new(key, Vec::new())
+    }
+
+    pub fn active(&self) -> &JwtKey {
+        &self.active
+    }
+
+    pub fn get(&self, kid: &str) -> Option<&JwtKey> {
+        std::iter::once(&self.active)
+            .chain(&self.verification_keys)
+            .find(|key| key.kid == kid)
+    }
+
+    // Public keys of every key that may still have live tokens
+    pub fn jwks(&self) -> JwkSet {
+        let keys = std::iter::once(&self.active)
+            .chain(&self.verification_keys)
+            .filter_map(|key| key.jwk().cloned())
+            .collect();
+
+        JwkSet { keys }
+    }
+}
+
+lazy_static! {
+    static ref JWT_KEYRING: StdRwLock<Arc<JwtKeyring>> = StdRwLock::new(Arc::new(
+        JwtKeyring::load().unwrap_or_else(|e| panic!("Failed to load JWT keyring: {:?}", e))
+    ));
+}
+
+pub fn jwt_keyring() -> Arc<JwtKeyring> {
+    JWT_KEYRING
+        .read()
+        .expect("JWT keyring lock poisoned")
+        .clone()
+}
+
+// Swap in a freshly loaded keyring. On error the current keyring stays in
+// place, so a bad edit to the key files can't take the service down.
+pub fn reload_jwt_keyring() -> Result<(), JwtKeyError> {
+    let keyring = JwtKeyring::load()?;
+    *JWT_KEYRING.write().expect("JWT keyring lock poisoned") = Arc::new(keyring);
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::{AccountStatus, HashedPassword, Password, TwoFAMethod, User, UserStore};
+    use crate::services::{
+        hashmap_refresh_token_store::HashmapRefreshTokenStore,
+        hashmap_session_store::HashmapSessionStore, hashmap_user_store::HashmapUserStore,
+        hashset_banned_token_store::HashsetBannedTokenStore,
+    };
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    async fn test_user(email: &Email) -> User {
+        let password = Password::parse("password".to_owned()).unwrap();
+        User::new(
+            email.clone(),
+            HashedPassword::parse(password).await.unwrap(),
+            TwoFAMethod::None,
+        )
+    }
+
+    // The stores `validate_token` checks tokens against
+    struct Stores {
+        banned_token_store: BannedtokenStoreType,
+        user_store: UserStoreType,
+        session_store: SessionStoreType,
+    }
+
+    impl Stores {
+        async fn new(emails: &[&Email]) -> Self {
+            let mut user_store = HashmapUserStore::default();
+            for email in emails {
+                user_store.add_user(test_user(email).await).await.unwrap();
+            }
+
+            Self {
+                banned_token_store: Arc::new(RwLock::new(HashsetBannedTokenStore::default())),
+                user_store: Arc::new(RwLock::new(user_store)),
+                session_store: Arc::new(RwLock::new(HashmapSessionStore::default())),
+            }
+        }
+
+        // Start a session for `email` and issue a jwt for it, like a login
+        async fn login(&self, email: &Email) -> (SessionId, String) {
+            let session = Session::new(None, None, Utc::now().timestamp());
+            let id = session.id.clone();
+            self.session_store
+                .write()
+                .await
+                .add_session(email.clone(), session)
+                .await
+                .unwrap();
+
+            let user = self.user_store.read().await.get_user(email).await.unwrap();
+            let token = generate_auth_token(&user, &id).unwrap();
+            (id, token)
+        }
+
+        async fn validate(&self, token: &str) -> Result<Claims, ValidateTokenError> {
+            validate_token(
+                token,
+                self.banned_token_store.clone(),
+                self.user_store.clone(),
+                self.session_store.clone(),
+            )
+            .await
+        }
+    }
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let user = test_user(&email).await;
+        let cookie = generate_auth_cookie(&user, &SessionId::default()).unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -113,16 +793,21 @@ mod tests {
     #[tokio::test]
     async fn test_generate_auth_token() {
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let user = test_user(&email).await;
+        let result = generate_auth_token(&user, &SessionId::default()).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let token = generate_auth_token(&email).unwrap();
-        let result = validate_token(&token).await.unwrap();
+        let stores = Stores::new(&[&email]).await;
+        let (session_id, token) = stores.login(&email).await;
+
+        let result = stores.validate(&token).await.unwrap();
         assert_eq!(result.sub, "test@example.com");
+        assert_eq!(result.jti, session_id.as_ref());
+        assert_eq!(result.ver, 0);
 
         let exp = Utc::now()
             .checked_add_signed(chrono::Duration::try_minutes(9).expect("valid duration"))
@@ -132,10 +817,377 @@ mod tests {
         assert!(result.exp > exp as usize);
     }
 
+    #[tokio::test]
+    async fn test_validate_token_only_touches_stale_sessions() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email]).await;
+        let user = stores
+            .user_store
+            .read()
+            .await
+            .get_user(&email)
+            .await
+            .unwrap();
+        let now = Utc::now().timestamp();
+
+        for (last_seen_at, touched) in [(now - 10, false), (now - 120, true)] {
+            let session = Session::new(None, None, last_seen_at);
+            let id = session.id.clone();
+            stores
+                .session_store
+                .write()
+                .await
+                .add_session(email.clone(), session)
+                .await
+                .unwrap();
+
+            let token = generate_auth_token(&user, &id).unwrap();
+            stores.validate(&token).await.unwrap();
+
+            let session = stores
+                .session_store
+                .read()
+                .await
+                .get_session(&email, &id)
+                .await
+                .unwrap();
+            assert_eq!(session.last_seen_at != last_seen_at, touched);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_of_an_ended_session() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email]).await;
+        let (session_id, token) = stores.login(&email).await;
+
+        stores
+            .session_store
+            .write()
+            .await
+            .remove_session(&email, &session_id)
+            .await
+            .unwrap();
+
+        let result = stores.validate(&token).await;
+        assert!(matches!(result, Err(ValidateTokenError::SessionNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_of_another_users_session() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let other = Email::parse("other@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email, &other]).await;
+        let (session_id, _) = stores.login(&other).await;
+        let user = stores
+            .user_store
+            .read()
+            .await
+            .get_user(&email)
+            .await
+            .unwrap();
+        let token = generate_auth_token(&user, &session_id).unwrap();
+
+        let result = stores.validate(&token).await;
+        assert!(matches!(result, Err(ValidateTokenError::SessionNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_after_token_version_bump() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email]).await;
+        let (session_id, old_token) = stores.login(&email).await;
+
+        stores
+            .user_store
+            .write()
+            .await
+            .bump_token_version(&email)
+            .await
+            .unwrap();
+
+        // The session is still live, but the token's version is outdated
+        let result = stores.validate(&old_token).await;
+        assert!(matches!(result, Err(ValidateTokenError::StaleToken)));
+
+        let user = stores
+            .user_store
+            .read()
+            .await
+            .get_user(&email)
+            .await
+            .unwrap();
+        let new_token = generate_auth_token(&user, &session_id).unwrap();
+        assert!(stores.validate(&new_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_after_role_change() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email]).await;
+        let (_, old_token) = stores.login(&email).await;
+        assert_eq!(stores.validate(&old_token).await.unwrap().role, Role::User);
+
+        stores
+            .user_store
+            .write()
+            .await
+            .set_role(&email, Role::Admin)
+            .await
+            .unwrap();
+
+        // The role in the token is outdated, so it can't be trusted either way
+        let result = stores.validate(&old_token).await;
+        assert!(matches!(result, Err(ValidateTokenError::StaleToken)));
+
+        let (_, new_token) = stores.login(&email).await;
+        assert_eq!(stores.validate(&new_token).await.unwrap().role, Role::Admin);
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_of_an_inactive_user() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email]).await;
+        let (_, token) = stores.login(&email).await;
+        let until = Utc::now().timestamp() + 60;
+
+        let status = AccountStatus::LockedUntil(until);
+        stores
+            .user_store
+            .write()
+            .await
+            .set_status(&email, status)
+            .await
+            .unwrap();
+        let locked = AccountStatusError::Locked { until };
+        let result = stores.validate(&token).await;
+        assert!(matches!(result, Err(ValidateTokenError::AccountInactive(e)) if e == locked));
+
+        // Once the lock runs out the token works again, unless it was revoked
+        let status = AccountStatus::LockedUntil(Utc::now().timestamp() - 1);
+        stores
+            .user_store
+            .write()
+            .await
+            .set_status(&email, status)
+            .await
+            .unwrap();
+        assert!(stores.validate(&token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_of_a_deleted_user() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email]).await;
+        let (_, token) = stores.login(&email).await;
+
+        stores
+            .user_store
+            .write()
+            .await
+            .delete_user(&email)
+            .await
+            .unwrap();
+
+        let result = stores.validate(&token).await;
+        assert!(matches!(result, Err(ValidateTokenError::StaleToken)));
+    }
+
+    #[tokio::test]
+    async fn test_validate_email_token() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let token_id = EmailTokenId::default();
+        let token =
+            generate_email_token(&email, EmailTokenPurpose::VerifyEmail, &token_id, 60).unwrap();
+
+        let result = validate_email_token(&token, EmailTokenPurpose::VerifyEmail).unwrap();
+        assert_eq!(result, (email, token_id));
+    }
+
+    #[tokio::test]
+    async fn test_email_tokens_and_auth_tokens_are_not_interchangeable() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email]).await;
+        let email_token = generate_email_token(
+            &email,
+            EmailTokenPurpose::VerifyEmail,
+            &EmailTokenId::default(),
+            60,
+        )
+        .unwrap();
+        let result = stores.validate(&email_token).await;
+        assert!(matches!(result, Err(ValidateTokenError::TokenError(_))));
+
+        let (_, auth_token) = stores.login(&email).await;
+        let result = validate_email_token(&auth_token, EmailTokenPurpose::VerifyEmail);
+        assert!(matches!(result, Err(ValidateTokenError::TokenError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_expired_email_token_is_rejected() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        // Past the default leeway of 60 seconds
+        let token = generate_email_token(
+            &email,
+            EmailTokenPurpose::VerifyEmail,
+            &EmailTokenId::default(),
+            -120,
+        )
+        .unwrap();
+
+        let result = validate_email_token(&token, EmailTokenPurpose::VerifyEmail);
+        assert!(matches!(result, Err(ValidateTokenError::TokenError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_user_tokens() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let other = Email::parse("other@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email, &other]).await;
+        let refresh_token_store: RefreshTokenStoreType =
+            Arc::new(RwLock::new(HashmapRefreshTokenStore::default()));
+
+        let (_, old_token) = stores.login(&email).await;
+        let (_, other_token) = stores.login(&other).await;
+
+        revoke_user_tokens(
+            &email,
+            &stores.user_store,
+            &refresh_token_store,
+            &stores.session_store,
+        )
+        .await
+        .unwrap();
+
+        let result = stores.validate(&old_token).await;
+        assert!(matches!(result, Err(ValidateTokenError::StaleToken)));
+        assert_eq!(
+            stores.session_store.read().await.get_sessions(&email).await,
+            Ok(vec![])
+        );
+
+        // Logging in again straight away works
+        let (_, new_token) = stores.login(&email).await;
+        assert!(stores.validate(&new_token).await.is_ok());
+        assert!(stores.validate(&other_token).await.is_ok());
+    }
+
+    fn claims() -> Claims {
+        Claims {
+            sub: "test@example.com".to_owned(),
+            exp: (Utc::now().timestamp() + 60) as usize,
+            iat: Utc::now().timestamp() as usize,
+            jti: SessionId::default().as_ref().to_owned(),
+            ver: 0,
+            role: Role::User,
+        }
+    }
+
+    fn rsa_key(kid: &str) -> JwtKey {
+        JwtKey::from_pem_files(
+            kid,
+            "RS256",
+            "tests/fixtures/keys/rsa_private.pem",
+            "tests/fixtures/keys/rsa_public.pem",
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_generated_token_carries_the_active_key_id() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let user = test_user(&email).await;
+        let token = generate_auth_token(&user, &SessionId::default()).unwrap();
+        let header = decode_header(&token).unwrap();
+        let keyring = jwt_keyring();
+        assert_eq!(header.kid, Some(keyring.active().kid.clone()));
+        assert_eq!(header.alg, keyring.active().algorithm);
+    }
+
+    #[tokio::test]
+    async fn test_decode_token_signed_with_asymmetric_keys() {
+        let keyring = JwtKeyring::new(rsa_key("rsa-1"), Vec::new()).unwrap();
+
+        let token = sign_token(&claims(), keyring.active()).unwrap();
+        let result: Claims = decode_token(&token, &keyring, None).unwrap();
+        assert_eq!(result.sub, "test@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_decode_token_signed_with_a_retired_key() {
+        let retired = JwtKey::from_secret("old", b"old secret");
+        let token = sign_token(&claims(), &retired).unwrap();
+
+        let keyring = JwtKeyring::new(rsa_key("new"), vec![retired]).unwrap();
+        assert!(decode_token::<Claims>(&token, &keyring, None).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_decode_token_with_unknown_key_id() {
+        let token = sign_token(&claims(), &JwtKey::from_secret("other", b"secret")).unwrap();
+
+        let keyring =
+            JwtKeyring::new(JwtKey::from_secret("current", b"secret"), Vec::new()).unwrap();
+        let result = decode_token::<Claims>(&token, &keyring, None);
+        assert!(matches!(result, Err(ValidateTokenError::UnknownKey)));
+    }
+
+    #[tokio::test]
+    async fn test_keyring_rejects_duplicate_key_ids() {
+        let result = JwtKeyring::new(
+            JwtKey::from_secret("same", b"a"),
+            vec![JwtKey::from_secret("same", b"b")],
+        );
+        assert!(matches!(result, Err(JwtKeyError::InvalidKeyring(_))));
+    }
+
+    #[tokio::test]
+    async fn test_keyring_from_file_publishes_every_public_key() {
+        let keyring = JwtKeyring::from_file("tests/fixtures/keys/keyring.json").unwrap();
+
+        assert_eq!(keyring.active().kid, "ed-2026-10");
+        assert!(keyring.get("rsa-2026-07").is_some());
+        assert!(keyring.get("hs-legacy").is_some());
+
+        let kids: Vec<_> = keyring
+            .jwks()
+            .keys
+            .into_iter()
+            .filter_map(|jwk| jwk.common.key_id)
+            .collect();
+        assert_eq!(kids, vec!["ed-2026-10", "rsa-2026-07"]);
+    }
+
+    #[tokio::test]
+    async fn test_reload_keeps_tokens_valid() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email]).await;
+        let (_, token) = stores.login(&email).await;
+
+        assert!(reload_jwt_keyring().is_ok());
+        assert!(stores.validate(&token).await.is_ok());
+    }
+
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
-        let token = "invalid_token".to_owned();
-        let result = validate_token(&token).await;
+        let stores = Stores::new(&[]).await;
+        let result = stores.validate("invalid_token").await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_validate_token_with_banned_token() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let stores = Stores::new(&[&email]).await;
+        let (_, token) = stores.login(&email).await;
+        let _ = stores
+            .banned_token_store
+            .write()
+            .await
+            .add_token(token.clone(), usize::MAX)
+            .await;
+
+        let result = stores.validate(&token).await;
+        assert!(matches!(result, Err(ValidateTokenError::BannedToken)));
+    }
 }
@@ -2,9 +2,32 @@ use dotenvy::dotenv;
 use lazy_static::lazy_static;
 use std::env as std_env;
 
+use std::time::Duration;
+
+use crate::{
+    domain::{Email, PasswordHashingParams, RelyingParty, ThrottlePolicy},
+    services::smtp_email_client::{SmtpSettings, SmtpTls},
+};
+
+use super::{
+    emails::TwoFAEmailSettings,
+    rate_limit::{RateLimit, RateLimits},
+};
+
 // Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
 lazy_static! {
     pub static ref JWT_SECRET: String = set_token();
+    pub static ref JWT_KEYRING_PATH: Option<String> = set_jwt_keyring_path();
+    pub static ref PASSWORD_HASHING_PARAMS: PasswordHashingParams = set_password_hashing_params();
+    pub static ref DATABASE_URL: Option<String> = set_database_url();
+    pub static ref SMTP_SETTINGS: Option<SmtpSettings> = set_smtp_settings();
+    pub static ref PRODUCT_NAME: String = set_product_name();
+    pub static ref TWO_FA_EMAIL_SETTINGS: TwoFAEmailSettings = set_two_fa_email_settings();
+    pub static ref WEBAUTHN_RELYING_PARTY: RelyingParty = set_webauthn_relying_party();
+    pub static ref RATE_LIMITS: RateLimits = set_rate_limits();
+    pub static ref EMAIL_LINK_BASE_URL: String = set_email_link_base_url();
+    pub static ref ADMIN_EMAILS: Vec<Email> = set_admin_emails();
+    pub static ref AUDIT_LOG_PATH: String = set_audit_log_path();
 }
 
 fn set_token() -> String {
@@ -18,11 +41,268 @@ fn set_token() -> String {
     secret
 }
 
+// Each cost falls back to the default when its variable is unset.
+fn set_password_hashing_params() -> PasswordHashingParams {
+    dotenv().ok();
+    let defaults = PasswordHashingParams::default();
+
+    let read = |name: &str, default: u32| match std_env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a positive integer.", name)),
+        Err(_) => default,
+    };
+
+    PasswordHashingParams {
+        memory_cost_kib: read(env::ARGON2_MEMORY_COST_ENV_VAR, defaults.memory_cost_kib),
+        time_cost: read(env::ARGON2_TIME_COST_ENV_VAR, defaults.time_cost),
+        parallelism: read(env::ARGON2_PARALLELISM_ENV_VAR, defaults.parallelism),
+    }
+}
+
+// Without a keyring file the service signs with the single key described by
+// the JWT_ALGORITHM/JWT_KEY_ID/JWT_*_KEY_PATH variables.
+fn set_jwt_keyring_path() -> Option<String> {
+    dotenv().ok();
+    std_env::var(env::JWT_KEYRING_PATH_ENV_VAR)
+        .ok()
+        .filter(|path| !path.is_empty())
+}
+
+// Users are kept in memory unless a database is configured.
+fn set_database_url() -> Option<String> {
+    dotenv().ok();
+    std_env::var(env::DATABASE_URL_ENV_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+}
+
+// Emails are only printed unless an SMTP host is configured.
+fn set_smtp_settings() -> Option<SmtpSettings> {
+    dotenv().ok();
+    let host = std_env::var(env::SMTP_HOST_ENV_VAR)
+        .ok()
+        .filter(|host| !host.is_empty())?;
+
+    let tls = match std_env::var(env::SMTP_TLS_ENV_VAR) {
+        Ok(value) => SmtpTls::parse(&value).unwrap_or_else(|e| panic!("{}", e)),
+        Err(_) => SmtpTls::StartTls,
+    };
+
+    let read = |name: &str, default: u64| match std_env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a positive integer.", name)),
+        Err(_) => default,
+    };
+
+    Some(SmtpSettings {
+        host,
+        port: read(env::SMTP_PORT_ENV_VAR, tls.default_port().into())
+            .try_into()
+            .expect("SMTP_PORT must be a valid port."),
+        tls,
+        username: std_env::var(env::SMTP_USERNAME_ENV_VAR).ok(),
+        password: std_env::var(env::SMTP_PASSWORD_ENV_VAR).ok(),
+        sender: std_env::var(env::SMTP_SENDER_ENV_VAR)
+            .expect("SMTP_SENDER must be set when SMTP_HOST is."),
+        timeout: Duration::from_secs(read(env::SMTP_TIMEOUT_SECONDS_ENV_VAR, 10)),
+        max_retries: read(env::SMTP_MAX_RETRIES_ENV_VAR, 3)
+            .try_into()
+            .expect("SMTP_MAX_RETRIES is too large."),
+        retry_backoff: Duration::from_millis(500),
+    })
+}
+
+// Shown to users in emails and as the issuer in authenticator apps
+fn set_product_name() -> String {
+    dotenv().ok();
+    std_env::var(env::PRODUCT_NAME_ENV_VAR).unwrap_or_else(|_| "Auth Service".to_owned())
+}
+
+// The subject defaults to one naming the product. An empty expiry notice
+// leaves the notice out of the email.
+fn set_two_fa_email_settings() -> TwoFAEmailSettings {
+    dotenv().ok();
+    let product_name = PRODUCT_NAME.clone();
+    let subject = std_env::var(env::TWO_FA_EMAIL_SUBJECT_ENV_VAR)
+        .unwrap_or_else(|_| format!("Your {} login code", product_name));
+    let expiry_notice = match std_env::var(env::TWO_FA_EMAIL_EXPIRY_NOTICE_ENV_VAR) {
+        Ok(notice) if notice.is_empty() => None,
+        Ok(notice) => Some(notice),
+        Err(_) => Some(format!(
+            "The code expires in {} minutes.",
+            TWO_FA_CODE_TTL_SECONDS / 60
+        )),
+    };
+
+    TwoFAEmailSettings {
+        subject,
+        product_name,
+        expiry_notice,
+    }
+}
+
+// Passkeys are bound to the site's domain. The defaults suit running locally.
+fn set_webauthn_relying_party() -> RelyingParty {
+    dotenv().ok();
+    RelyingParty {
+        id: std_env::var(env::WEBAUTHN_RP_ID_ENV_VAR).unwrap_or_else(|_| "localhost".to_owned()),
+        name: PRODUCT_NAME.clone(),
+        origin: std_env::var(env::WEBAUTHN_ORIGIN_ENV_VAR)
+            .unwrap_or_else(|_| "http://localhost:3000".to_owned()),
+    }
+}
+
+// Each limit is "<requests>/<window seconds>[,burst=<n>]". /verify_token is
+// called by other services on every request they handle, so it gets the most.
+fn set_rate_limits() -> RateLimits {
+    dotenv().ok();
+    let read = |name: &str, default: &str| {
+        let value = std_env::var(name).unwrap_or_else(|_| default.to_owned());
+        RateLimit::parse(&value).unwrap_or_else(|e| panic!("{}: {}", name, e))
+    };
+
+    RateLimits {
+        signup: read(env::RATE_LIMIT_SIGNUP_ENV_VAR, "5/60,burst=10"),
+        login: read(env::RATE_LIMIT_LOGIN_ENV_VAR, "20/60"),
+        verify_2fa: read(env::RATE_LIMIT_VERIFY_2FA_ENV_VAR, "10/60"),
+        verify_token: read(env::RATE_LIMIT_VERIFY_TOKEN_ENV_VAR, "600/60,burst=200"),
+        session: read(env::RATE_LIMIT_SESSION_ENV_VAR, "60/60"),
+        account: read(env::RATE_LIMIT_ACCOUNT_ENV_VAR, "30/60"),
+    }
+}
+
+// Where users reach this service, used to build the links in emails. The
+// default suits running locally.
+fn set_email_link_base_url() -> String {
+    dotenv().ok();
+    std_env::var(env::EMAIL_LINK_BASE_URL_ENV_VAR)
+        .unwrap_or_else(|_| "http://localhost:3000".to_owned())
+        .trim_end_matches('/')
+        .to_owned()
+}
+
+// Comma separated. Nobody is an admin by default.
+fn set_admin_emails() -> Vec<Email> {
+    dotenv().ok();
+    std_env::var(env::ADMIN_EMAILS_ENV_VAR)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|email| !email.is_empty())
+        .map(|email| {
+            Email::parse(email.to_owned()).unwrap_or_else(|_| {
+                panic!("{} must list email addresses.", env::ADMIN_EMAILS_ENV_VAR)
+            })
+        })
+        .collect()
+}
+
+// The audit log has to survive restarts to be any use as a trail, so there
+// is no in-memory fallback.
+fn set_audit_log_path() -> String {
+    dotenv().ok();
+    let path = std_env::var(env::AUDIT_LOG_PATH_ENV_VAR).expect("AUDIT_LOG_PATH must be set.");
+    if path.is_empty() {
+        panic!("AUDIT_LOG_PATH must not be empty.");
+    }
+    path
+}
+
 pub mod env {
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
+    pub const JWT_ALGORITHM_ENV_VAR: &str = "JWT_ALGORITHM";
+    pub const JWT_KEY_ID_ENV_VAR: &str = "JWT_KEY_ID";
+    pub const JWT_PRIVATE_KEY_PATH_ENV_VAR: &str = "JWT_PRIVATE_KEY_PATH";
+    pub const JWT_PUBLIC_KEY_PATH_ENV_VAR: &str = "JWT_PUBLIC_KEY_PATH";
+    pub const JWT_KEYRING_PATH_ENV_VAR: &str = "JWT_KEYRING_PATH";
+    pub const ARGON2_MEMORY_COST_ENV_VAR: &str = "ARGON2_MEMORY_COST_KIB";
+    pub const ARGON2_TIME_COST_ENV_VAR: &str = "ARGON2_TIME_COST";
+    pub const ARGON2_PARALLELISM_ENV_VAR: &str = "ARGON2_PARALLELISM";
+    pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
+    pub const SMTP_HOST_ENV_VAR: &str = "SMTP_HOST";
+    pub const SMTP_PORT_ENV_VAR: &str = "SMTP_PORT";
+    pub const SMTP_TLS_ENV_VAR: &str = "SMTP_TLS";
+    pub const SMTP_USERNAME_ENV_VAR: &str = "SMTP_USERNAME";
+    pub const SMTP_PASSWORD_ENV_VAR: &str = "SMTP_PASSWORD";
+    pub const SMTP_SENDER_ENV_VAR: &str = "SMTP_SENDER";
+    pub const SMTP_TIMEOUT_SECONDS_ENV_VAR: &str = "SMTP_TIMEOUT_SECONDS";
+    pub const SMTP_MAX_RETRIES_ENV_VAR: &str = "SMTP_MAX_RETRIES";
+    pub const PRODUCT_NAME_ENV_VAR: &str = "PRODUCT_NAME";
+    pub const TWO_FA_EMAIL_SUBJECT_ENV_VAR: &str = "TWO_FA_EMAIL_SUBJECT";
+    pub const TWO_FA_EMAIL_EXPIRY_NOTICE_ENV_VAR: &str = "TWO_FA_EMAIL_EXPIRY_NOTICE";
+    pub const WEBAUTHN_RP_ID_ENV_VAR: &str = "WEBAUTHN_RP_ID";
+    pub const WEBAUTHN_ORIGIN_ENV_VAR: &str = "WEBAUTHN_ORIGIN";
+    pub const RATE_LIMIT_SIGNUP_ENV_VAR: &str = "RATE_LIMIT_SIGNUP";
+    pub const RATE_LIMIT_LOGIN_ENV_VAR: &str = "RATE_LIMIT_LOGIN";
+    pub const RATE_LIMIT_VERIFY_2FA_ENV_VAR: &str = "RATE_LIMIT_VERIFY_2FA";
+    pub const RATE_LIMIT_VERIFY_TOKEN_ENV_VAR: &str = "RATE_LIMIT_VERIFY_TOKEN";
+    pub const RATE_LIMIT_SESSION_ENV_VAR: &str = "RATE_LIMIT_SESSION";
+    pub const RATE_LIMIT_ACCOUNT_ENV_VAR: &str = "RATE_LIMIT_ACCOUNT";
+    pub const EMAIL_LINK_BASE_URL_ENV_VAR: &str = "EMAIL_LINK_BASE_URL";
+    pub const ADMIN_EMAILS_ENV_VAR: &str = "ADMIN_EMAILS";
+    pub const AUDIT_LOG_PATH_ENV_VAR: &str = "AUDIT_LOG_PATH";
 }
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
+pub const REFRESH_TOKEN_COOKIE_NAME: &str = "refresh_token";
+
+// How often expired entries are removed from the token, session and login
+// throttle stores
+pub const BANNED_TOKEN_PRUNE_INTERVAL_SECONDS: u64 = 60;
+pub const REFRESH_TOKEN_PRUNE_INTERVAL_SECONDS: u64 = 3600;
+pub const SESSION_PRUNE_INTERVAL_SECONDS: u64 = 3600;
+pub const LOGIN_THROTTLE_PRUNE_INTERVAL_SECONDS: u64 = 600;
+
+// How stale a session's last seen time may get before checking one of its
+// jwts updates it. Checking jwts then mostly only reads the session store.
+pub const SESSION_TOUCH_INTERVAL_SECONDS: i64 = 60;
+
+// How long an emailed 2FA code stays valid, and how many wrong guesses it takes
+// to throw it away
+pub const TWO_FA_CODE_TTL_SECONDS: i64 = 600;
+pub const TWO_FA_CODE_MAX_ATTEMPTS: u32 = 5;
+
+// How many recovery codes a user gets each time they are generated
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+// Failed password logins per account. Legitimate users rarely get past the
+// free attempts; a guesser is slowed to a crawl and then locked out.
+pub const LOGIN_THROTTLE_EMAIL_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 3,
+    base_delay_seconds: 1,
+    max_delay_seconds: 60,
+    lockout_after: 10,
+    lockout_seconds: 900,
+    reset_after_seconds: 900,
+};
+
+// Failed password logins per client address. More generous, since many
+// users can share an address.
+pub const LOGIN_THROTTLE_IP_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 20,
+    base_delay_seconds: 1,
+    max_delay_seconds: 60,
+    lockout_after: 100,
+    lockout_seconds: 900,
+    reset_after_seconds: 900,
+};
+
+// How long the browser has to answer a WebAuthn challenge, and how many a
+// user can have outstanding per ceremony before the oldest is dropped
+pub const WEBAUTHN_CHALLENGE_TTL_SECONDS: i64 = 300;
+pub const WEBAUTHN_MAX_PENDING_CHALLENGES: usize = 5;
+
+// How long the link emailed at signup stays valid, and how long a user has to
+// wait before asking for another one
+pub const EMAIL_VERIFICATION_TTL_SECONDS: i64 = 24 * 60 * 60;
+pub const EMAIL_VERIFICATION_RESEND_COOLDOWN_SECONDS: i64 = 60;
+
+// Same for password reset links. Extra requests within the cooldown are
+// quietly dropped.
+pub const PASSWORD_RESET_TTL_SECONDS: i64 = 15 * 60;
+pub const PASSWORD_RESET_COOLDOWN_SECONDS: i64 = 60;
 
 pub mod prod {
     pub const APP_ADDRESS: &str = "0.0.0.0:3000";
@@ -0,0 +1,277 @@
+use askama::Template;
+
+use crate::domain::TwoFACode;
+
+use super::constants::{
+    EMAIL_VERIFICATION_TTL_SECONDS, PASSWORD_RESET_TTL_SECONDS, PRODUCT_NAME, TWO_FA_EMAIL_SETTINGS,
+};
+
+#[derive(Debug, Clone)]
+pub struct TwoFAEmailSettings {
+    pub subject: String,
+    pub product_name: String,
+    // Shown under the code; None leaves the notice out
+    pub expiry_notice: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub text: String,
+    pub html: String,
+}
+
+#[derive(Template)]
+#[template(path = "two_fa_code.txt")]
+struct TwoFACodeText<'a> {
+    product_name: &'a str,
+    code: &'a str,
+    expiry_notice: Option<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "two_fa_code.html")]
+struct TwoFACodeHtml<'a> {
+    product_name: &'a str,
+    code: &'a str,
+    expiry_notice: Option<&'a str>,
+}
+
+// Render the email that carries a 2FA code, using the configured settings
+pub fn two_fa_code_email(code: &TwoFACode) -> Result<RenderedEmail, askama::Error> {
+    render_two_fa_code_email(code, &TWO_FA_EMAIL_SETTINGS)
+}
+
+fn render_two_fa_code_email(
+    code: &TwoFACode,
+    settings: &TwoFAEmailSettings,
+) -> Result<RenderedEmail, askama::Error> {
+    let product_name = settings.product_name.as_str();
+    let expiry_notice = settings.expiry_notice.as_deref();
+
+    let text = TwoFACodeText {
+        product_name,
+        code: code.as_ref(),
+        expiry_notice,
+    }
+    .render()?;
+    let html = TwoFACodeHtml {
+        product_name,
+        code: code.as_ref(),
+        expiry_notice,
+    }
+    .render()?;
+
+    Ok(RenderedEmail {
+        subject: settings.subject.clone(),
+        text,
+        html,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "verify_email.txt")]
+struct VerifyEmailText<'a> {
+    product_name: &'a str,
+    link: &'a str,
+    expiry_hours: i64,
+}
+
+#[derive(Template)]
+#[template(path = "verify_email.html")]
+struct VerifyEmailHtml<'a> {
+    product_name: &'a str,
+    link: &'a str,
+    expiry_hours: i64,
+}
+
+// Render the email with the link that verifies a new account's address
+pub fn verify_email_email(link: &str) -> Result<RenderedEmail, askama::Error> {
+    render_verify_email_email(link, &PRODUCT_NAME)
+}
+
+fn render_verify_email_email(
+    link: &str,
+    product_name: &str,
+) -> Result<RenderedEmail, askama::Error> {
+    let expiry_hours = EMAIL_VERIFICATION_TTL_SECONDS / 3600;
+
+    let text = VerifyEmailText {
+        product_name,
+        link,
+        expiry_hours,
+    }
+    .render()?;
+    let html = VerifyEmailHtml {
+        product_name,
+        link,
+        expiry_hours,
+    }
+    .render()?;
+
+    Ok(RenderedEmail {
+        subject: format!("Confirm your {} email address", product_name),
+        text,
+        html,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "password_reset.txt")]
+struct PasswordResetText<'a> {
+    product_name: &'a str,
+    link: &'a str,
+    expiry_minutes: i64,
+}
+
+#[derive(Template)]
+#[template(path = "password_reset.html")]
+struct PasswordResetHtml<'a> {
+    product_name: &'a str,
+    link: &'a str,
+    expiry_minutes: i64,
+}
+
+// Render the email with the link that lets a user choose a new password
+pub fn password_reset_email(link: &str) -> Result<RenderedEmail, askama::Error> {
+    render_password_reset_email(link, &PRODUCT_NAME)
+}
+
+fn render_password_reset_email(
+    link: &str,
+    product_name: &str,
+) -> Result<RenderedEmail, askama::Error> {
+    let expiry_minutes = PASSWORD_RESET_TTL_SECONDS / 60;
+
+    let text = PasswordResetText {
+        product_name,
+        link,
+        expiry_minutes,
+    }
+    .render()?;
+    let html = PasswordResetHtml {
+        product_name,
+        link,
+        expiry_minutes,
+    }
+    .render()?;
+
+    Ok(RenderedEmail {
+        subject: format!("Reset your {} password", product_name),
+        text,
+        html,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "password_changed.txt")]
+struct PasswordChangedText<'a> {
+    product_name: &'a str,
+    link: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "password_changed.html")]
+struct PasswordChangedHtml<'a> {
+    product_name: &'a str,
+    link: &'a str,
+}
+
+// Render the notice sent after a user changed their password. `link` points
+// at the login page, where they can reset it if it wasn't them.
+pub fn password_changed_email(link: &str) -> Result<RenderedEmail, askama::Error> {
+    render_password_changed_email(link, &PRODUCT_NAME)
+}
+
+fn render_password_changed_email(
+    link: &str,
+    product_name: &str,
+) -> Result<RenderedEmail, askama::Error> {
+    let text = PasswordChangedText { product_name, link }.render()?;
+    let html = PasswordChangedHtml { product_name, link }.render()?;
+
+    Ok(RenderedEmail {
+        subject: format!("Your {} password was changed", product_name),
+        text,
+        html,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> TwoFAEmailSettings {
+        TwoFAEmailSettings {
+            subject: "Your <Acme> login code".to_owned(),
+            product_name: "<Acme>".to_owned(),
+            expiry_notice: Some("The code expires in 10 minutes.".to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_email_contains_the_code_and_notice() {
+        let code = TwoFACode::parse("123456".to_owned()).unwrap();
+        let email = render_two_fa_code_email(&code, &settings()).unwrap();
+
+        assert_eq!(email.subject, "Your <Acme> login code");
+        assert!(email.text.contains("    123456\n"));
+        assert!(email.text.contains("The code expires in 10 minutes."));
+        assert!(email.html.contains(">123456</p>"));
+        assert!(email.html.contains("The code expires in 10 minutes."));
+    }
+
+    #[test]
+    fn test_product_name_is_escaped_in_html_only() {
+        let code = TwoFACode::parse("123456".to_owned()).unwrap();
+        let email = render_two_fa_code_email(&code, &settings()).unwrap();
+
+        assert!(email.text.contains("Your <Acme> login code is:"));
+        assert!(email.html.contains("Your &lt;Acme&gt; login code is:"));
+    }
+
+    #[test]
+    fn test_expiry_notice_can_be_left_out() {
+        let code = TwoFACode::parse("123456".to_owned()).unwrap();
+        let settings = TwoFAEmailSettings {
+            expiry_notice: None,
+            ..settings()
+        };
+        let email = render_two_fa_code_email(&code, &settings).unwrap();
+
+        assert!(!email.text.contains("expires"));
+        assert!(!email.html.contains("expires"));
+    }
+
+    #[test]
+    fn test_verify_email_email_contains_the_link() {
+        let link = "https://auth.example.com/verify-email?token=a.b.c";
+        let email = render_verify_email_email(link, "<Acme>").unwrap();
+
+        assert_eq!(email.subject, "Confirm your <Acme> email address");
+        assert!(email.text.contains(&format!("\n{}\n", link)));
+        assert!(email.text.contains("expires in 24 hours"));
+        assert!(email.html.contains("token=a.b.c"));
+        assert!(email.html.contains("signing up for &lt;Acme&gt;"));
+    }
+
+    #[test]
+    fn test_password_reset_email_contains_the_link() {
+        let link = "https://auth.example.com/?reset_token=a.b.c";
+        let email = render_password_reset_email(link, "Acme").unwrap();
+
+        assert_eq!(email.subject, "Reset your Acme password");
+        assert!(email.text.contains(&format!("\n{}\n", link)));
+        assert!(email.text.contains("expires in 15 minutes"));
+        assert!(email.html.contains("reset_token=a.b.c"));
+    }
+
+    #[test]
+    fn test_password_changed_email_links_to_the_login_page() {
+        let email = render_password_changed_email("https://auth.example.com/", "Acme").unwrap();
+
+        assert_eq!(email.subject, "Your Acme password was changed");
+        assert!(email.text.contains("\nhttps://auth.example.com/"));
+        assert!(email.html.contains("auth.example.com"));
+    }
+}
@@ -0,0 +1,346 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    crypto,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey,
+};
+use rsa::{pkcs8::DecodePublicKey, traits::PublicKeyParts, RsaPublicKey};
+use serde::Deserialize;
+
+// DER prefix of an Ed25519 SubjectPublicKeyInfo, followed by the 32 byte key
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+#[derive(Debug)]
+pub enum JwtKeyError {
+    Io(std::io::Error),
+    InvalidKey(String),
+    UnsupportedAlgorithm(String),
+    InvalidKeyring(String),
+}
+
+// A key JWTs are signed and verified with. Every token carries the key's
+// `kid` in its header so verifiers know which key to use.
+#[derive(Clone)]
+pub struct JwtKey {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    // None for retired keys that are only kept to verify tokens they signed
+    encoding_key: Option<EncodingKey>,
+    decoding_key: DecodingKey,
+    // Public half of asymmetric keys, published on the JWKS endpoint
+    jwk: Option<Jwk>,
+}
+
+impl JwtKey {
+    pub fn from_secret(kid: &str, secret: &[u8]) -> Self {
+        Self {
+            kid: kid.to_owned(),
+            algorithm: Algorithm::HS256,
+            encoding_key: Some(EncodingKey::from_secret(secret)),
+            decoding_key: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    // RS256 key from a PKCS#8 or PKCS#1 private key and its SPKI public key
+    pub fn from_rsa_pem(
+        kid: &str,
+        private_pem: &[u8],
+        public_pem: &[u8],
+    ) -> Result<Self, JwtKeyError> {
+        let encoding_key = EncodingKey::from_rsa_pem(private_pem)
+            .map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?;
+        let (decoding_key, jwk) = rsa_public_key(kid, public_pem)?;
+
+        Self::asymmetric(kid, Algorithm::RS256, encoding_key, decoding_key, jwk)
+    }
+
+    // EdDSA key from a PKCS#8 Ed25519 private key and its SPKI public key
+    pub fn from_ed_pem(
+        kid: &str,
+        private_pem: &[u8],
+        public_pem: &[u8],
+    ) -> Result<Self, JwtKeyError> {
+        let encoding_key = EncodingKey::from_ed_pem(private_pem)
+            .map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?;
+        let (decoding_key, jwk) = ed_public_key(kid, public_pem)?;
+
+        Self::asymmetric(kid, Algorithm::EdDSA, encoding_key, decoding_key, jwk)
+    }
+
+    // Verification-only RS256 or EdDSA key from an SPKI public key
+    pub fn from_public_pem(
+        kid: &str,
+        algorithm: &str,
+        public_pem: &[u8],
+    ) -> Result<Self, JwtKeyError> {
+        let (algorithm, (decoding_key, jwk)) = match algorithm {
+            "RS256" => (Algorithm::RS256, rsa_public_key(kid, public_pem)?),
+            "EdDSA" => (Algorithm::EdDSA, ed_public_key(kid, public_pem)?),
+            other => return Err(JwtKeyError::UnsupportedAlgorithm(other.to_owned())),
+        };
+
+        Ok(Self {
+            kid: kid.to_owned(),
+            algorithm,
+            encoding_key: None,
+            decoding_key,
+            jwk: Some(jwk),
+        })
+    }
+
+    // Load an RS256 or EdDSA key pair from PEM files
+    pub fn from_pem_files(
+        kid: &str,
+        algorithm: &str,
+        private_key_path: &str,
+        public_key_path: &str,
+    ) -> Result<Self, JwtKeyError> {
+        let private_pem = std::fs::read(private_key_path).map_err(JwtKeyError::Io)?;
+        let public_pem = std::fs::read(public_key_path).map_err(JwtKeyError::Io)?;
+
+        match algorithm {
+            "RS256" => Self::from_rsa_pem(kid, &private_pem, &public_pem),
+            "EdDSA" => Self::from_ed_pem(kid, &private_pem, &public_pem),
+            other => Err(JwtKeyError::UnsupportedAlgorithm(other.to_owned())),
+        }
+    }
+
+    // Make sure the public key belongs to the private key before accepting
+    // the pair, otherwise every token we sign would fail verification.
+    fn asymmetric(
+        kid: &str,
+        algorithm: Algorithm,
+        encoding_key: EncodingKey,
+        decoding_key: DecodingKey,
+        jwk: Jwk,
+    ) -> Result<Self, JwtKeyError> {
+        let probe = b"jwt key pair check";
+        let signature = crypto::sign(probe, &encoding_key, algorithm)
+            .map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?;
+        match crypto::verify(&signature, probe, &decoding_key, algorithm) {
+            Ok(true) => {}
+            _ => {
+                return Err(JwtKeyError::InvalidKey(
+                    "public key does not match private key".to_string(),
+                ))
+            }
+        }
+
+        Ok(Self {
+            kid: kid.to_owned(),
+            algorithm,
+            encoding_key: Some(encoding_key),
+            decoding_key,
+            jwk: Some(jwk),
+        })
+    }
+
+    pub fn encoding_key(&self) -> Option<&EncodingKey> {
+        self.encoding_key.as_ref()
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    // None for shared secrets, which must never be published
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+}
+
+// One entry of a keyring file. Keys with a private key (or a secret) can sign,
+// keys with only a public key are kept to verify tokens they signed earlier.
+#[derive(Debug, Deserialize)]
+pub struct JwtKeySpec {
+    pub kid: String,
+    pub algorithm: String,
+    pub secret: Option<String>,
+    pub private_key_path: Option<String>,
+    pub public_key_path: Option<String>,
+}
+
+impl JwtKeySpec {
+    pub fn load(&self) -> Result<JwtKey, JwtKeyError> {
+        if self.algorithm == "HS256" {
+            return match &self.secret {
+                Some(secret) if !secret.is_empty() => {
+                    Ok(JwtKey::from_secret(&self.kid, secret.as_bytes()))
+                }
+                _ => Err(JwtKeyError::InvalidKeyring(format!(
+                    "key {} needs a secret",
+                    self.kid
+                ))),
+            };
+        }
+
+        let public_key_path = self.public_key_path.as_deref().ok_or_else(|| {
+            JwtKeyError::InvalidKeyring(format!("key {} needs a public_key_path", self.kid))
+        })?;
+
+        match self.private_key_path.as_deref() {
+            Some(private_key_path) => JwtKey::from_pem_files(
+                &self.kid,
+                &self.algorithm,
+                private_key_path,
+                public_key_path,
+            ),
+            None => {
+                let public_pem = std::fs::read(public_key_path).map_err(JwtKeyError::Io)?;
+                JwtKey::from_public_pem(&self.kid, &self.algorithm, &public_pem)
+            }
+        }
+    }
+}
+
+fn rsa_public_key(kid: &str, public_pem: &[u8]) -> Result<(DecodingKey, Jwk), JwtKeyError> {
+    let decoding_key = DecodingKey::from_rsa_pem(public_pem)
+        .map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?;
+
+    let public_pem =
+        std::str::from_utf8(public_pem).map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?;
+    let public_key = RsaPublicKey::from_public_key_pem(public_pem)
+        .map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?;
+
+    let jwk = Jwk {
+        common: common_parameters(kid, KeyAlgorithm::RS256),
+        algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n: URL_SAFE_NO_PAD.encode(public_key.n().to_bytes_be()),
+            e: URL_SAFE_NO_PAD.encode(public_key.e().to_bytes_be()),
+        }),
+    };
+
+    Ok((decoding_key, jwk))
+}
+
+fn ed_public_key(kid: &str, public_pem: &[u8]) -> Result<(DecodingKey, Jwk), JwtKeyError> {
+    let public_der = pem::parse(public_pem)
+        .map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?
+        .into_contents();
+    let public_key = match public_der.strip_prefix(&ED25519_SPKI_PREFIX) {
+        Some(x) if x.len() == 32 => x,
+        _ => {
+            return Err(JwtKeyError::InvalidKey(
+                "not an Ed25519 public key".to_string(),
+            ))
+        }
+    };
+    let x = URL_SAFE_NO_PAD.encode(public_key);
+
+    let decoding_key =
+        DecodingKey::from_ed_components(&x).map_err(|e| JwtKeyError::InvalidKey(e.to_string()))?;
+
+    let jwk = Jwk {
+        common: common_parameters(kid, KeyAlgorithm::EdDSA),
+        algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x,
+        }),
+    };
+
+    Ok((decoding_key, jwk))
+}
+
+fn common_parameters(kid: &str, algorithm: KeyAlgorithm) -> CommonParameters {
+    CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(algorithm),
+        key_id: Some(kid.to_owned()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSA_PRIVATE: &[u8] = include_bytes!("../../tests/fixtures/keys/rsa_private.pem");
+    const RSA_PUBLIC: &[u8] = include_bytes!("../../tests/fixtures/keys/rsa_public.pem");
+    const ED_PRIVATE: &[u8] = include_bytes!("../../tests/fixtures/keys/ed25519_private.pem");
+    const ED_PUBLIC: &[u8] = include_bytes!("../../tests/fixtures/keys/ed25519_public.pem");
+
+    #[test]
+    fn test_rsa_key_publishes_its_modulus_and_exponent() {
+        let key = JwtKey::from_rsa_pem("rsa-1", RSA_PRIVATE, RSA_PUBLIC).unwrap();
+
+        assert_eq!(key.algorithm, Algorithm::RS256);
+        let jwk = key.jwk().expect("RSA keys have a JWK");
+        assert_eq!(jwk.common.key_id, Some("rsa-1".to_owned()));
+        match &jwk.algorithm {
+            AlgorithmParameters::RSA(params) => assert_eq!(params.e, "AQAB"),
+            other => panic!("unexpected JWK parameters: {:?}", other),
+        }
+
+        // the published JWK must be enough to verify our signatures
+        let signature = crypto::sign(b"msg", key.encoding_key().unwrap(), key.algorithm).unwrap();
+        let from_jwk = DecodingKey::from_jwk(jwk).unwrap();
+        assert!(crypto::verify(&signature, b"msg", &from_jwk, key.algorithm).unwrap());
+    }
+
+    #[test]
+    fn test_ed_key_publishes_its_public_point() {
+        let key = JwtKey::from_ed_pem("ed-1", ED_PRIVATE, ED_PUBLIC).unwrap();
+
+        assert_eq!(key.algorithm, Algorithm::EdDSA);
+        let jwk = key.jwk().expect("Ed25519 keys have a JWK");
+
+        let signature = crypto::sign(b"msg", key.encoding_key().unwrap(), key.algorithm).unwrap();
+        let from_jwk = DecodingKey::from_jwk(jwk).unwrap();
+        assert!(crypto::verify(&signature, b"msg", &from_jwk, key.algorithm).unwrap());
+    }
+
+    #[test]
+    fn test_mismatched_key_pair_is_rejected() {
+        assert!(JwtKey::from_rsa_pem("rsa-1", RSA_PRIVATE, ED_PUBLIC).is_err());
+        assert!(JwtKey::from_ed_pem("ed-1", ED_PRIVATE, RSA_PUBLIC).is_err());
+    }
+
+    #[test]
+    fn test_public_key_alone_can_verify_but_not_sign() {
+        let key = JwtKey::from_rsa_pem("rsa-1", RSA_PRIVATE, RSA_PUBLIC).unwrap();
+        let signature = crypto::sign(b"msg", key.encoding_key().unwrap(), key.algorithm).unwrap();
+
+        let public = JwtKey::from_public_pem("rsa-1", "RS256", RSA_PUBLIC).unwrap();
+        assert!(public.encoding_key().is_none());
+        assert!(
+            crypto::verify(&signature, b"msg", public.decoding_key(), public.algorithm).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hs256_spec_without_secret_is_rejected() {
+        let spec = JwtKeySpec {
+            kid: "hmac-1".to_owned(),
+            algorithm: "HS256".to_owned(),
+            secret: None,
+            private_key_path: None,
+            public_key_path: None,
+        };
+        assert!(matches!(spec.load(), Err(JwtKeyError::InvalidKeyring(_))));
+    }
+
+    #[test]
+    fn test_secret_key_is_never_published() {
+        let key = JwtKey::from_secret("hmac-1", b"secret");
+        assert!(key.jwk().is_none());
+    }
+
+    #[test]
+    fn test_unsupported_algorithm_is_rejected() {
+        let result = JwtKey::from_pem_files(
+            "es-1",
+            "ES256",
+            "tests/fixtures/keys/rsa_private.pem",
+            "tests/fixtures/keys/rsa_public.pem",
+        );
+        assert!(matches!(result, Err(JwtKeyError::UnsupportedAlgorithm(_))));
+    }
+}
@@ -1,2 +1,7 @@
+pub mod audit;
 pub mod auth;
 pub mod constants;
+pub mod emails;
+pub mod jwt_keys;
+pub mod rate_limit;
+pub mod tasks;
@@ -0,0 +1,346 @@
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{header, HeaderMap, HeaderValue, Request, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tower::{Layer, Service};
+
+use super::{auth::token_subject, constants::JWT_COOKIE_NAME};
+use crate::ErrorResponse;
+
+// A token bucket: up to `burst` requests at once, refilled at `requests` per
+// `window`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub window: Duration,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    // Parses "<requests>/<window seconds>", optionally followed by
+    // ",burst=<n>". The burst defaults to the number of requests.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let error = || {
+            format!(
+                "invalid rate limit {:?}, expected e.g. \"10/60,burst=20\"",
+                value
+            )
+        };
+
+        let (rate, burst) = match value.split_once(',') {
+            Some((rate, burst)) => (rate, Some(burst)),
+            None => (value, None),
+        };
+        let (requests, window) = rate.split_once('/').ok_or_else(error)?;
+        let requests: u32 = requests.trim().parse().map_err(|_| error())?;
+        let window: u64 = window.trim().parse().map_err(|_| error())?;
+        let burst: u32 = match burst {
+            Some(burst) => burst
+                .trim()
+                .strip_prefix("burst=")
+                .and_then(|x| x.parse().ok())
+                .ok_or_else(error)?,
+            None => requests,
+        };
+
+        if requests == 0 || window == 0 || burst == 0 {
+            return Err(error());
+        }
+
+        Ok(Self {
+            requests,
+            window: Duration::from_secs(window),
+            burst,
+        })
+    }
+
+    fn tokens_per_second(&self) -> f64 {
+        f64::from(self.requests) / self.window.as_secs_f64()
+    }
+
+    fn seconds_until(&self, tokens: f64, target: f64) -> u64 {
+        ((target - tokens).max(0.0) / self.tokens_per_second()).ceil() as u64
+    }
+}
+
+// The limit of each group of routes. See `set_rate_limits` for the defaults.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimits {
+    pub signup: RateLimit,
+    pub login: RateLimit,
+    pub verify_2fa: RateLimit,
+    pub verify_token: RateLimit,
+    pub session: RateLimit,
+    pub account: RateLimit,
+}
+
+// What a request's bucket is picked by. `Subject` falls back to the client
+// address for requests without a valid jwt cookie.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RateLimitKey {
+    Ip,
+    Subject,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+// Full buckets are dropped at most once per window, so the map stays small
+// without scanning it on every request. A dropped bucket is the same as the
+// full one a new request would start with.
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    pruned_at: Instant,
+}
+
+#[derive(Debug, PartialEq)]
+struct Decision {
+    allowed: bool,
+    remaining: u32,
+    // Seconds until the bucket is full again
+    reset: u64,
+    // Seconds until the next request would be let through
+    retry_after: u64,
+}
+
+// Tower layer enforcing one `RateLimit` on the routes it wraps. Every layer
+// has its own buckets, kept in memory.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    limit: RateLimit,
+    key: RateLimitKey,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limit: RateLimit, key: RateLimitKey) -> Self {
+        Self {
+            limit,
+            key,
+            buckets: Arc::new(Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                pruned_at: Instant::now(),
+            })),
+        }
+    }
+
+    fn take(&self, key: &str, now: Instant) -> Decision {
+        let limit = &self.limit;
+        let capacity = f64::from(limit.burst);
+        let mut buckets = self.buckets.lock().expect("rate limit lock poisoned");
+
+        if now.saturating_duration_since(buckets.pruned_at) >= limit.window {
+            buckets
+                .by_key
+                .retain(|_, bucket| refill(limit, bucket, now) < capacity);
+            buckets.pruned_at = now;
+        }
+
+        let bucket = buckets.by_key.entry(key.to_owned()).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+        bucket.tokens = refill(limit, bucket, now);
+        bucket.updated_at = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        Decision {
+            allowed,
+            remaining: bucket.tokens.floor() as u32,
+            reset: limit.seconds_until(bucket.tokens, capacity),
+            retry_after: limit.seconds_until(bucket.tokens, 1.0),
+        }
+    }
+
+    fn key_for(&self, request: &Request<Body>) -> String {
+        let subject = match self.key {
+            RateLimitKey::Subject => CookieJar::from_headers(request.headers())
+                .get(JWT_COOKIE_NAME)
+                .and_then(|cookie| token_subject(cookie.value())),
+            RateLimitKey::Ip => None,
+        };
+
+        match subject {
+            Some(subject) => format!("sub:{}", subject),
+            None => match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+                Some(ConnectInfo(addr)) => format!("ip:{}", addr.ip()),
+                None => "ip:unknown".to_owned(),
+            },
+        }
+    }
+}
+
+fn refill(limit: &RateLimit, bucket: &Bucket, now: Instant) -> f64 {
+    let elapsed = now.saturating_duration_since(bucket.updated_at);
+    (bucket.tokens + elapsed.as_secs_f64() * limit.tokens_per_second()).min(f64::from(limit.burst))
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    layer: RateLimitLayer,
+}
+
+impl<S> Service<Request<Body>> for RateLimitService<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let key = self.layer.key_for(&request);
+        let decision = self.layer.take(&key, Instant::now());
+        let limit = self.layer.limit.burst;
+
+        if !decision.allowed {
+            let mut response = (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(ErrorResponse {
+                    error: "Too many requests".to_owned(),
+                    ..Default::default()
+                }),
+            )
+                .into_response();
+            let headers = response.headers_mut();
+            set_headers(headers, limit, &decision);
+            headers.insert(header::RETRY_AFTER, decision.retry_after.into());
+            return Box::pin(async move { Ok(response) });
+        }
+
+        // The clone may not be ready, so swap it for the one `poll_ready` was
+        // called on.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let mut response = inner.call(request).await?;
+            set_headers(response.headers_mut(), limit, &decision);
+            Ok(response)
+        })
+    }
+}
+
+// The RateLimit-* fields from the IETF draft on rate limit headers
+fn set_headers(headers: &mut HeaderMap, limit: u32, decision: &Decision) {
+    headers.insert("ratelimit-limit", HeaderValue::from(limit));
+    headers.insert("ratelimit-remaining", HeaderValue::from(decision.remaining));
+    headers.insert("ratelimit-reset", HeaderValue::from(decision.reset));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layer(limit: &str) -> RateLimitLayer {
+        RateLimitLayer::new(RateLimit::parse(limit).unwrap(), RateLimitKey::Ip)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            RateLimit::parse("10/60"),
+            Ok(RateLimit {
+                requests: 10,
+                window: Duration::from_secs(60),
+                burst: 10,
+            })
+        );
+        assert_eq!(RateLimit::parse("10/60,burst=3").unwrap().burst, 3);
+        assert!(RateLimit::parse("10").is_err());
+        assert!(RateLimit::parse("0/60").is_err());
+        assert!(RateLimit::parse("10/60,3").is_err());
+    }
+
+    #[test]
+    fn test_burst_then_reject() {
+        let layer = layer("1/10,burst=2");
+        let now = Instant::now();
+
+        assert!(layer.take("a", now).allowed);
+        let second = layer.take("a", now);
+        assert!(second.allowed);
+        assert_eq!(second.remaining, 0);
+        assert_eq!(second.reset, 20);
+
+        let third = layer.take("a", now);
+        assert!(!third.allowed);
+        assert_eq!(third.retry_after, 10);
+    }
+
+    #[test]
+    fn test_tokens_refill_over_time() {
+        let layer = layer("1/10,burst=1");
+        let now = Instant::now();
+
+        assert!(layer.take("a", now).allowed);
+        assert!(!layer.take("a", now + Duration::from_secs(5)).allowed);
+        assert!(layer.take("a", now + Duration::from_secs(10)).allowed);
+    }
+
+    #[test]
+    fn test_keys_have_separate_buckets() {
+        let layer = layer("1/10,burst=1");
+        let now = Instant::now();
+
+        assert!(layer.take("a", now).allowed);
+        assert!(layer.take("b", now).allowed);
+        assert!(!layer.take("a", now).allowed);
+    }
+
+    #[test]
+    fn test_full_buckets_are_pruned_once_per_window() {
+        let layer = layer("1/10,burst=1");
+        let now = Instant::now();
+
+        layer.take("a", now);
+        layer.take("b", now + Duration::from_secs(5));
+        let count = || layer.buckets.lock().unwrap().by_key.len();
+
+        // Too soon since the last prune
+        layer.take("c", now + Duration::from_secs(9));
+        assert_eq!(count(), 3);
+
+        // Only "a" has refilled
+        layer.take("c", now + Duration::from_secs(10));
+        assert_eq!(count(), 2);
+        assert!(!layer.take("b", now + Duration::from_secs(10)).allowed);
+    }
+}
@@ -0,0 +1,148 @@
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::app_state::{
+    BannedtokenStoreType, LoginThrottleStoreType, RefreshTokenStoreType, SessionStoreType,
+};
+
+use super::auth::REFRESH_TOKEN_TTL_SECONDS;
+use super::constants::{LOGIN_THROTTLE_EMAIL_POLICY, LOGIN_THROTTLE_IP_POLICY};
+
+// Periodically drop banned tokens whose `exp` has passed so the store doesn't
+// grow forever. The task runs until the runtime shuts down.
+pub fn spawn_banned_token_pruner(
+    banned_token_store: BannedtokenStoreType,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if banned_token_store
+                .write()
+                .await
+                .prune_expired()
+                .await
+                .is_err()
+            {
+                eprintln!("failed to prune expired banned tokens");
+            }
+        }
+    })
+}
+
+// Same as above for refresh tokens, including rotated ones that were only
+// kept around to detect reuse.
+pub fn spawn_refresh_token_pruner(
+    refresh_token_store: RefreshTokenStoreType,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if refresh_token_store
+                .write()
+                .await
+                .prune_expired()
+                .await
+                .is_err()
+            {
+                eprintln!("failed to prune expired refresh tokens");
+            }
+        }
+    })
+}
+
+// Drop sessions idle for longer than a refresh token lives. Nothing can use
+// them any more: their last jwt and refresh token have both expired.
+pub fn spawn_session_pruner(session_store: SessionStoreType, period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let last_seen_before = chrono::Utc::now().timestamp() - REFRESH_TOKEN_TTL_SECONDS;
+            if session_store
+                .write()
+                .await
+                .prune_idle(last_seen_before)
+                .await
+                .is_err()
+            {
+                eprintln!("failed to prune idle sessions");
+            }
+        }
+    })
+}
+
+// Drop failed login counters once they would be forgotten anyway, so
+// addresses that tried once don't stay in the store forever.
+pub fn spawn_login_throttle_pruner(
+    login_throttle_store: LoginThrottleStoreType,
+    period: Duration,
+) -> JoinHandle<()> {
+    let reset_after_seconds = LOGIN_THROTTLE_EMAIL_POLICY
+        .reset_after_seconds
+        .max(LOGIN_THROTTLE_IP_POLICY.reset_after_seconds);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let last_failure_before = chrono::Utc::now().timestamp() - reset_after_seconds;
+            if login_throttle_store
+                .write()
+                .await
+                .prune_expired(last_failure_before)
+                .await
+                .is_err()
+            {
+                eprintln!("failed to prune expired login failures");
+            }
+        }
+    })
+}
+
+// Reload the JWT keyring whenever the process gets SIGHUP, so keys can be
+// rotated by editing the keyring file without a restart.
+#[cfg(unix)]
+pub fn spawn_jwt_keyring_reloader() -> std::io::Result<JoinHandle<()>> {
+    use super::auth::reload_jwt_keyring;
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    Ok(tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match reload_jwt_keyring() {
+                Ok(()) => println!("reloaded JWT keyring"),
+                Err(e) => eprintln!("failed to reload JWT keyring, keeping current keys: {e:?}"),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::BannedTokenStore;
+    use crate::services::hashset_banned_token_store::HashsetBannedTokenStore;
+    use chrono::Utc;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_pruner_removes_expired_tokens() {
+        let mut store = HashsetBannedTokenStore::default();
+        let expired_at = (Utc::now().timestamp() - 1) as usize;
+        let _ = store.add_token("expired".to_string(), expired_at).await;
+        let banned_token_store: BannedtokenStoreType = Arc::new(RwLock::new(store));
+
+        let handle =
+            spawn_banned_token_pruner(banned_token_store.clone(), Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        handle.abort();
+
+        let result = banned_token_store.read().await.get_token("expired").await;
+        assert_eq!(result, Ok(false));
+    }
+}
@@ -0,0 +1,146 @@
+use reqwest::Url;
+
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    domain::{Email, EmailTokenPurpose, ThrottleKey},
+    services::hashset_banned_token_store::HashsetBannedTokenStore,
+    utils::constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME},
+};
+
+fn user(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })
+}
+
+// Signs up and logs in a user without 2FA, returning their email, jwt and
+// refresh token
+async fn login(app: &TestApp) -> (String, String, String) {
+    let email = get_random_email();
+    let response = app.post_signup_verified(&user(&email)).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&user(&email)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let cookie = |name| {
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_owned())
+            .expect("No cookie found")
+    };
+    let token = cookie(JWT_COOKIE_NAME);
+    let refresh_token = cookie(REFRESH_TOKEN_COOKIE_NAME);
+
+    (email, token, refresh_token)
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_401_and_keep_the_account_if_password_is_wrong() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let (email, _, _) = login(&app).await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "wrongpassword" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_login(&user(&email)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_delete_the_account_and_end_the_session() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let (email, token, refresh_token) = login(&app).await;
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(auth_cookie.value().is_empty());
+
+    let response = app.post_login(&user(&email)).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // A copy of the refresh token doesn't work either
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}; Path=/", REFRESH_TOKEN_COOKIE_NAME, refresh_token),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_purge_the_user_from_other_stores() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let (email, _, _) = login(&app).await;
+
+    // Leave a reset link and a failed attempt behind
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    app.smtp_server
+        .wait_for_email()
+        .await
+        .expect("No password reset email");
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "wrongpassword" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .delete_account(&serde_json::json!({ "password": "password123" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let email = Email::parse(email).unwrap();
+    let sent_at = app
+        .email_token_store
+        .read()
+        .await
+        .get_sent_at(&email, EmailTokenPurpose::ResetPassword)
+        .await;
+    assert_eq!(sent_at, Ok(None));
+
+    let failures = app
+        .login_throttle_store
+        .read()
+        .await
+        .get_failures(&ThrottleKey::Email(email.clone()))
+        .await;
+    assert_eq!(failures, Ok(None));
+
+    // The address is free to sign up again
+    let response = app.post_signup(&user(email.as_ref())).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
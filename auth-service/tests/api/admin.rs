@@ -0,0 +1,321 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    domain::{Email, Role},
+    routes::{AdminUserResponse, ListUsersResponse},
+    services::hashset_banned_token_store::HashsetBannedTokenStore,
+    utils::constants::JWT_COOKIE_NAME,
+    ErrorResponse,
+};
+use chrono::Utc;
+
+fn credentials(email: &str, requires_2fa: bool) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": requires_2fa
+    })
+}
+
+async fn signup(app: &TestApp, email: &str, requires_2fa: bool) {
+    let response = app
+        .post_signup_verified(&credentials(email, requires_2fa))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+// Logs `email` in and returns the jwt. The client carries on as that user.
+async fn login(app: &TestApp, email: &str) -> String {
+    let response = app.post_login(&credentials(email, false)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_owned())
+        .expect("No auth cookie found");
+    token
+}
+
+// Signs up a user, makes them an admin and logs them in
+async fn login_as_admin(app: &TestApp) {
+    let email = get_random_email();
+    signup(app, &email, false).await;
+
+    app.user_store
+        .write()
+        .await
+        .set_role(&Email::parse(email.clone()).unwrap(), Role::Admin)
+        .await
+        .unwrap();
+
+    login(app, &email).await;
+}
+
+async fn admin_user(response: reqwest::Response) -> AdminUserResponse {
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json::<AdminUserResponse>()
+        .await
+        .expect("Could not deserialize response body to AdminUserResponse")
+}
+
+fn lock_for(seconds: i64) -> (i64, serde_json::Value) {
+    let until = Utc::now().timestamp() + seconds;
+    (until, serde_json::json!({ "lockedUntil": until }))
+}
+
+fn disable(reason: &str) -> serde_json::Value {
+    serde_json::json!({ "reason": reason })
+}
+
+fn no_body() -> serde_json::Value {
+    serde_json::json!({})
+}
+
+async fn verify_token(app: &TestApp, token: &str) -> u16 {
+    app.post_verify_token(&serde_json::json!({ "token": token }))
+        .await
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn should_return_400_without_jwt() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app.get_admin_users(&[]).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_403_for_users_who_are_not_admins() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+    login(&app, &email).await;
+
+    let response = app.get_admin_users(&[]).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let response = app
+        .post_admin_user_action(&email, "disable", &disable("Compromised"))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn should_list_users_a_page_at_a_time() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    for email in [
+        "list-1@example.com",
+        "list-2@example.com",
+        "list-3@example.com",
+    ] {
+        signup(&app, email, false).await;
+    }
+    login_as_admin(&app).await;
+
+    let response = app
+        .get_admin_users(&[("emailPrefix", "list-"), ("limit", "2")])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response
+        .json::<ListUsersResponse>()
+        .await
+        .expect("Could not deserialize response body to ListUsersResponse");
+    let emails: Vec<_> = page.users.iter().map(|user| user.email.as_str()).collect();
+    assert_eq!(emails, ["list-1@example.com", "list-2@example.com"]);
+    assert_eq!(page.next_cursor.as_deref(), Some("list-2@example.com"));
+
+    let response = app
+        .get_admin_users(&[
+            ("emailPrefix", "list-"),
+            ("limit", "2"),
+            ("after", "list-2@example.com"),
+        ])
+        .await;
+    let page = response
+        .json::<ListUsersResponse>()
+        .await
+        .expect("Could not deserialize response body to ListUsersResponse");
+    let emails: Vec<_> = page.users.iter().map(|user| user.email.as_str()).collect();
+    assert_eq!(emails, ["list-3@example.com"]);
+    assert_eq!(page.next_cursor, None);
+
+    let response = app.get_admin_users(&[("limit", "0")]).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_get_a_user() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup(&app, &email, true).await;
+    login_as_admin(&app).await;
+
+    let user = admin_user(app.get_admin_user(&email).await).await;
+    assert_eq!(user.email, email);
+    assert_eq!(user.role, Role::User);
+    assert_eq!(user.two_fa_method, "email");
+    assert!(user.requires_2fa);
+    assert!(user.email_verified);
+    assert_eq!(user.status, "active");
+    assert_eq!((user.locked_until, user.disabled_reason), (None, None));
+}
+
+#[tokio::test]
+async fn should_return_404_for_an_unknown_user() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    login_as_admin(&app).await;
+
+    let response = app.get_admin_user(&get_random_email()).await;
+    assert_eq!(response.status().as_u16(), 404);
+    assert_eq!(
+        response.json::<ErrorResponse>().await.unwrap().error,
+        "User not found"
+    );
+
+    let response = app.get_admin_user("not-an-email").await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_lock_users_out_until_unlocked() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+    let token = login(&app, &email).await;
+    login_as_admin(&app).await;
+
+    let (until, body) = lock_for(3600);
+    let user = admin_user(app.post_admin_user_action(&email, "lock", &body).await).await;
+    assert_eq!(user.status, "locked");
+    assert_eq!(user.locked_until, Some(until));
+
+    // Logged out everywhere, and told until when they can't log in
+    assert_eq!(verify_token(&app, &token).await, 403);
+    let response = app.post_login(&credentials(&email, false)).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let error = response.json::<ErrorResponse>().await.unwrap();
+    assert_eq!(error.error, "Account locked");
+    assert_eq!(error.locked_until, Some(until));
+
+    let user = admin_user(
+        app.post_admin_user_action(&email, "activate", &no_body())
+            .await,
+    )
+    .await;
+    assert_eq!(user.status, "active");
+
+    let token = login(&app, &email).await;
+    assert_eq!(verify_token(&app, &token).await, 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_lock_has_already_run_out() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+    login_as_admin(&app).await;
+
+    let (_, body) = lock_for(-60);
+    let response = app.post_admin_user_action(&email, "lock", &body).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_disable_users_until_activated() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+    let token = login(&app, &email).await;
+    login_as_admin(&app).await;
+
+    let body = disable("Suspicious activity");
+    let user = admin_user(app.post_admin_user_action(&email, "disable", &body).await).await;
+    assert_eq!(user.status, "disabled");
+    assert_eq!(user.disabled_reason.as_deref(), Some("Suspicious activity"));
+
+    // The reason is passed on, so the login page can show it
+    assert_eq!(verify_token(&app, &token).await, 403);
+    let response = app.post_login(&credentials(&email, false)).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let error = response.json::<ErrorResponse>().await.unwrap();
+    assert_eq!(error.error, "Account disabled");
+    assert_eq!(error.reason.as_deref(), Some("Suspicious activity"));
+
+    // A wrong password isn't told about it
+    let body = serde_json::json!({ "email": email, "password": "wrong-password" });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_admin_user_action(&email, "disable", &disable("  "))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_admin_user_action(&email, "activate", &no_body())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    login(&app, &email).await;
+}
+
+#[tokio::test]
+async fn should_log_a_user_out_everywhere() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+    let token = login(&app, &email).await;
+    login_as_admin(&app).await;
+
+    let response = app
+        .post_admin_user_action(&email, "logout", &no_body())
+        .await;
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(verify_token(&app, &token).await, 401);
+
+    let response = app
+        .post_admin_user_action(&get_random_email(), "logout", &no_body())
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn should_turn_2fa_off_and_on() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup(&app, &email, true).await;
+    login_as_admin(&app).await;
+
+    let body = serde_json::json!({ "requires2FA": false });
+    let user = admin_user(app.put_admin_user_2fa(&email, &body).await).await;
+    assert_eq!(user.two_fa_method, "none");
+    assert!(!user.requires_2fa);
+
+    let body = serde_json::json!({ "requires2FA": true });
+    let user = admin_user(app.put_admin_user_2fa(&email, &body).await).await;
+    assert_eq!(user.two_fa_method, "email");
+
+    let response = app.post_login(&credentials(&email, false)).await;
+    assert_eq!(response.status().as_u16(), 206);
+}
+
+#[tokio::test]
+async fn should_delete_a_user() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup(&app, &email, false).await;
+    let token = login(&app, &email).await;
+    login_as_admin(&app).await;
+
+    let response = app.delete_admin_user(&email).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    assert_eq!(verify_token(&app, &token).await, 401);
+    let response = app.get_admin_user(&email).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app.delete_admin_user(&email).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
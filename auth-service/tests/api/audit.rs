@@ -0,0 +1,206 @@
+use crate::helpers::{get_emailed_2fa_code, get_random_email, TestApp, TEST_USER_AGENT};
+use auth_service::{
+    domain::{verify_chain, AuditAction, AuditOutcome, AuditRecord, Email, Role},
+    routes::TwoFactorAuthResponse,
+    services::hashset_banned_token_store::HashsetBannedTokenStore,
+    utils::constants::JWT_COOKIE_NAME,
+};
+
+fn credentials(email: &str, requires_2fa: bool) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": requires_2fa
+    })
+}
+
+// Every record so far, after checking they form an unbroken chain
+async fn audit_records(app: &TestApp) -> Vec<AuditRecord> {
+    let records = app.audit_sink.read().await.get_records().await.unwrap();
+    assert_eq!(verify_chain(&records), Ok(records.len()));
+    records
+}
+
+#[tokio::test]
+async fn should_audit_signup_and_logins() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+
+    let response = app.post_signup_verified(&credentials(&email, false)).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&credentials(&email, false)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let wrong_password = serde_json::json!({
+        "email": email,
+        "password": "wrong-password",
+    });
+    let response = app.post_login(&wrong_password).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let records = audit_records(&app).await;
+    let actions: Vec<_> = records.iter().map(|x| x.event.action).collect();
+    assert_eq!(
+        actions,
+        [
+            AuditAction::Signup,
+            AuditAction::VerifyEmail,
+            AuditAction::Login,
+            AuditAction::Login,
+        ]
+    );
+
+    for record in &records {
+        assert_eq!(record.event.email.as_deref(), Some(email.as_str()));
+        assert_eq!(record.event.ip, Some("127.0.0.1".parse().unwrap()));
+        assert_eq!(record.event.user_agent.as_deref(), Some(TEST_USER_AGENT));
+    }
+
+    assert_eq!(records[0].event.status, 201);
+    assert_eq!(records[2].event.outcome, AuditOutcome::Success);
+    assert_eq!(records[3].event.outcome, AuditOutcome::Failure);
+    assert_eq!(records[3].event.status, 401);
+    assert_eq!(
+        records[3].event.error.as_deref(),
+        Some("Incorrect credentials")
+    );
+}
+
+#[tokio::test]
+async fn should_audit_the_second_factor_of_a_login() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+
+    let response = app.post_signup_verified(&credentials(&email, true)).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&credentials(&email, true)).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let code = get_emailed_2fa_code(&app.smtp_server.take_received().pop().unwrap());
+    let wrong_code = if code == "000000" { "111111" } else { "000000" };
+
+    for (two_fa_code, status) in [(wrong_code, 401), (code.as_str(), 200)] {
+        let body = serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": two_fa_code,
+        });
+        let response = app.post_verify_2fa(&body).await;
+        assert_eq!(response.status().as_u16(), status);
+    }
+
+    let records = audit_records(&app).await;
+    let outcomes: Vec<_> = records[2..]
+        .iter()
+        .map(|x| (x.event.action, x.event.outcome))
+        .collect();
+    assert_eq!(
+        outcomes,
+        [
+            (AuditAction::Login, AuditOutcome::SecondFactorRequired),
+            (AuditAction::Verify2FA, AuditOutcome::Failure),
+            (AuditAction::Verify2FA, AuditOutcome::Success),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn should_only_audit_failed_token_verifications() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+
+    let response = app.post_signup_verified(&credentials(&email, false)).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let response = app.post_login(&credentials(&email, false)).await;
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_owned())
+        .expect("No auth cookie found");
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(audit_records(&app).await.len(), 3);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": "invalid" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The banned token is rejected by the route's extractor before the
+    // route runs
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let records = audit_records(&app).await;
+    let events: Vec<_> = records[3..]
+        .iter()
+        .map(|x| (x.event.action, x.event.outcome, x.event.email.clone()))
+        .collect();
+    assert_eq!(
+        events,
+        [
+            (AuditAction::VerifyToken, AuditOutcome::Failure, None),
+            (
+                AuditAction::Logout,
+                AuditOutcome::Success,
+                Some(email.clone())
+            ),
+            (AuditAction::VerifyToken, AuditOutcome::Failure, Some(email)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn should_audit_admin_actions_with_the_admin_as_actor() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let admin = get_random_email();
+    let user = get_random_email();
+
+    for email in [&admin, &user] {
+        let response = app.post_signup_verified(&credentials(email, false)).await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+
+    // Not an admin yet
+    let response = app.post_login(&credentials(&admin, false)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.get_admin_user(&user).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.user_store
+        .write()
+        .await
+        .set_role(&Email::parse(admin.clone()).unwrap(), Role::Admin)
+        .await
+        .unwrap();
+    let response = app.post_login(&credentials(&admin, false)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.get_admin_user(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let records = audit_records(&app).await;
+    let refused = &records[records.len() - 3].event;
+    assert_eq!(refused.action, AuditAction::AdminAccess);
+    assert_eq!(refused.outcome, AuditOutcome::Failure);
+    assert_eq!(refused.email.as_deref(), Some(admin.as_str()));
+
+    let allowed = &records[records.len() - 1].event;
+    assert_eq!(allowed.action, AuditAction::AdminGetUser);
+    assert_eq!(allowed.outcome, AuditOutcome::Success);
+    assert_eq!(allowed.email.as_deref(), Some(user.as_str()));
+    assert_eq!(allowed.actor.as_deref(), Some(admin.as_str()));
+}
@@ -0,0 +1,153 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    services::hashset_banned_token_store::HashsetBannedTokenStore,
+    utils::constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME},
+};
+
+// Signs up and logs in a user without 2FA, returning their email and jwt
+async fn login(app: &TestApp) -> (String, String) {
+    let email = get_random_email();
+    let response = app
+        .post_signup_verified(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    (email, token)
+}
+
+fn change(current_password: &str, new_password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "currentPassword": current_password,
+        "newPassword": new_password,
+    })
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app
+        .post_change_password(&change("password123", "newpassword123"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_401_if_current_password_is_wrong() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    login(&app).await;
+
+    let response = app
+        .post_change_password(&change("wrongpassword", "newpassword123"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_is_invalid() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    login(&app).await;
+
+    let response = app
+        .post_change_password(&change("password123", "short"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_change_the_password_and_notify_the_user() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let (email, _) = login(&app).await;
+
+    let response = app
+        .post_change_password(&change("password123", "newpassword123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let emails = app.smtp_server.received();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].recipients, vec![email.clone()]);
+    assert_eq!(
+        emails[0].subject(),
+        Some("Your Auth Service password was changed")
+    );
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "newpassword123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_log_out_other_sessions_only() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let (email, other_session) = login(&app).await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_change_password(&change("password123", "newpassword123"))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(response
+        .cookies()
+        .any(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE_NAME));
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": other_session }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": auth_cookie.value() }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The refresh cookie was replaced along with the jwt
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
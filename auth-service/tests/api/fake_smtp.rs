@@ -0,0 +1,165 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+#[derive(Debug, Clone)]
+pub struct ReceivedEmail {
+    pub mail_from: String,
+    pub recipients: Vec<String>,
+    // The message exactly as sent after DATA, headers and body
+    pub data: String,
+}
+
+impl ReceivedEmail {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let prefix = format!("{}: ", name);
+        self.data
+            .split("\r\n")
+            .take_while(|line| !line.is_empty())
+            .find_map(|line| line.strip_prefix(&prefix))
+    }
+
+    pub fn subject(&self) -> Option<&str> {
+        self.header("Subject")
+    }
+
+    pub fn body(&self) -> &str {
+        match self.data.split_once("\r\n\r\n") {
+            Some((_, body)) => body,
+            None => "",
+        }
+    }
+}
+
+// Just enough of an SMTP server to accept mail from SmtpEmailClient over a
+// plain connection and record it, so tests can look at what was sent.
+#[derive(Clone)]
+pub struct FakeSmtpServer {
+    pub port: u16,
+    received: Arc<Mutex<Vec<ReceivedEmail>>>,
+    // Reply codes to answer the next messages with instead of accepting them
+    rejections: Arc<Mutex<VecDeque<u16>>>,
+}
+
+impl FakeSmtpServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind fake SMTP server");
+        let server = Self {
+            port: listener.local_addr().unwrap().port(),
+            received: Arc::new(Mutex::new(Vec::new())),
+            rejections: Arc::new(Mutex::new(VecDeque::new())),
+        };
+
+        let accepting = server.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let connection = accepting.clone();
+                tokio::spawn(async move {
+                    let _ = connection.handle(stream).await;
+                });
+            }
+        });
+
+        server
+    }
+
+    pub fn received(&self) -> Vec<ReceivedEmail> {
+        self.received.lock().unwrap().clone()
+    }
+
+    // Like `received`, but forgets the emails so later checks only see new ones
+    pub fn take_received(&self) -> Vec<ReceivedEmail> {
+        std::mem::take(&mut *self.received.lock().unwrap())
+    }
+
+    // Wait a little for an email sent after the response, e.g. from a spawned
+    // task, and take it. None if nothing arrives.
+    pub async fn wait_for_email(&self) -> Option<ReceivedEmail> {
+        for _ in 0..50 {
+            if let Some(email) = self.take_received().pop() {
+                return Some(email);
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        None
+    }
+
+    pub fn reject_next(&self, code: u16) {
+        self.rejections.lock().unwrap().push_back(code);
+    }
+
+    async fn handle(&self, stream: TcpStream) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+
+        writer.write_all(b"220 fake-smtp ESMTP\r\n").await?;
+
+        let mut mail_from = String::new();
+        let mut recipients = Vec::new();
+
+        while let Some(line) = lines.next_line().await? {
+            let command = line.to_ascii_uppercase();
+
+            let reply = if command.starts_with("EHLO") || command.starts_with("HELO") {
+                "250 fake-smtp".to_owned()
+            } else if command.starts_with("MAIL FROM:") {
+                mail_from = address(&line);
+                recipients.clear();
+                "250 OK".to_owned()
+            } else if command.starts_with("RCPT TO:") {
+                recipients.push(address(&line));
+                "250 OK".to_owned()
+            } else if command == "DATA" {
+                writer
+                    .write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n")
+                    .await?;
+
+                let mut data = Vec::new();
+                while let Some(line) = lines.next_line().await? {
+                    if line == "." {
+                        break;
+                    }
+                    // Undo dot-stuffing
+                    data.push(line.strip_prefix('.').map(str::to_owned).unwrap_or(line));
+                }
+
+                let rejection = self.rejections.lock().unwrap().pop_front();
+                match rejection {
+                    Some(code) => format!("{} Rejected by test", code),
+                    None => {
+                        self.received.lock().unwrap().push(ReceivedEmail {
+                            mail_from: mail_from.clone(),
+                            recipients: std::mem::take(&mut recipients),
+                            data: data.join("\r\n"),
+                        });
+                        "250 OK".to_owned()
+                    }
+                }
+            } else if command == "QUIT" {
+                writer.write_all(b"221 Bye\r\n").await?;
+                break;
+            } else if command == "RSET" || command == "NOOP" {
+                "250 OK".to_owned()
+            } else {
+                "502 Command not implemented".to_owned()
+            };
+
+            writer
+                .write_all(format!("{}\r\n", reply).as_bytes())
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+// `MAIL FROM:<a@b.c> SIZE=123` -> `a@b.c`
+fn address(line: &str) -> String {
+    match (line.find('<'), line.find('>')) {
+        (Some(start), Some(end)) if start < end => line[start + 1..end].to_owned(),
+        _ => String::new(),
+    }
+}
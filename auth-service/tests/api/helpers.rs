@@ -1,48 +1,111 @@
 use reqwest::cookie::Jar;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+use crate::fake_smtp::{FakeSmtpServer, ReceivedEmail};
 use auth_service::{
     app_state::{
-        AppState, BannedtokenStoreType, EmailClientType, TwoFACodeStoreType, UserStoreType,
+        AppState, AuditSinkType, BannedtokenStoreType, EmailClientType, EmailTokenStoreType,
+        LoginThrottleStoreType, TwoFACodeStoreType, UserStoreType,
     },
     services::{
-        hashmap_two_fa_code_store::HashmapTwoFACodeStore, hashmap_user_store::HashmapUserStore,
-        hashset_banned_token_store::HashsetBannedTokenStore, mock_email_client::MockEmailClient,
+        database::get_database_pool,
+        hashmap_refresh_token_store::HashmapRefreshTokenStore,
+        hashmap_session_store::HashmapSessionStore,
+        hashmap_two_fa_code_store::HashmapTwoFACodeStore,
+        hashmap_webauthn_challenge_store::HashmapWebAuthnChallengeStore,
+        hashset_banned_token_store::HashsetBannedTokenStore,
+        smtp_email_client::{SmtpEmailClient, SmtpSettings, SmtpTls},
+        sql_email_token_store::SqlEmailTokenStore,
+        sql_login_throttle_store::SqlLoginThrottleStore,
+        sql_passkey_store::SqlPasskeyStore,
+        sql_recovery_code_store::SqlRecoveryCodeStore,
+        sql_totp_store::SqlTotpStore,
+        sql_user_store::SqlUserStore,
+        vec_audit_sink::VecAuditSink,
+    },
+    utils::{
+        constants::{test, RATE_LIMITS},
+        rate_limit::RateLimits,
     },
-    utils::constants::test,
     Application,
 };
 
+// Sent with every request, so sessions record it
+pub const TEST_USER_AGENT: &str = "auth-service-tests";
+
 //#[derive(Debug)]
 pub struct TestApp {
     pub address: String,
     pub cookie_jar: Arc<Jar>,
     pub http_client: reqwest::Client,
+    pub user_store: UserStoreType,
+    pub banned_token_store: BannedtokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
-    pub email_client: EmailClientType,
+    pub login_throttle_store: LoginThrottleStoreType,
+    pub email_token_store: EmailTokenStoreType,
+    pub audit_sink: AuditSinkType,
+    pub smtp_server: FakeSmtpServer,
+    pub db_path: PathBuf,
 }
 
 impl TestApp {
-    pub async fn new(
+    pub async fn new(injected_banned_token_store: HashsetBannedTokenStore) -> Self {
+        Self::with_rate_limits(injected_banned_token_store, &RATE_LIMITS).await
+    }
+
+    pub async fn with_rate_limits(
         injected_banned_token_store: HashsetBannedTokenStore,
-        email_client: MockEmailClient,
+        rate_limits: &RateLimits,
     ) -> Self {
-        let user_store: UserStoreType = Arc::new(RwLock::new(HashmapUserStore::default()));
+        // Every test app gets its own SQLite database file.
+        let db_path = std::env::temp_dir().join(format!("auth-service-test-{}.db", Uuid::new_v4()));
+        let pool = get_database_pool(&format!("sqlite://{}?mode=rwc", db_path.display()))
+            .await
+            .expect("Failed to open test database");
+        let user_store: UserStoreType = Arc::new(RwLock::new(SqlUserStore::new(pool.clone())));
+        let totp_store = Arc::new(RwLock::new(SqlTotpStore::new(pool.clone())));
+        let recovery_code_store = Arc::new(RwLock::new(SqlRecoveryCodeStore::new(pool.clone())));
+        let passkey_store = Arc::new(RwLock::new(SqlPasskeyStore::new(pool.clone())));
+        let login_throttle_store: LoginThrottleStoreType =
+            Arc::new(RwLock::new(SqlLoginThrottleStore::new(pool.clone())));
+        let email_token_store: EmailTokenStoreType =
+            Arc::new(RwLock::new(SqlEmailTokenStore::new(pool)));
+        let webauthn_challenge_store =
+            Arc::new(RwLock::new(HashmapWebAuthnChallengeStore::default()));
         let banned_token_store: BannedtokenStoreType =
             Arc::new(RwLock::new(injected_banned_token_store.clone()));
+        let refresh_token_store = Arc::new(RwLock::new(HashmapRefreshTokenStore::default()));
         let two_fa_code_store = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
-        let email_client: EmailClientType = Arc::new(email_client);
+        let session_store = Arc::new(RwLock::new(HashmapSessionStore::default()));
+        // Emails go through the real SMTP client to a server the test can inspect.
+        let smtp_server = FakeSmtpServer::start().await;
+        let email_client: EmailClientType = Arc::new(
+            SmtpEmailClient::new(smtp_settings(&smtp_server)).expect("Failed to build SMTP client"),
+        );
+
+        let audit_sink: AuditSinkType = Arc::new(RwLock::new(VecAuditSink::default()));
 
         let app_state = AppState::new(
-            user_store,
-            banned_token_store,
+            user_store.clone(),
+            banned_token_store.clone(),
+            refresh_token_store,
             two_fa_code_store.clone(),
-            email_client.clone(),
+            totp_store,
+            recovery_code_store,
+            passkey_store,
+            webauthn_challenge_store,
+            login_throttle_store.clone(),
+            email_token_store.clone(),
+            session_store,
+            email_client,
+            audit_sink.clone(),
         );
 
-        let app = Application::build(app_state, test::APP_ADDRESS)
+        let app = Application::build_with_rate_limits(app_state, test::APP_ADDRESS, rate_limits)
             .await
             .expect("Failed to build app");
 
@@ -57,6 +120,7 @@ impl TestApp {
 
         let http_client = reqwest::Client::builder()
             .cookie_provider(cookie_jar.clone())
+            .user_agent(TEST_USER_AGENT)
             .build()
             .unwrap();
 
@@ -64,14 +128,28 @@ impl TestApp {
             address,
             cookie_jar,
             http_client,
+            user_store,
+            banned_token_store,
             two_fa_code_store,
-            email_client,
+            login_throttle_store,
+            email_token_store,
+            audit_sink,
+            smtp_server,
+            db_path,
         }
     }
 
     pub async fn get_root(&self) -> reqwest::Response {
         self.http_client
-            .get(&format!("{}/", &self.address))
+            .get(format!("{}/", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_jwks(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/.well-known/jwks.json", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -84,7 +162,95 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/signup", &self.address))
+            .post(format!("{}/signup", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // Sign up and follow the link in the verification email, so the account
+    // can log in. Returns the signup response.
+    pub async fn post_signup_verified<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let response = self.post_signup(body).await;
+
+        let email = self
+            .smtp_server
+            .take_received()
+            .pop()
+            .expect("No verification email");
+        let verified = self.get_verify_email(&get_emailed_token(&email)).await;
+        assert_eq!(verified.status().as_u16(), 200);
+
+        response
+    }
+
+    pub async fn get_verify_email(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/verify-email", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_email_resend<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-email/resend", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_request<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/request", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_password_reset_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/password-reset/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_change_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/change-password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_account<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .delete(format!("{}/account", &self.address))
             .json(body)
             .send()
             .await
@@ -96,7 +262,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.http_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .json(body)
             .send()
             .await
@@ -105,7 +271,7 @@ impl TestApp {
 
     pub async fn logout(&self) -> reqwest::Response {
         self.http_client
-            .post(&format!("{}/logout", &self.address))
+            .post(format!("{}/logout", &self.address))
             //    .header("Content-Type", "application/json")
             //    .json(&p)
             .send()
@@ -113,11 +279,175 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
-    pub async fn verify_2fa(&self) -> reqwest::Response {
+    pub async fn post_verify_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
         self.http_client
-            .post(&format!("{}/verify_2fa", &self.address))
-            //    .header("Content-Type", "application/json")
-            //    .json(&p)
+            .post(format!("{}/verify_2fa", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_refresh(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/refresh", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_session(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/sessions/{}", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_users(&self, query: &[(&str, &str)]) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/users", &self.address))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_user(&self, email: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/users/{}", &self.address, email))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin_user(&self, email: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/admin/users/{}", &self.address, email))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // `action` is one of lock, disable, activate and logout
+    pub async fn post_admin_user_action<Body>(
+        &self,
+        email: &str,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!(
+                "{}/admin/users/{}/{}",
+                &self.address, email, action
+            ))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_admin_user_2fa<Body>(&self, email: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .put(format!("{}/admin/users/{}/2fa", &self.address, email))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_totp_enroll(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/totp/enroll", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_totp_confirm<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/totp/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_recovery_codes_regenerate(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/recovery_codes/regenerate", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_register_start(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/webauthn/register/start", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_register_finish<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/webauthn/register/finish", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_login_start<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/webauthn/login/start", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_webauthn_login_finish<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/webauthn/login/finish", &self.address))
+            .json(body)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -136,6 +466,48 @@ impl TestApp {
     }
 }
 
+impl Drop for TestApp {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.db_path);
+    }
+}
+
+pub fn smtp_settings(smtp_server: &FakeSmtpServer) -> SmtpSettings {
+    SmtpSettings {
+        host: "127.0.0.1".to_owned(),
+        port: smtp_server.port,
+        tls: SmtpTls::None,
+        username: None,
+        password: None,
+        sender: "auth@example.com".to_owned(),
+        timeout: Duration::from_secs(5),
+        max_retries: 2,
+        retry_backoff: Duration::from_millis(10),
+    }
+}
+
+// The plain text part of the 2FA email puts the code on an indented line
+pub fn get_emailed_2fa_code(email: &ReceivedEmail) -> String {
+    email
+        .body()
+        .split("\r\n")
+        .find_map(|line| line.strip_prefix("    "))
+        .expect("No 2FA code in email")
+        .to_owned()
+}
+
+// The token in the link of a verification email. Long lines of the plain text
+// part are quoted-printable encoded, so undo that first.
+pub fn get_emailed_token(email: &ReceivedEmail) -> String {
+    let body = email.body().replace("=\r\n", "").replace("=3D", "=");
+    let start = body.find("token=").expect("No link in email") + "token=".len();
+
+    body[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        .collect()
+}
+
 pub fn get_random_email() -> String {
     format!("{}@example.com", Uuid::new_v4())
 }
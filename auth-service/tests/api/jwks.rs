@@ -0,0 +1,18 @@
+use crate::helpers::TestApp;
+use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
+
+#[tokio::test]
+async fn should_return_200_with_a_key_set() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app.get_jwks().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<serde_json::Value>()
+        .await
+        .expect("Could not deserialize response body to JSON");
+
+    // The tests sign with JWT_SECRET, and shared secrets are never published
+    assert_eq!(body["keys"], serde_json::json!([]));
+}
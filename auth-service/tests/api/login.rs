@@ -1,16 +1,18 @@
 use serde::{Deserialize, Serialize};
 
-//use crate::helpers::{get_random_email, TestApp};
-use crate::helpers::{get_random_email, TestApp};
+//use crate::helpers::{get_emailed_2fa_code, get_random_email, TestApp};
+use crate::helpers::{get_emailed_2fa_code, get_random_email, TestApp};
 use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
-use auth_service::services::mock_email_client::MockEmailClient;
 use auth_service::{
-    domain::Email, routes::TwoFactorAuthResponse, utils::constants::JWT_COOKIE_NAME,
+    domain::{Email, ThrottleKey},
+    routes::TwoFactorAuthResponse,
+    utils::constants::{JWT_COOKIE_NAME, LOGIN_THROTTLE_EMAIL_POLICY, LOGIN_THROTTLE_IP_POLICY},
 };
+use chrono::Utc;
 
 #[tokio::test]
 async fn should_return_422_if_malformed_credentials() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     #[derive(Debug, Serialize, Deserialize)]
     struct EmptyBody {}
@@ -25,7 +27,7 @@ async fn should_return_400_if_invalid_input() {
     // Call the log-in route with invalid credentials and assert that a
     // 400 HTTP status code is returned along with the appropriate error message.
 
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let invalid_creds = serde_json::json!({
         "email": "fooexample.com".to_string(),
@@ -42,7 +44,7 @@ async fn should_return_401_if_incorrect_credentials() {
     // Call the log-in route with incorrect credentials and assert
     // that a 401 HTTP status code is returned along with the appropriate error message.
 
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let user = serde_json::json!({
         "email": "foo@example.com",
@@ -56,7 +58,7 @@ async fn should_return_401_if_incorrect_credentials() {
         "requires2FA": true
     });
 
-    let _ = app.post_signup(&user).await;
+    let _ = app.post_signup_verified(&user).await;
 
     let response = app.post_login(&invalid_creds).await;
 
@@ -65,7 +67,7 @@ async fn should_return_401_if_incorrect_credentials() {
 
 #[tokio::test]
 async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let random_email = get_random_email();
 
@@ -75,7 +77,7 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
         "requires2FA": false
     });
 
-    let response = app.post_signup(&signup_body).await;
+    let response = app.post_signup_verified(&signup_body).await;
 
     assert_eq!(response.status().as_u16(), 201);
 
@@ -98,7 +100,7 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
 
 #[tokio::test]
 async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let random_email = get_random_email();
 
@@ -113,12 +115,18 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
         "password": "password123",
     });
 
-    let response = app.post_signup(&signup_body).await;
+    let response = app.post_signup_verified(&signup_body).await;
     assert_eq!(response.status().as_u16(), 201);
 
     let response = app.post_login(&login_body).await;
     assert_eq!(response.status().as_u16(), 206);
 
+    // The auth cookie is only issued once the 2FA code has been verified.
+    assert!(response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .is_none());
+
     let json_body = response
         .json::<TwoFactorAuthResponse>()
         .await
@@ -131,6 +139,262 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
         .get_code(&Email::parse(random_email).unwrap())
         .await;
 
-    assert_eq!(result.is_ok(), true);
-    assert_eq!(json_body.login_attempt_id.is_empty(), false);
+    assert!(result.is_ok());
+    assert!(!json_body.login_attempt_id.is_empty());
+}
+
+#[tokio::test]
+async fn should_send_2fa_email_if_2fa_enabled() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_signup_verified(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let emails = app.smtp_server.received();
+    assert_eq!(emails.len(), 1);
+
+    let email = &emails[0];
+    assert_eq!(email.mail_from, "auth@example.com");
+    assert_eq!(email.recipients, vec![random_email.clone()]);
+    assert_eq!(email.subject(), Some("Your Auth Service login code"));
+    assert!(email.body().contains("text/plain"));
+    assert!(email.body().contains("text/html"));
+
+    let (_, expected_code) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&Email::parse(random_email).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(get_emailed_2fa_code(email), expected_code.as_ref());
+}
+
+// Records `count` failed logins for `key` as if they had just happened
+async fn fail_logins(app: &TestApp, key: ThrottleKey, count: u32) {
+    let mut store = app.login_throttle_store.write().await;
+    for _ in 0..count {
+        store
+            .record_failure(key.clone(), Utc::now().timestamp(), 900)
+            .await
+            .unwrap();
+    }
+}
+
+fn retry_after(response: &reqwest::Response) -> u64 {
+    response
+        .headers()
+        .get("retry-after")
+        .expect("No Retry-After header")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn should_count_failed_logins_per_account_and_address() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    let user = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let _ = app.post_signup_verified(&user).await;
+
+    let wrong_password = serde_json::json!({
+        "email": email,
+        "password": "not-the-same",
+    });
+    for _ in 0..LOGIN_THROTTLE_EMAIL_POLICY.free_attempts {
+        let response = app.post_login(&wrong_password).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let email_key = ThrottleKey::Email(Email::parse(email).unwrap());
+    let ip_key = ThrottleKey::Ip("127.0.0.1".parse().unwrap());
+    let store = app.login_throttle_store.read().await;
+    let attempts = LOGIN_THROTTLE_EMAIL_POLICY.free_attempts;
+    assert_eq!(
+        store.get_failures(&email_key).await.unwrap().unwrap().count,
+        attempts
+    );
+    assert_eq!(
+        store.get_failures(&ip_key).await.unwrap().unwrap().count,
+        attempts
+    );
+    drop(store);
+
+    // Logging in resets the account, but not the address
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let store = app.login_throttle_store.read().await;
+    assert_eq!(store.get_failures(&email_key).await, Ok(None));
+    assert_eq!(
+        store.get_failures(&ip_key).await.unwrap().unwrap().count,
+        attempts
+    );
+}
+
+#[tokio::test]
+async fn should_return_429_while_backing_off() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    let user = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let _ = app.post_signup_verified(&user).await;
+
+    // Three free attempts, then 1, 2 and 4 seconds
+    fail_logins(&app, ThrottleKey::Email(Email::parse(email).unwrap()), 6).await;
+
+    // Even the right password has to wait
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 429);
+    let seconds = retry_after(&response);
+    assert!((1..=4).contains(&seconds), "Retry-After: {}", seconds);
+}
+
+#[tokio::test]
+async fn should_lock_out_account_after_too_many_failures() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    let user = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let _ = app.post_signup_verified(&user).await;
+
+    let key = ThrottleKey::Email(Email::parse(email).unwrap());
+    fail_logins(&app, key, LOGIN_THROTTLE_EMAIL_POLICY.lockout_after).await;
+
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(retry_after(&response) > 800);
+}
+
+#[tokio::test]
+async fn should_return_429_for_any_account_from_a_throttled_address() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    });
+    let _ = app.post_signup_verified(&user).await;
+
+    fail_logins(&app, ThrottleKey::Ip("127.0.0.1".parse().unwrap()), 100).await;
+
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn should_count_concurrent_failed_logins_before_letting_more_in() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    let user = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let _ = app.post_signup_verified(&user).await;
+
+    let wrong_password = serde_json::json!({
+        "email": email,
+        "password": "not-the-same",
+    });
+    let responses = tokio::join!(
+        app.post_login(&wrong_password),
+        app.post_login(&wrong_password),
+        app.post_login(&wrong_password),
+        app.post_login(&wrong_password),
+        app.post_login(&wrong_password),
+        app.post_login(&wrong_password),
+        app.post_login(&wrong_password),
+        app.post_login(&wrong_password),
+    );
+    let responses = [
+        responses.0,
+        responses.1,
+        responses.2,
+        responses.3,
+        responses.4,
+        responses.5,
+        responses.6,
+        responses.7,
+    ];
+
+    // Three free attempts and one more before the first delay kicks in
+    let checked = responses
+        .iter()
+        .filter(|x| x.status().as_u16() == 401)
+        .count();
+    assert_eq!(
+        checked as u32,
+        LOGIN_THROTTLE_EMAIL_POLICY.free_attempts + 1
+    );
+}
+
+#[tokio::test]
+async fn should_not_extend_the_address_backoff_on_success() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    });
+    let _ = app.post_signup_verified(&user).await;
+
+    // Others behind the address failed a while ago, long enough for the
+    // backoff to have run out
+    let ip_key = ThrottleKey::Ip("127.0.0.1".parse().unwrap());
+    let failed_at = Utc::now().timestamp() - 300;
+    {
+        let mut store = app.login_throttle_store.write().await;
+        for _ in 0..LOGIN_THROTTLE_IP_POLICY.free_attempts + 5 {
+            store
+                .record_failure(ip_key.clone(), failed_at, 900)
+                .await
+                .unwrap();
+        }
+    }
+
+    for _ in 0..2 {
+        let response = app.post_login(&user).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let failures = app
+        .login_throttle_store
+        .read()
+        .await
+        .get_failures(&ip_key)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(failures.last_failure_at, failed_at);
+    let now = Utc::now().timestamp();
+    assert_eq!(LOGIN_THROTTLE_IP_POLICY.retry_after(&failures, now), None);
 }
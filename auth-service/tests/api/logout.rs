@@ -1,45 +1,69 @@
-use auth_service::utils::constants::JWT_COOKIE_NAME;
+use auth_service::utils::constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME};
 use reqwest::Url;
 
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::domain::BannedTokenStore;
-use auth_service::domain::Email;
 use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
-use auth_service::services::mock_email_client::MockEmailClient;
-use auth_service::utils::auth::generate_auth_cookie;
+
+// Signs up and logs in a user without 2FA, returning the jwt and refresh
+// cookies
+async fn login(app: &TestApp) -> (String, String) {
+    let user = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup_verified(&user).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let cookie = |name| {
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| format!("{}={}", cookie.name(), cookie.value()))
+            .expect("No cookie found")
+    };
+
+    (cookie(JWT_COOKIE_NAME), cookie(REFRESH_TOKEN_COOKIE_NAME))
+}
 
 #[tokio::test]
 async fn should_return_200_if_valid_jwt_cookie() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
-    let email = Email::parse("foo@example.com".to_string()).unwrap();
-    let cookie = generate_auth_cookie(&email).unwrap();
-
-    app.cookie_jar.add_cookie_str(
-        &cookie.to_string(),
-        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
-    );
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let (cookie, _) = login(&app).await;
+    let token = cookie.trim_start_matches(&format!("{}=", JWT_COOKIE_NAME));
 
     let response = app.logout().await;
 
     assert_eq!(response.status().as_u16(), 200);
 
-    //    assert_eq!(
-    //        app.get_banned_token_store()
-    //            .get_token(&String::from(cookie.value()))
-    //            .await,
-    //        true
-    //    );
+    let is_banned = app.banned_token_store.read().await.get_token(token).await;
+    assert_eq!(is_banned, Ok(true));
 }
 
 #[tokio::test]
-async fn should_return_400_if_logout_called_twice_in_a_row() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
-    let email = Email::parse("foo@example.com".to_string()).unwrap();
+async fn should_end_the_session_on_logout() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let (_, refresh_cookie) = login(&app).await;
 
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The refresh token went with the session
     app.cookie_jar.add_cookie_str(
-        &generate_auth_cookie(&email).unwrap().to_string(),
+        &refresh_cookie,
         &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
     );
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_400_if_logout_called_twice_in_a_row() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let _ = login(&app).await;
 
     let _ = app.logout().await;
     let response = app.logout().await;
@@ -49,14 +73,14 @@ async fn should_return_400_if_logout_called_twice_in_a_row() {
 
 #[tokio::test]
 async fn should_return_400_if_jwt_cookie_missing() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
     let response = app.logout().await;
     assert_eq!(response.status().as_u16(), 400);
 }
 
 #[tokio::test]
 async fn should_return_401_if_invalid_token() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     // add invalid cookie - it's not a jwt.
     app.cookie_jar.add_cookie_str(
@@ -71,3 +95,19 @@ async fn should_return_401_if_invalid_token() {
 
     assert_eq!(response.status().as_u16(), 401);
 }
+
+#[tokio::test]
+async fn should_return_401_if_token_already_banned() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let (cookie, _) = login(&app).await;
+    let url = Url::parse("http://127.0.0.1").expect("Failed to parse URL");
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // replay the revoked token
+    app.cookie_jar.add_cookie_str(&cookie, &url);
+    let response = app.logout().await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -1,7 +1,23 @@
+mod account;
+mod admin;
+mod audit;
+mod change_password;
+mod fake_smtp;
 mod helpers;
+mod jwks;
 mod login;
 mod logout;
+mod password_reset;
+mod rate_limit;
+mod recovery_codes;
+mod refresh;
 mod root;
+mod sessions;
 mod signup;
+mod smtp_email_client;
+mod software_authenticator;
+mod totp;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
+mod webauthn;
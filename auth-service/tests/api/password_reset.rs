@@ -0,0 +1,206 @@
+use crate::helpers::{get_emailed_token, get_random_email, TestApp};
+use auth_service::{
+    services::hashset_banned_token_store::HashsetBannedTokenStore,
+    utils::constants::JWT_COOKIE_NAME, ErrorResponse,
+};
+
+async fn signup(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app
+        .post_signup_verified(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    email
+}
+
+async fn request_reset(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let email = app
+        .smtp_server
+        .wait_for_email()
+        .await
+        .expect("No password reset email");
+    assert_eq!(email.subject(), Some("Reset your Auth Service password"));
+
+    get_emailed_token(&email)
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn should_return_202_without_sending_email_to_unknown_address() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": get_random_email() }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    assert!(app.smtp_server.wait_for_email().await.is_none());
+}
+
+#[tokio::test]
+async fn should_return_202_if_email_is_invalid() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": "not-an-email" }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn should_return_202_if_the_email_cannot_be_sent() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = signup(&app).await;
+    app.smtp_server.reject_next(554);
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": email }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn should_not_send_another_email_within_the_cooldown() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = signup(&app).await;
+    request_reset(&app, &email).await;
+
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": email }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 202);
+    assert!(app.smtp_server.wait_for_email().await.is_none());
+}
+
+#[tokio::test]
+async fn should_replace_the_password() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = signup(&app).await;
+    let token = request_reset(&app, &email).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "newpassword123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = login(&app, &email, "password123").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = login(&app, &email, "newpassword123").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_only_accept_a_link_once() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = signup(&app).await;
+    let token = request_reset(&app, &email).await;
+
+    let body = serde_json::json!({
+        "token": token,
+        "newPassword": "newpassword123",
+    });
+    let response = app.post_password_reset_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_password_reset_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_if_token_is_invalid() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": "invalid",
+            "newPassword": "newpassword123",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.json::<ErrorResponse>().await.unwrap().error,
+        "Unauthorized"
+    );
+}
+
+#[tokio::test]
+async fn should_keep_the_link_if_new_password_is_invalid() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = signup(&app).await;
+    let token = request_reset(&app, &email).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "short",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "newpassword123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_revoke_existing_sessions() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = signup(&app).await;
+
+    let response = login(&app, &email, "password123").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    let token = request_reset(&app, &email).await;
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "newPassword": "newpassword123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": auth_cookie.value() }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The refresh cookie from the login is still in the client's jar
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = login(&app, &email, "newpassword123").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,88 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
+use auth_service::utils::{
+    constants::RATE_LIMITS,
+    rate_limit::{RateLimit, RateLimits},
+};
+
+fn header(response: &reqwest::Response, name: &str) -> String {
+    response
+        .headers()
+        .get(name)
+        .unwrap_or_else(|| panic!("No {} header", name))
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+fn signup_body() -> serde_json::Value {
+    serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    })
+}
+
+#[tokio::test]
+async fn should_return_ratelimit_headers() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app.post_signup(&signup_body()).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let burst = RATE_LIMITS.signup.burst;
+    assert_eq!(header(&response, "ratelimit-limit"), burst.to_string());
+    assert_eq!(
+        header(&response, "ratelimit-remaining"),
+        (burst - 1).to_string()
+    );
+    assert!(header(&response, "ratelimit-reset").parse::<u64>().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn should_return_429_once_the_burst_is_used_up() {
+    let rate_limits = RateLimits {
+        signup: RateLimit::parse("1/60,burst=2").unwrap(),
+        ..*RATE_LIMITS
+    };
+    let app = TestApp::with_rate_limits(HashsetBannedTokenStore::default(), &rate_limits).await;
+
+    for _ in 0..2 {
+        let response = app.post_signup(&signup_body()).await;
+        assert_eq!(response.status().as_u16(), 201);
+    }
+
+    let response = app.post_signup(&signup_body()).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(header(&response, "ratelimit-remaining"), "0");
+    assert_eq!(header(&response, "retry-after"), "60");
+
+    // Other routes have their own limits
+    let response = app.post_login(&signup_body()).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_limit_authenticated_routes_per_user() {
+    let rate_limits = RateLimits {
+        account: RateLimit::parse("1/60").unwrap(),
+        ..*RATE_LIMITS
+    };
+    let app = TestApp::with_rate_limits(HashsetBannedTokenStore::default(), &rate_limits).await;
+
+    let first_user = signup_body();
+    let second_user = signup_body();
+    app.post_signup_verified(&first_user).await;
+    app.post_signup_verified(&second_user).await;
+
+    app.post_login(&first_user).await;
+    let response = app.post_totp_enroll().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_totp_enroll().await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    // Same address, different user
+    app.post_login(&second_user).await;
+    let response = app.post_totp_enroll().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,200 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::domain::TotpSecret;
+use auth_service::routes::{
+    RecoveryCodesResponse, SignupResponse, TotpEnrollmentResponse, TwoFactorAuthResponse,
+};
+use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
+use chrono::Utc;
+
+async fn signup_with_2fa(app: &TestApp, email: &str) -> Vec<String> {
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    let response = app.post_signup_verified(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse")
+        .recovery_codes
+        .expect("No recovery codes in signup response")
+}
+
+async fn login(app: &TestApp, email: &str) -> String {
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id
+}
+
+async fn verify(app: &TestApp, email: &str, login_attempt_id: &str, code: &str) -> u16 {
+    let request = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    app.post_verify_2fa(&request).await.status().as_u16()
+}
+
+#[tokio::test]
+async fn should_accept_a_recovery_code_once() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    let codes = signup_with_2fa(&app, &email).await;
+
+    let login_attempt_id = login(&app, &email).await;
+    assert_eq!(
+        verify(&app, &email, &login_attempt_id, &codes[0]).await,
+        200
+    );
+
+    let login_attempt_id = login(&app, &email).await;
+    assert_eq!(
+        verify(&app, &email, &login_attempt_id, &codes[0]).await,
+        401
+    );
+    // in whatever form the user typed it in
+    let code = codes[1].replace('-', "").to_uppercase();
+    assert_eq!(verify(&app, &email, &login_attempt_id, &code).await, 200);
+}
+
+#[tokio::test]
+async fn should_keep_a_recovery_code_given_with_a_wrong_login_attempt() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    let codes = signup_with_2fa(&app, &email).await;
+
+    let login_attempt_id = login(&app, &email).await;
+    let wrong_login_attempt_id = uuid::Uuid::new_v4().to_string();
+    assert_eq!(
+        verify(&app, &email, &wrong_login_attempt_id, &codes[0]).await,
+        401
+    );
+
+    assert_eq!(
+        verify(&app, &email, &login_attempt_id, &codes[0]).await,
+        200
+    );
+}
+
+#[tokio::test]
+async fn should_return_401_for_someone_elses_recovery_code() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup_with_2fa(&app, &email).await;
+    let other_codes = signup_with_2fa(&app, &get_random_email()).await;
+
+    let login_attempt_id = login(&app, &email).await;
+    assert_eq!(
+        verify(&app, &email, &login_attempt_id, &other_codes[0]).await,
+        401
+    );
+}
+
+#[tokio::test]
+async fn should_invalidate_old_codes_on_regenerate() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    let old_codes = signup_with_2fa(&app, &email).await;
+
+    let login_attempt_id = login(&app, &email).await;
+    assert_eq!(
+        verify(&app, &email, &login_attempt_id, &old_codes[0]).await,
+        200
+    );
+
+    let response = app.post_recovery_codes_regenerate().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_codes = response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse")
+        .recovery_codes;
+    assert_eq!(new_codes.len(), 10);
+
+    let login_attempt_id = login(&app, &email).await;
+    assert_eq!(
+        verify(&app, &email, &login_attempt_id, &old_codes[1]).await,
+        401
+    );
+    assert_eq!(
+        verify(&app, &email, &login_attempt_id, &new_codes[0]).await,
+        200
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_regenerating_without_2fa() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let body = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    });
+    app.post_signup_verified(&body).await;
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_recovery_codes_regenerate().await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_400_if_regenerating_without_jwt() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app.post_recovery_codes_regenerate().await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_issue_recovery_codes_when_confirming_totp() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    app.post_signup_verified(&body).await;
+    app.post_login(&body).await;
+
+    let secret = app
+        .post_totp_enroll()
+        .await
+        .json::<TotpEnrollmentResponse>()
+        .await
+        .expect("Could not deserialize response body to TotpEnrollmentResponse")
+        .secret;
+    let secret = TotpSecret::parse(secret).unwrap();
+
+    let response = app
+        .post_totp_confirm(&serde_json::json!({
+            "code": secret.generate(Utc::now().timestamp() as u64)
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let codes = response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse")
+        .recovery_codes;
+
+    // Lost the phone: log in with a recovery code instead
+    let login_attempt_id = login(&app, &email).await;
+    assert_eq!(
+        verify(&app, &email, &login_attempt_id, &codes[0]).await,
+        200
+    );
+}
@@ -0,0 +1,183 @@
+use reqwest::Url;
+
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::domain::{AccountStatus, Email};
+use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
+use auth_service::utils::constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME};
+
+// Signs up and logs in a user without 2FA, returning the refresh token.
+async fn login(app: &TestApp) -> String {
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup_verified(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("No refresh cookie found");
+
+    refresh_cookie.value().to_owned()
+}
+
+fn set_refresh_cookie(app: &TestApp, token: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!(
+            "{}={}; HttpOnly; SameSite=Lax; Path=/",
+            REFRESH_TOKEN_COOKIE_NAME, token
+        ),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_refresh_cookie_missing() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app.post_refresh().await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_401_if_unknown_refresh_token() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    set_refresh_cookie(&app, "invalid");
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    set_refresh_cookie(&app, &"a".repeat(64));
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_200_and_rotate_tokens() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let first_refresh_token = login(&app).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(!auth_cookie.value().is_empty());
+
+    let refresh_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("No refresh cookie found");
+    assert_ne!(refresh_cookie.value(), first_refresh_token);
+    assert!(refresh_cookie.http_only());
+
+    let request = serde_json::json!({ "token": auth_cookie.value() });
+    let response = app.post_verify_token(&request).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_revoke_the_family_if_a_rotated_token_is_reused() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let first_refresh_token = login(&app).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let second_refresh_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("No refresh cookie found")
+        .value()
+        .to_owned();
+    let auth_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    // an attacker replays the first token
+    set_refresh_cookie(&app, &first_refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // the legitimate client's current token is revoked as well
+    set_refresh_cookie(&app, &second_refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // and so is the session, along with its jwts
+    let request = serde_json::json!({ "token": auth_token });
+    let response = app.post_verify_token(&request).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_after_logout() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let refresh_token = login(&app).await;
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    set_refresh_cookie(&app, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_403_and_end_the_session_of_a_disabled_user() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    let user = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    app.post_signup_verified(&user).await;
+    let response = app.post_login(&user).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let refresh_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE_NAME)
+        .expect("No refresh cookie found")
+        .value()
+        .to_owned();
+
+    // Disabled behind the service's back, so the session is still there
+    let email = Email::parse(email).unwrap();
+    app.user_store
+        .write()
+        .await
+        .set_status(&email, AccountStatus::Disabled("compromised".to_owned()))
+        .await
+        .unwrap();
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    // The refresh token doesn't work again once the account is back on
+    app.user_store
+        .write()
+        .await
+        .set_status(&email, AccountStatus::Active)
+        .await
+        .unwrap();
+    set_refresh_cookie(&app, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -1,11 +1,10 @@
 use crate::helpers::TestApp;
 use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
-use auth_service::services::mock_email_client::MockEmailClient;
 
 // Tokio's test macro is used to run the test in an async environment
 #[tokio::test]
 async fn root_returns_auth_ui() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let response = app.get_root().await;
 
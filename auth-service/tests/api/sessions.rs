@@ -0,0 +1,206 @@
+use reqwest::Url;
+
+use crate::helpers::{get_random_email, TestApp, TEST_USER_AGENT};
+use auth_service::{
+    routes::{SessionResponse, SessionsResponse},
+    services::hashset_banned_token_store::HashsetBannedTokenStore,
+    utils::constants::{JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME},
+};
+
+fn user(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })
+}
+
+// Logs `email` in, returning the session's jwt and refresh token. The client
+// carries on in the new session.
+async fn login(app: &TestApp, email: &str) -> (String, String) {
+    let response = app.post_login(&user(email)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let cookie = |name| {
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_owned())
+            .expect("No cookie found")
+    };
+
+    (cookie(JWT_COOKIE_NAME), cookie(REFRESH_TOKEN_COOKIE_NAME))
+}
+
+// Signs up a user and logs them in twice, as if from two devices. The client
+// is left in the second session; the first one's tokens are returned.
+async fn login_twice(app: &TestApp) -> (String, String) {
+    let email = get_random_email();
+    let response = app.post_signup_verified(&user(&email)).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let first = login(app, &email).await;
+    let _ = login(app, &email).await;
+    first
+}
+
+async fn sessions(app: &TestApp) -> Vec<SessionResponse> {
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response
+        .json::<SessionsResponse>()
+        .await
+        .expect("Could not deserialize response body to SessionsResponse")
+        .sessions
+}
+
+// Sessions started in the same second may be listed in either order, so the
+// one this client is in is found by its flag
+async fn current_session(app: &TestApp) -> SessionResponse {
+    sessions(app)
+        .await
+        .into_iter()
+        .find(|session| session.current)
+        .expect("No current session")
+}
+
+async fn other_session(app: &TestApp) -> SessionResponse {
+    sessions(app)
+        .await
+        .into_iter()
+        .find(|session| !session.current)
+        .expect("No other session")
+}
+
+fn add_refresh_cookie(app: &TestApp, refresh_token: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}", REFRESH_TOKEN_COOKIE_NAME, refresh_token),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app.get_sessions().await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_list_every_session_and_mark_the_current_one() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let _ = login_twice(&app).await;
+
+    let sessions = sessions(&app).await;
+
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions.iter().filter(|session| session.current).count(), 1);
+    for session in &sessions {
+        assert_eq!(session.user_agent.as_deref(), Some(TEST_USER_AGENT));
+        assert_eq!(session.ip.as_deref(), Some("127.0.0.1"));
+        assert!(session.last_seen_at >= session.created_at);
+    }
+}
+
+#[tokio::test]
+async fn should_keep_the_session_across_refreshes() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let _ = login_twice(&app).await;
+    let before = current_session(&app).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(sessions(&app).await.len(), 2);
+    assert_eq!(current_session(&app).await.id, before.id);
+}
+
+#[tokio::test]
+async fn should_revoke_another_session() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let (token, refresh_token) = login_twice(&app).await;
+    let other = other_session(&app).await;
+
+    let response = app.delete_session(&other.id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let sessions = sessions(&app).await;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+
+    // The other device's jwt and refresh token stop working straight away
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    add_refresh_cookie(&app, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_clear_cookies_when_revoking_the_current_session() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let _ = login_twice(&app).await;
+    let current = current_session(&app).await;
+
+    let response = app.delete_session(&current.id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    assert!(auth_cookie.value().is_empty());
+
+    // The session is over, so its jwt no longer works either
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_log_out_everywhere() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let (token, refresh_token) = login_twice(&app).await;
+
+    let response = app.delete_sessions().await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    add_refresh_cookie(&app, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_400_if_session_id_is_invalid() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let _ = login_twice(&app).await;
+
+    let response = app.delete_session("not-a-session").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_404_for_unknown_or_other_users_sessions() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let _ = login_twice(&app).await;
+    let other_users_session = current_session(&app).await;
+
+    // Log in as someone else
+    let _ = login_twice(&app).await;
+
+    let response = app.delete_session(&uuid::Uuid::new_v4().to_string()).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app.delete_session(&other_users_session.id).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -1,14 +1,11 @@
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::TestApp;
 use auth_service::routes::SignupResponse;
 use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
-use auth_service::services::mock_email_client::MockEmailClient;
 use auth_service::ErrorResponse;
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
-
-    let random_email = get_random_email(); // Call helper method to generate email
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     // TODO: add more malformed input test cases
     let test_cases = [serde_json::json!({
@@ -29,11 +26,7 @@ async fn should_return_422_if_malformed_input() {
 
 #[tokio::test]
 async fn should_return_201_if_valid_input() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
-
-    let expected_response = SignupResponse {
-        message: "User created successfully!".to_owned(),
-    };
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let user = serde_json::json!({
         "email": "foo@example.com",
@@ -44,9 +37,35 @@ async fn should_return_201_if_valid_input() {
     let response = app.post_signup(&user);
 
     // Assert that we are getting the correct response body!
+    let body = response
+        .await
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to UserBody");
+    assert_eq!(body.message, "User created successfully!");
+    // 2FA users get their recovery codes up front
+    assert_eq!(body.recovery_codes.map(|codes| codes.len()), Some(10));
+}
+
+#[tokio::test]
+async fn should_not_return_recovery_codes_without_2fa() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let expected_response = SignupResponse {
+        message: "User created successfully!".to_owned(),
+        recovery_codes: None,
+    };
+
+    let user = serde_json::json!({
+        "email": "foo@example.com",
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&user).await;
+    assert_eq!(response.status().as_u16(), 201);
     assert_eq!(
         response
-            .await
             .json::<SignupResponse>()
             .await
             .expect("Could not deserialize response body to UserBody"),
@@ -56,7 +75,7 @@ async fn should_return_201_if_valid_input() {
 
 #[tokio::test]
 async fn should_return_400_if_invalid_input() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let input = [
         serde_json::json!({
@@ -93,7 +112,7 @@ async fn should_return_400_if_invalid_input() {
 
 #[tokio::test]
 async fn should_return_409_if_email_already_exists() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let user = serde_json::json!({
         "email": "foo@example.com",
@@ -0,0 +1,65 @@
+use crate::fake_smtp::FakeSmtpServer;
+use crate::helpers::smtp_settings;
+use auth_service::domain::{Email, EmailClient};
+use auth_service::services::smtp_email_client::SmtpEmailClient;
+
+fn recipient() -> Email {
+    Email::parse("foo@example.com".to_owned()).unwrap()
+}
+
+#[tokio::test]
+async fn should_deliver_email() {
+    let server = FakeSmtpServer::start().await;
+    let client = SmtpEmailClient::new(smtp_settings(&server)).unwrap();
+
+    let result = client
+        .send_email(&recipient(), "Hello", "First line\nSecond line")
+        .await;
+    assert!(result.is_ok());
+
+    let emails = server.received();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].recipients, vec!["foo@example.com".to_owned()]);
+    assert_eq!(emails[0].header("From"), Some("auth@example.com"));
+    assert_eq!(emails[0].header("To"), Some("foo@example.com"));
+    assert_eq!(emails[0].subject(), Some("Hello"));
+    assert_eq!(emails[0].body(), "First line\r\nSecond line");
+}
+
+#[tokio::test]
+async fn should_retry_after_a_temporary_failure() {
+    let server = FakeSmtpServer::start().await;
+    let client = SmtpEmailClient::new(smtp_settings(&server)).unwrap();
+    server.reject_next(451);
+
+    let result = client.send_email(&recipient(), "Hello", "content").await;
+    assert!(result.is_ok());
+    assert_eq!(server.received().len(), 1);
+}
+
+#[tokio::test]
+async fn should_give_up_after_max_retries() {
+    let server = FakeSmtpServer::start().await;
+    let client = SmtpEmailClient::new(smtp_settings(&server)).unwrap();
+    // one attempt plus the two retries from the test settings
+    for _ in 0..3 {
+        server.reject_next(451);
+    }
+
+    let result = client.send_email(&recipient(), "Hello", "content").await;
+    assert!(result.is_err());
+    assert!(server.received().is_empty());
+}
+
+#[tokio::test]
+async fn should_not_retry_a_permanent_failure() {
+    let server = FakeSmtpServer::start().await;
+    let client = SmtpEmailClient::new(smtp_settings(&server)).unwrap();
+    server.reject_next(554);
+
+    let result = client.send_email(&recipient(), "Hello", "content").await;
+    assert!(result.is_err());
+
+    // a retry would have gone through
+    assert!(server.received().is_empty());
+}
@@ -0,0 +1,137 @@
+use auth_service::domain::{
+    AssertionResponse, AttestationResponse, AuthenticationCredential, RegistrationCredential,
+    COSE_ALG_ES256,
+};
+use auth_service::routes::{PublicKeyCredentialCreationOptions, PublicKeyCredentialRequestOptions};
+use auth_service::utils::constants::WEBAUTHN_RELYING_PARTY;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ciborium::Value;
+use p256::ecdsa::{signature::Signer, Signature, SigningKey};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+const USER_PRESENT: u8 = 0x01;
+const USER_VERIFIED: u8 = 0x04;
+const ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+// Plays the part of the browser and a platform authenticator holding a
+// single P-256 credential, so the WebAuthn ceremonies can be tested without
+// hardware.
+pub struct SoftwareAuthenticator {
+    key: SigningKey,
+    credential_id: Vec<u8>,
+    sign_count: u32,
+    user_verification: bool,
+}
+
+impl SoftwareAuthenticator {
+    pub fn new() -> Self {
+        let mut credential_id = vec![0u8; 16];
+        OsRng.fill_bytes(&mut credential_id);
+
+        Self {
+            key: SigningKey::random(&mut OsRng),
+            credential_id,
+            sign_count: 0,
+            user_verification: true,
+        }
+    }
+
+    // A security key without a PIN: it only proves someone touched it
+    pub fn without_user_verification() -> Self {
+        Self {
+            user_verification: false,
+            ..Self::new()
+        }
+    }
+
+    pub fn credential_id(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.credential_id)
+    }
+
+    pub fn create(
+        &mut self,
+        options: &PublicKeyCredentialCreationOptions,
+    ) -> RegistrationCredential {
+        assert!(options
+            .pub_key_cred_params
+            .iter()
+            .any(|param| param.alg == COSE_ALG_ES256));
+
+        let point = self.key.verifying_key().to_encoded_point(false);
+        let cose_key = Value::Map(vec![
+            (1.into(), 2.into()),
+            (3.into(), COSE_ALG_ES256.into()),
+            ((-1).into(), 1.into()),
+            ((-2).into(), Value::Bytes(point.x().unwrap().to_vec())),
+            ((-3).into(), Value::Bytes(point.y().unwrap().to_vec())),
+        ]);
+
+        let mut auth_data = self.authenticator_data(&options.rp.id, ATTESTED_CREDENTIAL_DATA);
+        auth_data.extend_from_slice(&[0u8; 16]);
+        auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.credential_id);
+        ciborium::into_writer(&cose_key, &mut auth_data).unwrap();
+
+        let attestation_object = Value::Map(vec![
+            ("fmt".into(), "none".into()),
+            ("attStmt".into(), Value::Map(vec![])),
+            ("authData".into(), Value::Bytes(auth_data)),
+        ]);
+        let mut attestation_bytes = Vec::new();
+        ciborium::into_writer(&attestation_object, &mut attestation_bytes).unwrap();
+
+        RegistrationCredential {
+            id: self.credential_id(),
+            response: AttestationResponse {
+                client_data_json: client_data("webauthn.create", &options.challenge),
+                attestation_object: URL_SAFE_NO_PAD.encode(attestation_bytes),
+            },
+        }
+    }
+
+    pub fn get(&mut self, options: &PublicKeyCredentialRequestOptions) -> AuthenticationCredential {
+        self.sign_count += 1;
+
+        let client_data_json = client_data("webauthn.get", &options.challenge);
+        let auth_data = self.authenticator_data(&options.rp_id, 0);
+
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(
+            URL_SAFE_NO_PAD.decode(&client_data_json).unwrap(),
+        ));
+        let signature: Signature = self.key.sign(&signed);
+
+        AuthenticationCredential {
+            id: self.credential_id(),
+            response: AssertionResponse {
+                client_data_json,
+                authenticator_data: URL_SAFE_NO_PAD.encode(auth_data),
+                signature: URL_SAFE_NO_PAD.encode(signature.to_der().as_bytes()),
+            },
+        }
+    }
+
+    fn authenticator_data(&self, rp_id: &str, extra_flags: u8) -> Vec<u8> {
+        let mut flags = USER_PRESENT | extra_flags;
+        if self.user_verification {
+            flags |= USER_VERIFIED;
+        }
+
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&self.sign_count.to_be_bytes());
+        data
+    }
+}
+
+// The browser fills in the origin of the page making the request
+fn client_data(ceremony: &str, challenge: &str) -> String {
+    let json = serde_json::json!({
+        "type": ceremony,
+        "challenge": challenge,
+        "origin": WEBAUTHN_RELYING_PARTY.origin,
+        "crossOrigin": false,
+    });
+    URL_SAFE_NO_PAD.encode(json.to_string())
+}
@@ -0,0 +1,161 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::domain::TotpSecret;
+use auth_service::routes::{TotpEnrollmentResponse, TwoFactorAuthResponse};
+use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
+use chrono::Utc;
+
+fn now() -> u64 {
+    Utc::now().timestamp() as u64
+}
+
+// Signs up a user without 2FA and logs them in, leaving the jwt cookie in the
+// app's cookie jar.
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup_verified(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn enroll(app: &TestApp) -> TotpSecret {
+    let response = app.post_totp_enroll().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<TotpEnrollmentResponse>()
+        .await
+        .expect("Could not deserialize response body to TotpEnrollmentResponse");
+
+    TotpSecret::parse(body.secret).unwrap()
+}
+
+async fn login_with_totp(app: &TestApp, email: &str) -> String {
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id
+}
+
+#[tokio::test]
+async fn should_return_400_if_enrolling_without_jwt() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app.post_totp_enroll().await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_otpauth_uri_and_qr_code_on_enroll() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    signup_and_login(&app, &get_random_email()).await;
+
+    let response = app.post_totp_enroll().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response
+        .json::<TotpEnrollmentResponse>()
+        .await
+        .expect("Could not deserialize response body to TotpEnrollmentResponse");
+
+    assert!(body.otpauth_uri.starts_with("otpauth://totp/"));
+    assert!(body
+        .otpauth_uri
+        .contains(&format!("secret={}", body.secret)));
+    assert!(body.qr_code_svg.contains("<svg"));
+}
+
+#[tokio::test]
+async fn should_return_401_if_confirming_with_wrong_code() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    let secret = enroll(&app).await;
+
+    // a code from well outside the accepted window
+    let stale_code = secret.generate(now() - 300);
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": stale_code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Without a confirmed secret login still works without a second factor
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_require_totp_on_login_once_confirmed() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    let secret = enroll(&app).await;
+
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": secret.generate(now()) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let login_attempt_id = login_with_totp(&app, &email).await;
+    // no email for authenticator app users
+    assert!(app.smtp_server.received().is_empty());
+
+    // The code used to confirm can't be used again, so use the next one.
+    let request = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": secret.generate(now() + 30),
+    });
+    let response = app.post_verify_2fa(&request).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_totp_code_is_replayed() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    let secret = enroll(&app).await;
+
+    let response = app
+        .post_totp_confirm(&serde_json::json!({ "code": secret.generate(now()) }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let code = secret.generate(now() + 30);
+
+    let login_attempt_id = login_with_totp(&app, &email).await;
+    let request = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    let response = app.post_verify_2fa(&request).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let login_attempt_id = login_with_totp(&app, &email).await;
+    let request = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    let response = app.post_verify_2fa(&request).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
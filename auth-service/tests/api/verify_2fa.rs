@@ -1,10 +1,271 @@
-use crate::helpers::TestApp;
+use crate::helpers::{get_emailed_2fa_code, get_random_email, TestApp};
+use auth_service::domain::{Email, LoginAttemptId, TwoFACode};
+use auth_service::routes::TwoFactorAuthResponse;
 use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
-use auth_service::services::mock_email_client::MockEmailClient;
+use auth_service::utils::constants::{
+    JWT_COOKIE_NAME, REFRESH_TOKEN_COOKIE_NAME, TWO_FA_CODE_MAX_ATTEMPTS,
+};
+use auth_service::ErrorResponse;
+
+// Signs up a 2FA user, logs in, and swaps the generated code for a known one
+// so the tests don't depend on how codes are generated.
+async fn login_with_2fa(app: &TestApp, email: &str, code: &str) -> String {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup_verified(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let _ = app
+        .two_fa_code_store
+        .write()
+        .await
+        .add_code(
+            Email::parse(email.to_owned()).unwrap(),
+            LoginAttemptId::parse(login_attempt_id.clone()).unwrap(),
+            TwoFACode::parse(code.to_owned()).unwrap(),
+        )
+        .await;
+
+    login_attempt_id
+}
 
 #[tokio::test]
-async fn verify_2fa_returns_200() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
-    let response = app.verify_2fa().await;
+async fn should_return_422_if_malformed_input() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let test_cases = [
+        serde_json::json!({}),
+        serde_json::json!({
+            "email": get_random_email(),
+            "loginAttemptId": LoginAttemptId::default().to_string(),
+        }),
+        serde_json::json!({
+            "loginAttemptId": LoginAttemptId::default().to_string(),
+            "2FACode": "123456",
+        }),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app.post_verify_2fa(test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            422,
+            "Failed for input: {:?}",
+            test_case
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_input() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let test_cases = [
+        serde_json::json!({
+            "email": "fooexample.com",
+            "loginAttemptId": LoginAttemptId::default().to_string(),
+            "2FACode": "123456",
+        }),
+        serde_json::json!({
+            "email": get_random_email(),
+            "loginAttemptId": "not-a-uuid",
+            "2FACode": "123456",
+        }),
+        serde_json::json!({
+            "email": get_random_email(),
+            "loginAttemptId": LoginAttemptId::default().to_string(),
+            "2FACode": "12345",
+        }),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app.post_verify_2fa(test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            test_case
+        );
+
+        assert_eq!(
+            response
+                .json::<ErrorResponse>()
+                .await
+                .expect("Could not deserialize response body to ErrorResponse")
+                .error,
+            "Invalid credentials".to_owned()
+        );
+    }
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_credentials() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let random_email = get_random_email();
+    let login_attempt_id = login_with_2fa(&app, &random_email, "123456").await;
+
+    let test_cases = [
+        // wrong code
+        serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": "654321",
+        }),
+        // wrong login attempt id
+        serde_json::json!({
+            "email": random_email,
+            "loginAttemptId": LoginAttemptId::default().to_string(),
+            "2FACode": "123456",
+        }),
+        // no login attempt for this email
+        serde_json::json!({
+            "email": get_random_email(),
+            "loginAttemptId": login_attempt_id,
+            "2FACode": "123456",
+        }),
+    ];
+
+    for test_case in test_cases.iter() {
+        let response = app.post_verify_2fa(test_case).await;
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "Failed for input: {:?}",
+            test_case
+        );
+        assert!(response
+            .cookies()
+            .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+            .is_none());
+    }
+}
+
+#[tokio::test]
+async fn should_return_200_if_correct_code() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let random_email = get_random_email();
+    let login_attempt_id = login_with_2fa(&app, &random_email, "123456").await;
+
+    let request = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": "123456",
+    });
+
+    let response = app.post_verify_2fa(&request).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    assert!(!auth_cookie.value().is_empty());
+
+    assert!(response
+        .cookies()
+        .any(|cookie| cookie.name() == REFRESH_TOKEN_COOKIE_NAME));
+}
+
+#[tokio::test]
+async fn should_return_401_if_same_code_twice() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let random_email = get_random_email();
+    let login_attempt_id = login_with_2fa(&app, &random_email, "123456").await;
+
+    let request = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": "123456",
+    });
+
+    let response = app.post_verify_2fa(&request).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_verify_2fa(&request).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_200_with_the_emailed_code() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let random_email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    let response = app.post_signup_verified(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let code = get_emailed_2fa_code(&app.smtp_server.received()[0]);
+
+    let request = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    let response = app.post_verify_2fa(&request).await;
     assert_eq!(response.status().as_u16(), 200);
 }
+
+#[tokio::test]
+async fn should_return_401_after_too_many_wrong_codes() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let random_email = get_random_email();
+    let login_attempt_id = login_with_2fa(&app, &random_email, "123456").await;
+
+    let wrong_code = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": "654321",
+    });
+    for _ in 0..TWO_FA_CODE_MAX_ATTEMPTS {
+        let response = app.post_verify_2fa(&wrong_code).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // The code has been thrown away, so guessing it now doesn't help
+    let right_code = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": "123456",
+    });
+    let response = app.post_verify_2fa(&right_code).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
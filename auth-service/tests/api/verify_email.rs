@@ -0,0 +1,196 @@
+use chrono::Utc;
+
+use crate::helpers::{get_emailed_token, get_random_email, TestApp};
+use auth_service::{
+    domain::{Email, EmailTokenId, EmailTokenPurpose},
+    services::hashset_banned_token_store::HashsetBannedTokenStore,
+    utils::constants::JWT_COOKIE_NAME,
+    ErrorResponse,
+};
+
+fn user(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    })
+}
+
+#[tokio::test]
+async fn should_refuse_login_until_email_is_verified() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+
+    let response = app.post_signup(&user(&email)).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let emails = app.smtp_server.received();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].recipients, vec![email.clone()]);
+    assert_eq!(
+        emails[0].subject(),
+        Some("Confirm your Auth Service email address")
+    );
+
+    let response = app.post_login(&user(&email)).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response.json::<ErrorResponse>().await.unwrap().error,
+        "Email not verified"
+    );
+
+    let response = app.get_verify_email(&get_emailed_token(&emails[0])).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&user(&email)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_not_say_an_account_is_unverified_without_the_password() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    app.post_signup(&user(&email)).await;
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "wrongpassword",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_only_accept_a_link_once() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    app.post_signup(&user(&get_random_email())).await;
+    let token = get_emailed_token(&app.smtp_server.received()[0]);
+
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_for_a_bad_token() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    app.post_signup(&user(&get_random_email())).await;
+    let token = get_emailed_token(&app.smtp_server.received()[0]);
+
+    let tampered = format!("{}x", token);
+    let response = app.get_verify_email(&tampered).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.get_verify_email("not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_not_accept_an_auth_token_as_a_link() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    app.post_signup_verified(&user(&email)).await;
+    let response = app.post_login(&user(&email)).await;
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    let response = app.get_verify_email(auth_cookie.value()).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_enforce_the_resend_cooldown() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    app.post_signup(&user(&email)).await;
+    let first_token = get_emailed_token(&app.smtp_server.take_received()[0]);
+
+    let body = serde_json::json!({ "email": email });
+    let response = app.post_verify_email_resend(&body).await;
+    // Same answer as for an address that hasn't signed up
+    assert_eq!(response.status().as_u16(), 202);
+    assert!(app.smtp_server.wait_for_email().await.is_none());
+
+    // Pretend the last link went out two minutes ago
+    app.email_token_store
+        .write()
+        .await
+        .add_token(
+            Email::parse(email.clone()).unwrap(),
+            EmailTokenPurpose::VerifyEmail,
+            EmailTokenId::default(),
+            Utc::now().timestamp() - 120,
+        )
+        .await
+        .unwrap();
+
+    let response = app.post_verify_email_resend(&body).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let email = app
+        .smtp_server
+        .wait_for_email()
+        .await
+        .expect("No verification email");
+    let second_token = get_emailed_token(&email);
+    assert_ne!(first_token, second_token);
+
+    // Only the newest link works
+    let response = app.get_verify_email(&first_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.get_verify_email(&second_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_not_email_unknown_or_verified_addresses() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    app.post_signup_verified(&user(&email)).await;
+
+    for address in [email, get_random_email()] {
+        let response = app
+            .post_verify_email_resend(&serde_json::json!({ "email": address }))
+            .await;
+        assert_eq!(response.status().as_u16(), 202);
+    }
+    assert!(app.smtp_server.wait_for_email().await.is_none());
+
+    let response = app
+        .post_verify_email_resend(&serde_json::json!({ "email": "not-an-email" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+}
+
+#[tokio::test]
+async fn should_return_202_if_the_email_cannot_be_sent() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    app.post_signup(&user(&email)).await;
+    app.smtp_server.take_received();
+
+    // Pretend the last link went out two minutes ago, so the cooldown is over
+    app.email_token_store
+        .write()
+        .await
+        .add_token(
+            Email::parse(email.clone()).unwrap(),
+            EmailTokenPurpose::VerifyEmail,
+            EmailTokenId::default(),
+            Utc::now().timestamp() - 120,
+        )
+        .await
+        .unwrap();
+    app.smtp_server.reject_next(554);
+
+    let response = app
+        .post_verify_email_resend(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+}
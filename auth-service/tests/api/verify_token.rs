@@ -1,12 +1,11 @@
 use crate::helpers::{get_random_email, TestApp};
 
 use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
-use auth_service::services::mock_email_client::MockEmailClient;
 use auth_service::utils::constants::JWT_COOKIE_NAME;
 
 #[tokio::test]
 async fn should_return_200_valid_token() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let random_email = get_random_email();
 
@@ -16,7 +15,7 @@ async fn should_return_200_valid_token() {
         "requires2FA": false
     });
 
-    let response = app.post_signup(&signup_body).await;
+    let response = app.post_signup_verified(&signup_body).await;
 
     assert_eq!(response.status().as_u16(), 201);
 
@@ -45,7 +44,7 @@ async fn should_return_200_valid_token() {
 
 #[tokio::test]
 async fn should_return_401_if_invalid_token() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let valid_request = serde_json::json!({
       "token": "bogus".to_string()
@@ -58,7 +57,7 @@ async fn should_return_401_if_invalid_token() {
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
-    let app = TestApp::new(HashsetBannedTokenStore::default(), MockEmailClient {}).await;
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
 
     let invalid_request = serde_json::json!({});
 
@@ -66,3 +65,43 @@ async fn should_return_422_if_malformed_input() {
 
     assert_eq!(response.status().as_u16(), 422);
 }
+
+#[tokio::test]
+async fn should_return_401_if_banned_token() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup_verified(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let request = serde_json::json!({
+      "token": auth_cookie.value()
+    });
+
+    let response = app.post_verify_token(&request).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,252 @@
+use crate::helpers::{get_emailed_2fa_code, get_random_email, TestApp};
+use crate::software_authenticator::SoftwareAuthenticator;
+use auth_service::domain::AuthenticationCredential;
+use auth_service::routes::{
+    PublicKeyCredentialCreationOptions, PublicKeyCredentialRequestOptions, TwoFactorAuthResponse,
+    WebAuthnOptionsResponse,
+};
+use auth_service::services::hashset_banned_token_store::HashsetBannedTokenStore;
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+
+// Signs up a user without 2FA and logs them in with their password
+async fn signup_and_login(app: &TestApp, email: &str) {
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup_verified(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+async fn register(app: &TestApp, authenticator: &mut SoftwareAuthenticator) {
+    let response = app.post_webauthn_register_start().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let options = response
+        .json::<WebAuthnOptionsResponse<PublicKeyCredentialCreationOptions>>()
+        .await
+        .expect("Could not deserialize creation options")
+        .public_key;
+
+    let response = app
+        .post_webauthn_register_finish(&authenticator.create(&options))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn start_login(app: &TestApp, email: &str) -> PublicKeyCredentialRequestOptions {
+    let response = app
+        .post_webauthn_login_start(&serde_json::json!({ "email": email }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<WebAuthnOptionsResponse<PublicKeyCredentialRequestOptions>>()
+        .await
+        .expect("Could not deserialize request options")
+        .public_key
+}
+
+async fn finish_login(
+    app: &TestApp,
+    email: &str,
+    login_attempt_id: Option<&str>,
+    credential: &AuthenticationCredential,
+) -> reqwest::Response {
+    app.post_webauthn_login_finish(&serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "credential": credential,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn should_return_400_if_registering_without_jwt() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+
+    let response = app.post_webauthn_register_start().await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_log_in_with_a_passkey_alone() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &mut authenticator).await;
+
+    let options = start_login(&app, &email).await;
+    assert_eq!(options.allow_credentials.len(), 1);
+    assert_eq!(
+        options.allow_credentials[0].id,
+        authenticator.credential_id()
+    );
+
+    let response = finish_login(&app, &email, None, &authenticator.get(&options)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .cookies()
+        .any(|cookie| cookie.name() == JWT_COOKIE_NAME && !cookie.value().is_empty()));
+}
+
+#[tokio::test]
+async fn should_require_user_verification_without_a_password() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    let mut authenticator = SoftwareAuthenticator::without_user_verification();
+    register(&app, &mut authenticator).await;
+
+    let options = start_login(&app, &email).await;
+    let response = finish_login(&app, &email, None, &authenticator.get(&options)).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_accept_a_passkey_in_place_of_a_2fa_code() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true
+    });
+    app.post_signup_verified(&body).await;
+
+    // Log in with the emailed code once to register the passkey
+    let response = app.post_login(&body).await;
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let code = get_emailed_2fa_code(&app.smtp_server.received()[0]);
+    let response = app
+        .post_verify_2fa(&serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let mut authenticator = SoftwareAuthenticator::without_user_verification();
+    register(&app, &mut authenticator).await;
+
+    let response = app.post_login(&body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let options = start_login(&app, &email).await;
+    let response = finish_login(
+        &app,
+        &email,
+        Some(&login_attempt_id),
+        &authenticator.get(&options),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_assertion_is_replayed() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &mut authenticator).await;
+
+    let options = start_login(&app, &email).await;
+    let credential = authenticator.get(&options);
+    let response = finish_login(&app, &email, None, &credential).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = finish_login(&app, &email, None, &credential).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_for_an_unregistered_authenticator() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    register(&app, &mut SoftwareAuthenticator::new()).await;
+
+    let options = start_login(&app, &email).await;
+    let credential = SoftwareAuthenticator::new().get(&options);
+    let response = finish_login(&app, &email, None, &credential).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_not_tell_who_has_passkeys() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+
+    for email in [email, get_random_email()] {
+        let options = start_login(&app, &email).await;
+        assert_eq!(options.allow_credentials.len(), 1);
+        let again = start_login(&app, &email).await;
+        assert_eq!(
+            again.allow_credentials[0].id,
+            options.allow_credentials[0].id
+        );
+
+        let credential = SoftwareAuthenticator::new().get(&options);
+        let response = finish_login(&app, &email, None, &credential).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn should_keep_earlier_challenges_when_a_login_is_started_again() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    let email = get_random_email();
+    signup_and_login(&app, &email).await;
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &mut authenticator).await;
+
+    let options = start_login(&app, &email).await;
+    // e.g. someone else starting a login for the same email
+    start_login(&app, &email).await;
+
+    let response = finish_login(&app, &email, None, &authenticator.get(&options)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_not_register_the_same_authenticator_twice() {
+    let app = TestApp::new(HashsetBannedTokenStore::default()).await;
+    signup_and_login(&app, &get_random_email()).await;
+    let mut authenticator = SoftwareAuthenticator::new();
+    register(&app, &mut authenticator).await;
+
+    let options = app
+        .post_webauthn_register_start()
+        .await
+        .json::<WebAuthnOptionsResponse<PublicKeyCredentialCreationOptions>>()
+        .await
+        .expect("Could not deserialize creation options")
+        .public_key;
+    // Browsers refuse to create a credential on an excluded authenticator
+    assert_eq!(
+        options.exclude_credentials[0].id,
+        authenticator.credential_id()
+    );
+
+    let response = app
+        .post_webauthn_register_finish(&authenticator.create(&options))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}